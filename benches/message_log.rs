@@ -0,0 +1,111 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Benchmarks of looking up messages and blocks in a `PbftLog` that is as full as the default
+//! `max_log_size` allows
+//!
+//! Run with `cargo bench --features testkit --bench message_log`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use sawtooth_pbft_engine::message_log::PbftLog;
+use sawtooth_pbft_engine::message_type::PbftMessageType;
+use sawtooth_pbft_engine::state::PbftState;
+use sawtooth_pbft_engine::testkit::{mock_block, mock_config, mock_msg};
+
+const MAX_LOG_SIZE: u64 = 10_000;
+const MEMBERS: u8 = 16;
+
+/// A log of `MAX_LOG_SIZE` messages: a PrePrepare, and a Prepare and Commit from every member, for
+/// as many consecutive blocks as fit, along with the blocks themselves; returns the log and the
+/// number of blocks
+fn full_log() -> (PbftLog, u64) {
+    let mut config = mock_config(MEMBERS);
+    config.max_log_size = MAX_LOG_SIZE;
+    let mut log = PbftLog::new(&config);
+
+    let messages_per_block = 1 + 2 * u64::from(MEMBERS);
+    let blocks = MAX_LOG_SIZE / messages_per_block;
+    // The node has caught up with all of the blocks, so the log accepts messages for all of them
+    let state = PbftState::new(vec![0], blocks, &config);
+    for seq_num in 1..=blocks {
+        let mut block = mock_block(seq_num as u8);
+        block.block_id = seq_num.to_be_bytes().to_vec();
+        block.block_num = seq_num;
+        log.add_validated_block(block.clone());
+
+        log.add_message(
+            mock_msg(
+                PbftMessageType::PrePrepare,
+                0,
+                seq_num,
+                vec![0],
+                block.block_id.clone(),
+                false,
+            ),
+            &state,
+        );
+        for member in 0..MEMBERS {
+            for msg_type in &[PbftMessageType::Prepare, PbftMessageType::Commit] {
+                log.add_message(
+                    mock_msg(
+                        *msg_type,
+                        0,
+                        seq_num,
+                        vec![member],
+                        block.block_id.clone(),
+                        false,
+                    ),
+                    &state,
+                );
+            }
+        }
+    }
+
+    (log, blocks)
+}
+
+fn log_lookups(c: &mut Criterion) {
+    let (log, blocks) = full_log();
+    let seq_num = blocks / 2;
+    let block_id = seq_num.to_be_bytes().to_vec();
+
+    c.bench_function("get_messages_of_type_seq", |b| {
+        b.iter(|| log.get_messages_of_type_seq(PbftMessageType::Commit, seq_num))
+    });
+    c.bench_function("get_messages_of_type_seq_view_block", |b| {
+        b.iter(|| {
+            log.get_messages_of_type_seq_view_block(PbftMessageType::Commit, seq_num, 0, &block_id)
+        })
+    });
+    c.bench_function("has_pre_prepare", |b| {
+        b.iter(|| log.has_pre_prepare(seq_num, 0, &block_id))
+    });
+    c.bench_function("get_block_with_id", |b| {
+        b.iter(|| log.get_block_with_id(&block_id))
+    });
+    c.bench_function("get_blocks_with_num", |b| {
+        b.iter(|| log.get_blocks_with_num(seq_num))
+    });
+}
+
+fn log_insertion(c: &mut Criterion) {
+    c.bench_function("fill_log", |b| b.iter(full_log));
+}
+
+criterion_group!(benches, log_lookups, log_insertion);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Benchmarks of consensus seal verification, comparing checking the signatures of a seal's votes
+//! one at a time with checking them in parallel
+//!
+//! Run with `cargo bench --features testkit --bench verification`.
+
+use criterion::{criterion_group, criterion_main, Criterion, ParameterizedBenchmark};
+
+use sawtooth_pbft_engine::host::{PeerId, SawtoothHost};
+use sawtooth_pbft_engine::message_type::PbftMessageType;
+use sawtooth_pbft_engine::protos::pbft_message::{PbftSeal, PbftSignedVote};
+use sawtooth_pbft_engine::signature::Secp256k1Verifier;
+use sawtooth_pbft_engine::testkit::{mock_seal, mock_signer_network, mock_vote};
+use sawtooth_pbft_engine::verification::{max_faulty_nodes, verify_seal, verify_seal_with};
+
+/// A network of the given size and a seal with a vote from every member other than its signer
+fn sealed_network(size: u8) -> (Vec<PeerId>, PbftSeal) {
+    let key_pairs = mock_signer_network(size);
+    let votes = key_pairs[1..]
+        .iter()
+        .map(|key_pair| mock_vote(PbftMessageType::Commit, 0, 1, vec![1], key_pair))
+        .collect();
+    let seal = mock_seal(0, 1, vec![1], &key_pairs[0], votes);
+    let members = key_pairs
+        .into_iter()
+        .map(|key_pair| key_pair.pub_key)
+        .collect();
+    (members, seal)
+}
+
+fn seal_verification(c: &mut Criterion) {
+    let networks = vec![16u8, 64];
+
+    c.bench(
+        "seal_verification",
+        ParameterizedBenchmark::new(
+            "serial",
+            |b, size| {
+                let (members, seal) = sealed_network(*size);
+                let f = max_faulty_nodes(members.len());
+                b.iter(|| {
+                    verify_seal_with(&seal, &members, f, &|votes: &[PbftSignedVote]| {
+                        votes
+                            .iter()
+                            .map(|vote| {
+                                SawtoothHost::authenticate_vote_with(vote, &Secp256k1Verifier)
+                            })
+                            .collect()
+                    })
+                    .expect("Seal is invalid")
+                })
+            },
+            networks,
+        )
+        .with_function("parallel", |b, size| {
+            let (members, seal) = sealed_network(*size);
+            let f = max_faulty_nodes(members.len());
+            b.iter(|| verify_seal(&seal, &members, f, &Secp256k1Verifier).expect("Seal is invalid"))
+        }),
+    );
+}
+
+criterion_group!(benches, seal_verification);
+criterion_main!(benches);
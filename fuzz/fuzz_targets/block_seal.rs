@@ -0,0 +1,41 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Verify a seal on its own, then as the payload of block 2, which the node verifies and (if the
+//! seal is valid) uses to catch up by committing block 1
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sawtooth_pbft_engine::protos::pbft_message::PbftSeal;
+use sawtooth_pbft_engine::signature::Secp256k1Verifier;
+use sawtooth_pbft_engine::verification::verify_seal;
+use sawtooth_pbft_engine_fuzz::{block, Network, TestNode, NODE};
+
+fuzz_target!(|data: &[u8]| {
+    let network = Network::new();
+
+    if let Ok(seal) = protobuf::parse_from_bytes::<PbftSeal>(data) {
+        let _ = verify_seal(&seal, &network.members(), 1, &Secp256k1Verifier);
+    }
+
+    let mut node = TestNode::new(&network, NODE);
+    node.receive_block(block(1));
+    let mut block2 = block(2);
+    block2.payload = data.to_vec();
+    node.receive_block(block2);
+});
@@ -0,0 +1,29 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Handle a NewView message whose content is the input, which verifies the ViewChange votes it
+//! carries and (if they're valid) moves the node to the new view
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sawtooth_pbft_engine_fuzz::{Network, TestNode, NODE};
+
+fuzz_target!(|data: &[u8]| {
+    let mut node = TestNode::new(&Network::new(), NODE);
+    node.receive("NewView", data.to_vec());
+});
@@ -0,0 +1,32 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Parse and handle a peer message of any type. The first byte of the input selects the message
+//! type; the rest is the message's content.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sawtooth_pbft_engine_fuzz::{Network, TestNode, MESSAGE_TYPES, NODE};
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((message_type, content)) = data.split_first() {
+        let message_type = MESSAGE_TYPES[*message_type as usize % MESSAGE_TYPES.len()];
+        let mut node = TestNode::new(&Network::new(), NODE);
+        node.receive(message_type, content.to_vec());
+    }
+});
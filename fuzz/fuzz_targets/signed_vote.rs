@@ -0,0 +1,36 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Verify a serialized `PbftSignedVote`, including its header bytes and signature, as each type
+//! of vote that appears in seals and NewView messages
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sawtooth_pbft_engine::message_type::{ParsedMessage, PbftMessageType};
+use sawtooth_pbft_engine::protos::pbft_message::PbftSignedVote;
+use sawtooth_pbft_engine::signature::Secp256k1Verifier;
+use sawtooth_pbft_engine::verification::verify_vote;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(vote) = protobuf::parse_from_bytes::<PbftSignedVote>(data) {
+        for expected_type in &[PbftMessageType::Commit, PbftMessageType::ViewChange] {
+            let _ = verify_vote(&vote, *expected_type, &Secp256k1Verifier, |_| Ok(()));
+        }
+        let _ = ParsedMessage::from_signed_vote(&vote);
+    }
+});
@@ -0,0 +1,136 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Write seed corpora for the fuzz targets, made of validly signed messages from the fuzzed
+//! network (along with a few that are valid apart from a single problem), to `corpus/<target>/`
+//! or to the directory given as the first argument
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use protobuf::Message;
+use sawtooth_pbft_engine::message_type::PbftMessageType;
+use sawtooth_pbft_engine_fuzz::{block, Network, MESSAGE_TYPES};
+
+fn main() {
+    let corpus = env::args().nth(1).unwrap_or_else(|| "corpus".into());
+    let network = Network::new();
+    let block1 = block(1).block_id;
+
+    // Seal for block 1 from the primary of view 0, as published in block 2
+    let seal = network.seal(0, 0, 1, block1.clone(), &[1, 3]);
+    // NewView for view 1 from its primary
+    let new_view = network.new_view(1, 1, 0, &[0, 3]);
+
+    let peer_messages = vec![
+        (
+            "pre_prepare",
+            "PrePrepare",
+            network
+                .message(0, PbftMessageType::PrePrepare, 0, 1, block1.clone())
+                .write_to_bytes(),
+        ),
+        (
+            "prepare",
+            "Prepare",
+            network
+                .message(3, PbftMessageType::Prepare, 0, 1, block1.clone())
+                .write_to_bytes(),
+        ),
+        (
+            "commit",
+            "Commit",
+            network
+                .message(3, PbftMessageType::Commit, 0, 1, block1.clone())
+                .write_to_bytes(),
+        ),
+        (
+            "view_change",
+            "ViewChange",
+            network
+                .message(3, PbftMessageType::ViewChange, 1, 0, vec![])
+                .write_to_bytes(),
+        ),
+        ("new_view", "NewView", new_view.write_to_bytes()),
+        (
+            "seal_request",
+            "SealRequest",
+            network
+                .message(3, PbftMessageType::SealRequest, 0, 0, vec![])
+                .write_to_bytes(),
+        ),
+        ("seal", "Seal", seal.write_to_bytes()),
+    ];
+    for (name, message_type, content) in peer_messages {
+        let type_index = MESSAGE_TYPES
+            .iter()
+            .position(|known_type| *known_type == message_type)
+            .expect("Unknown message type") as u8;
+        let mut input = vec![type_index];
+        input.extend(content.expect("Failed to serialize message"));
+        write(&corpus, "peer_message", name, &input);
+    }
+
+    let votes = vec![
+        (
+            "commit",
+            network.vote(3, PbftMessageType::Commit, 0, 1, block1.clone()),
+        ),
+        (
+            "view_change",
+            network.vote(3, PbftMessageType::ViewChange, 1, 0, vec![]),
+        ),
+    ];
+    for (name, vote) in votes {
+        write(&corpus, "signed_vote", name, &serialize(&vote));
+    }
+
+    let new_views = vec![
+        ("valid", new_view.clone()),
+        ("too_few_votes", network.new_view(1, 1, 0, &[0])),
+        ("duplicate_votes", network.new_view(1, 1, 0, &[0, 0])),
+        ("not_from_primary", network.new_view(3, 1, 0, &[0, 2])),
+        ("vote_from_primary", network.new_view(1, 1, 0, &[1, 3])),
+    ];
+    for (name, new_view) in new_views {
+        write(&corpus, "new_view", name, &serialize(&new_view));
+    }
+
+    let seals = vec![
+        ("valid", seal),
+        ("no_votes", network.seal(0, 0, 1, block1.clone(), &[])),
+        ("too_few_votes", network.seal(0, 0, 1, block1.clone(), &[3])),
+        ("vote_from_signer", network.seal(0, 0, 1, block1, &[0, 3])),
+        ("wrong_block", network.seal(0, 0, 1, vec![2], &[1, 3])),
+    ];
+    for (name, seal) in seals {
+        write(&corpus, "block_seal", name, &serialize(&seal));
+    }
+}
+
+fn serialize<M: Message>(message: &M) -> Vec<u8> {
+    message
+        .write_to_bytes()
+        .expect("Failed to serialize message")
+}
+
+fn write(corpus: &str, target: &str, name: &str, input: &[u8]) {
+    let dir = Path::new(corpus).join(target);
+    fs::create_dir_all(&dir).expect("Failed to create corpus directory");
+    fs::write(dir.join(name), input).expect("Failed to write seed");
+}
@@ -0,0 +1,279 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Shared setup for the fuzz targets
+//!
+//! The targets feed attacker-controlled bytes to a node of a 4-node network whose members have
+//! fixed keys, so the seed corpora (generated from validly signed messages by the
+//! `generate_corpus` binary) stay valid from one run to the next.
+
+use protobuf::{Message, RepeatedField};
+use sawtooth_pbft_engine::config::PbftConfig;
+use sawtooth_pbft_engine::hash::hash_sha512;
+use sawtooth_pbft_engine::message_type::{ParsedMessage, PbftMessageType};
+use sawtooth_pbft_engine::node::PbftNode;
+use sawtooth_pbft_engine::protos::pbft_message::{
+    PbftMessage, PbftMessageInfo, PbftNewView, PbftSeal, PbftSignedVote,
+};
+use sawtooth_pbft_engine::state::PbftState;
+use sawtooth_pbft_engine::testkit::{handle_update, MockService};
+use sawtooth_sdk::consensus::engine::{
+    Block, BlockId, PeerId, PeerMessage, PeerMessageHeader, Update,
+};
+use sawtooth_sdk::messages::consensus::ConsensusPeerMessageHeader;
+use sawtooth_sdk::signing::secp256k1::Secp256k1PrivateKey;
+use sawtooth_sdk::signing::{create_context, Context};
+
+/// Number of members in the fuzzed network
+pub const NETWORK_SIZE: usize = 4;
+
+/// The member whose node is fed the fuzzed input; the primary of view 0 is member 0 and the
+/// primary of view 1 is member 1, so messages from both primaries reach it from a peer
+pub const NODE: usize = 2;
+
+/// The peer message types the `peer_message` target chooses from (using its first byte)
+pub const MESSAGE_TYPES: [&str; 7] = [
+    "PrePrepare",
+    "Prepare",
+    "Commit",
+    "ViewChange",
+    "NewView",
+    "SealRequest",
+    "Seal",
+];
+
+/// Members of the fuzzed network and their signing keys
+pub struct Network {
+    context: Box<dyn Context>,
+    /// Public and private key of each member
+    keys: Vec<(PeerId, Secp256k1PrivateKey)>,
+}
+
+impl Network {
+    pub fn new() -> Self {
+        let context = create_context("secp256k1").expect("Failed to create context");
+        let keys = (0..NETWORK_SIZE)
+            .map(|member| {
+                let private_key =
+                    Secp256k1PrivateKey::from_hex(&hex::encode(vec![member as u8 + 1; 32]))
+                        .expect("Failed to create private key");
+                let public_key = context
+                    .get_public_key(&private_key)
+                    .expect("Failed to get public key");
+                (public_key.as_slice().to_vec(), private_key)
+            })
+            .collect();
+
+        Network { context, keys }
+    }
+
+    pub fn id(&self, member: usize) -> PeerId {
+        self.keys[member].0.clone()
+    }
+
+    pub fn members(&self) -> Vec<PeerId> {
+        (0..NETWORK_SIZE).map(|member| self.id(member)).collect()
+    }
+
+    pub fn info(
+        &self,
+        member: usize,
+        msg_type: PbftMessageType,
+        view: u64,
+        seq_num: u64,
+    ) -> PbftMessageInfo {
+        PbftMessageInfo::new_from(msg_type, view, seq_num, self.id(member))
+    }
+
+    pub fn message(
+        &self,
+        member: usize,
+        msg_type: PbftMessageType,
+        view: u64,
+        seq_num: u64,
+        block_id: BlockId,
+    ) -> PbftMessage {
+        let mut message = PbftMessage::new();
+        message.set_info(self.info(member, msg_type, view, seq_num));
+        message.set_block_id(block_id);
+        message
+    }
+
+    /// Create a vote, signed by the member the way the validator signs its messages
+    pub fn vote(
+        &self,
+        member: usize,
+        msg_type: PbftMessageType,
+        view: u64,
+        seq_num: u64,
+        block_id: BlockId,
+    ) -> PbftSignedVote {
+        let message_bytes = self
+            .message(member, msg_type, view, seq_num, block_id)
+            .write_to_bytes()
+            .expect("Failed to serialize message");
+
+        let mut header = ConsensusPeerMessageHeader::new();
+        header.set_signer_id(self.id(member));
+        header.set_content_sha512(hash_sha512(&message_bytes));
+        header.set_message_type(msg_type.into());
+        let header_bytes = header.write_to_bytes().expect("Failed to serialize header");
+        let header_signature = hex::decode(
+            self.context
+                .sign(&header_bytes, &self.keys[member].1)
+                .expect("Failed to sign header"),
+        )
+        .expect("Failed to decode signature");
+
+        let mut vote = PbftSignedVote::new();
+        vote.set_header_bytes(header_bytes);
+        vote.set_header_signature(header_signature);
+        vote.set_message_bytes(message_bytes);
+        vote
+    }
+
+    /// Create a seal for the block, signed by one member with Commit votes from the voters
+    pub fn seal(
+        &self,
+        member: usize,
+        view: u64,
+        seq_num: u64,
+        block_id: BlockId,
+        voters: &[usize],
+    ) -> PbftSeal {
+        let mut seal = PbftSeal::new();
+        seal.set_info(self.info(member, PbftMessageType::Seal, view, seq_num));
+        seal.set_block_id(block_id.clone());
+        seal.set_commit_votes(RepeatedField::from(
+            voters
+                .iter()
+                .map(|voter| {
+                    self.vote(
+                        *voter,
+                        PbftMessageType::Commit,
+                        view,
+                        seq_num,
+                        block_id.clone(),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        ));
+        seal
+    }
+
+    /// Create a NewView for the view, announced by one member with ViewChange votes from the
+    /// voters
+    pub fn new_view(
+        &self,
+        member: usize,
+        view: u64,
+        seq_num: u64,
+        voters: &[usize],
+    ) -> PbftNewView {
+        let mut new_view = PbftNewView::new();
+        new_view.set_info(self.info(member, PbftMessageType::NewView, view, seq_num));
+        new_view.set_view_changes(RepeatedField::from(
+            voters
+                .iter()
+                .map(|voter| self.vote(*voter, PbftMessageType::ViewChange, view, seq_num, vec![]))
+                .collect::<Vec<_>>(),
+        ));
+        new_view
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A block of the fuzzed chain, which starts with block 0
+pub fn block(num: u64) -> Block {
+    Block {
+        block_id: vec![num as u8],
+        previous_id: if num == 0 {
+            vec![]
+        } else {
+            vec![num as u8 - 1]
+        },
+        signer_id: vec![],
+        block_num: num,
+        payload: vec![],
+        summary: vec![],
+    }
+}
+
+/// A node of the fuzzed network, on top of block 0
+pub struct TestNode {
+    pub node: PbftNode,
+    pub state: PbftState,
+}
+
+impl TestNode {
+    pub fn new(network: &Network, member: usize) -> Self {
+        let mut config = PbftConfig::default();
+        config.members = network.members();
+
+        let mut state = PbftState::new(network.id(member), 0, &config);
+        let node = PbftNode::new(
+            &config,
+            block(0),
+            vec![],
+            Box::new(MockService::new(&config)),
+            &mut state,
+        );
+
+        TestNode { node, state }
+    }
+
+    /// Handle an update the way the engine does; errors are expected, but panics are bugs
+    pub fn handle(&mut self, update: Update) {
+        let _ = handle_update(&mut self.node, Ok(update), &mut self.state);
+    }
+
+    /// Receive a peer message with the given content from the member the content claims to be
+    /// from (since that's the only sender the node doesn't reject outright)
+    pub fn receive(&mut self, message_type: &str, content: Vec<u8>) {
+        let mut message = PeerMessage {
+            header: PeerMessageHeader {
+                signer_id: vec![],
+                content_sha512: hash_sha512(&content),
+                message_type: message_type.into(),
+                name: "pbft".into(),
+                version: "1.0".into(),
+            },
+            header_bytes: vec![],
+            header_signature: vec![],
+            content,
+        };
+
+        if let Ok(parsed) = ParsedMessage::from_peer_message(message.clone(), &[]) {
+            message.header.signer_id = parsed.info().get_signer_id().to_vec();
+        }
+        let sender = message.header.signer_id.clone();
+
+        self.handle(Update::PeerMessage(message, sender));
+    }
+
+    /// Receive a block and have the validator report that it's valid
+    pub fn receive_block(&mut self, block: Block) {
+        let block_id = block.block_id.clone();
+        self.handle(Update::BlockNew(block));
+        self.handle(Update::BlockValid(block_id));
+    }
+}
@@ -0,0 +1,394 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Local administration socket for querying the running engine
+//!
+//! A client connects to the socket, sends a single request as a line of text, and receives a
+//! single line of JSON in response before the connection is closed. Successful responses have the
+//! form `{"result": ...}`; failed requests get `{"error": "..."}`.
+//!
+//! Supported requests:
+//!
+//! + `finality-proof <block ID as hex>`: the finality proof for a committed block
+//! + `audit <max seals>`: audit the most recent seals in the chain, starting from the chain head
+//! + `participation`: how consistently each member has taken part in consensus recently
+//! + `verification-cache`: hits and misses of the cache of verified vote signatures
+//! + `message-log`: the size of the message log and the messages it rejected
+
+use std::fs::remove_file;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+
+use hex;
+use serde_json::{json, Value};
+
+use crate::host::BlockId;
+
+/// Longest request a client may send; clients that send a longer line are dropped
+const MAX_REQUEST_LENGTH: usize = 4096;
+
+/// Most reads made from a single client in one poll, so a client that keeps sending data can't
+/// hold up the engine
+const MAX_READS_PER_POLL: usize = 4;
+
+/// Most seals a single audit request may check; audits run on the engine thread, so larger limits
+/// are reduced to this
+const MAX_AUDIT_SEALS: u64 = 100;
+
+/// Queries that can be made over the admin socket
+#[derive(Debug, PartialEq)]
+pub enum AdminRequest {
+    /// Get the finality proof for the block with this ID
+    FinalityProof(BlockId),
+    /// Audit the seals in the chain, checking at most this many
+    Audit(u64),
+    /// Get the report on recent member participation
+    Participation,
+    /// Get the statistics of the vote signature verification cache
+    VerificationCache,
+    /// Get the size of the message log and counts of the messages it rejected
+    MessageLog,
+}
+
+impl AdminRequest {
+    /// Parse a request from a line of text sent by a client
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (Some("finality-proof"), Some(block_id), None) => hex::decode(block_id)
+                .map(AdminRequest::FinalityProof)
+                .map_err(|err| format!("Invalid block ID: {}", err)),
+            (Some("finality-proof"), _, _) => Err("Usage: finality-proof <block ID>".into()),
+            (Some("audit"), Some(max), None) => max
+                .parse::<u64>()
+                .map(|max| AdminRequest::Audit(max.min(MAX_AUDIT_SEALS)))
+                .map_err(|err| format!("Invalid max seals: {}", err)),
+            (Some("audit"), _, _) => Err("Usage: audit <max seals>".into()),
+            (Some("participation"), None, _) => Ok(AdminRequest::Participation),
+            (Some("participation"), _, _) => Err("Usage: participation".into()),
+            (Some("verification-cache"), None, _) => Ok(AdminRequest::VerificationCache),
+            (Some("verification-cache"), _, _) => Err("Usage: verification-cache".into()),
+            (Some("message-log"), None, _) => Ok(AdminRequest::MessageLog),
+            (Some("message-log"), _, _) => Err("Usage: message-log".into()),
+            (Some(command), _, _) => Err(format!("Unknown request: {}", command)),
+            (None, _, _) => Err("Empty request".into()),
+        }
+    }
+}
+
+/// A connected client that hasn't sent a complete request or received its full response yet
+struct AdminClient {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    /// The part of the response that hasn't been written yet, once the request has been answered
+    response: Option<Vec<u8>>,
+}
+
+/// Listens for admin requests on a local Unix socket
+///
+/// The socket is non-blocking; requests are only read and answered when `poll` is called, so the
+/// engine is never blocked waiting on a client. Responses that a client isn't ready to receive
+/// are kept and written on later polls.
+pub struct AdminSocket {
+    listener: UnixListener,
+    clients: Vec<AdminClient>,
+}
+
+impl AdminSocket {
+    pub fn bind<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        // Remove a socket left behind by a previous run, since it would prevent binding
+        if path.as_ref().exists() {
+            remove_file(&path).map_err(|err| format!("Couldn't remove old socket: {}", err))?;
+        }
+
+        let listener = UnixListener::bind(&path)
+            .map_err(|err| format!("Couldn't bind admin socket: {}", err))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| format!("Couldn't configure admin socket: {}", err))?;
+
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Accept any new clients, then answer every client that has sent a complete request using
+    /// the given handler
+    pub fn poll<F>(&mut self, mut handler: F)
+    where
+        F: FnMut(AdminRequest) -> Result<Value, String>,
+    {
+        self.accept_clients();
+
+        let mut pending = Vec::with_capacity(self.clients.len());
+        for mut client in self.clients.drain(..) {
+            if client.response.is_none() {
+                match read_request(&mut client) {
+                    Ok(Some(line)) => {
+                        let response = match AdminRequest::parse(&line).and_then(&mut handler) {
+                            Ok(result) => json!({ "result": result }),
+                            Err(err) => json!({ "error": err }),
+                        };
+                        match serialize_response(&response) {
+                            Ok(line) => client.response = Some(line),
+                            Err(err) => {
+                                warn!("Couldn't answer admin request: {}", err);
+                                continue;
+                            }
+                        }
+                    }
+                    Ok(None) => {
+                        pending.push(client);
+                        continue;
+                    }
+                    Err(err) => {
+                        debug!("Dropping admin client due to error: {}", err);
+                        continue;
+                    }
+                }
+            }
+
+            match send_response(&mut client) {
+                Ok(true) => {}
+                Ok(false) => pending.push(client),
+                Err(err) => warn!("Couldn't answer admin request: {}", err),
+            }
+        }
+        self.clients = pending;
+    }
+
+    /// Add any clients that have connected since the last poll
+    fn accept_clients(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match stream.set_nonblocking(true) {
+                    Ok(_) => self.clients.push(AdminClient {
+                        stream,
+                        buffer: Vec::new(),
+                        response: None,
+                    }),
+                    Err(err) => warn!("Couldn't configure admin client: {}", err),
+                },
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("Couldn't accept admin client: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Read what the client has sent since the last poll, up to `MAX_READS_PER_POLL` reads; return
+/// the request if a complete line has been received
+fn read_request(client: &mut AdminClient) -> Result<Option<String>, String> {
+    let mut chunk = [0; 1024];
+    let mut complete = client.buffer.contains(&b'\n');
+    let mut reads = 0;
+    while !complete && reads < MAX_READS_PER_POLL {
+        reads += 1;
+        match client.stream.read(&mut chunk) {
+            // The client closed its end; treat whatever it sent as the full request
+            Ok(0) if client.buffer.is_empty() => return Err("Client disconnected".into()),
+            Ok(0) => complete = true,
+            Ok(len) => {
+                client.buffer.extend_from_slice(&chunk[..len]);
+                complete = chunk[..len].contains(&b'\n');
+            }
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => return Err(err.to_string()),
+        }
+    }
+
+    let line_len = client
+        .buffer
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .unwrap_or_else(|| client.buffer.len());
+    if line_len > MAX_REQUEST_LENGTH {
+        return Err(format!(
+            "Request is longer than {} bytes",
+            MAX_REQUEST_LENGTH
+        ));
+    }
+    if !complete {
+        return Ok(None);
+    }
+
+    String::from_utf8(client.buffer[..line_len].to_vec())
+        .map(Some)
+        .map_err(|err| format!("Request is not valid UTF-8: {}", err))
+}
+
+/// Serialize the response as a single line of JSON
+fn serialize_response(response: &Value) -> Result<Vec<u8>, String> {
+    let mut line = serde_json::to_vec(response)
+        .map_err(|err| format!("Couldn't serialize response: {}", err))?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Write as much of the client's response as it will accept without blocking; return whether the
+/// whole response has been written
+fn send_response(client: &mut AdminClient) -> Result<bool, String> {
+    let response = match client.response.as_mut() {
+        Some(response) => response,
+        None => return Ok(true),
+    };
+
+    while !response.is_empty() {
+        match client.stream.write(response) {
+            Ok(0) => return Err("Client stopped accepting the response".into()),
+            Ok(len) => {
+                response.drain(..len);
+            }
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err.to_string()),
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::distributions::Alphanumeric;
+    use self::rand::{thread_rng, Rng};
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    fn random_path() -> String {
+        String::from("/tmp/")
+            + &thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .collect::<String>()
+    }
+
+    /// Requests are single lines of whitespace-separated words; malformed requests must be
+    /// rejected with a useful message instead of being passed to the handler.
+    #[test]
+    fn test_request_parsing() {
+        assert_eq!(
+            Ok(AdminRequest::FinalityProof(vec![0xab, 0xcd])),
+            AdminRequest::parse("finality-proof abcd")
+        );
+        assert!(AdminRequest::parse("finality-proof").is_err());
+        assert!(AdminRequest::parse("finality-proof xyz").is_err());
+        assert!(AdminRequest::parse("finality-proof ab cd").is_err());
+        assert!(AdminRequest::parse("audit").is_err());
+        assert_eq!(Ok(AdminRequest::Audit(10)), AdminRequest::parse("audit 10"));
+        assert_eq!(
+            Ok(AdminRequest::Audit(MAX_AUDIT_SEALS)),
+            AdminRequest::parse("audit 1000000")
+        );
+        assert!(AdminRequest::parse("audit -1").is_err());
+        assert!(AdminRequest::parse("audit 1 2").is_err());
+        assert_eq!(
+            Ok(AdminRequest::Participation),
+            AdminRequest::parse("participation")
+        );
+        assert_eq!(
+            Ok(AdminRequest::VerificationCache),
+            AdminRequest::parse("verification-cache")
+        );
+        assert!(AdminRequest::parse("verification-cache now").is_err());
+        assert_eq!(
+            Ok(AdminRequest::MessageLog),
+            AdminRequest::parse("message-log")
+        );
+        assert!(AdminRequest::parse("message-log now").is_err());
+        assert!(AdminRequest::parse("").is_err());
+        assert!(AdminRequest::parse("status").is_err());
+    }
+
+    /// A client that sends a request must get the handler's result back as a line of JSON, and a
+    /// client that sends an invalid request must get an error without the handler being called.
+    /// Clients that haven't finished sending their request must be kept until they do.
+    #[test]
+    fn test_admin_socket() {
+        let path = random_path();
+        let mut socket = AdminSocket::bind(&path).unwrap();
+
+        let mut valid = UnixStream::connect(&path).unwrap();
+        valid.write_all(b"finality-proof 01\n").unwrap();
+        let mut invalid = UnixStream::connect(&path).unwrap();
+        invalid.write_all(b"bogus\n").unwrap();
+        let mut partial = UnixStream::connect(&path).unwrap();
+        partial.write_all(b"finality-").unwrap();
+
+        let mut requests = vec![];
+        socket.poll(|request| {
+            requests.push(request);
+            Ok(json!("proof"))
+        });
+        assert_eq!(vec![AdminRequest::FinalityProof(vec![1])], requests);
+
+        let mut line = String::new();
+        BufReader::new(&valid).read_line(&mut line).unwrap();
+        assert_eq!(
+            json!({"result": "proof"}),
+            serde_json::from_str::<Value>(&line).unwrap()
+        );
+
+        line.clear();
+        BufReader::new(&invalid).read_line(&mut line).unwrap();
+        assert!(serde_json::from_str::<Value>(&line).unwrap()["error"].is_string());
+
+        // Finish the partial request and verify that it's answered on the next poll
+        partial.write_all(b"proof 02\n").unwrap();
+        socket.poll(|request| {
+            assert_eq!(AdminRequest::FinalityProof(vec![2]), request);
+            Err("not committed".into())
+        });
+        line.clear();
+        BufReader::new(&partial).read_line(&mut line).unwrap();
+        assert_eq!(
+            json!({"error": "not committed"}),
+            serde_json::from_str::<Value>(&line).unwrap()
+        );
+
+        remove_file(path).unwrap();
+    }
+
+    /// A client must not be able to make the node buffer an arbitrarily long request; once it has
+    /// sent more than `MAX_REQUEST_LENGTH` bytes without ending the line, it must be dropped
+    /// without the handler being called.
+    #[test]
+    fn test_admin_socket_long_request() {
+        let path = random_path();
+        let mut socket = AdminSocket::bind(&path).unwrap();
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        client
+            .write_all(&vec![b'a'; MAX_REQUEST_LENGTH + 1])
+            .unwrap();
+
+        // The client may need more than one poll to be read completely
+        for _ in 0..MAX_REQUEST_LENGTH / (MAX_READS_PER_POLL * 1024) + 2 {
+            socket.poll(|_| panic!("Handler called for an overlong request"));
+        }
+        assert!(socket.clients.is_empty());
+
+        remove_file(path).unwrap();
+    }
+}
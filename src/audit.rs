@@ -0,0 +1,351 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Audit of the consensus seals stored in the chain
+//!
+//! Every block after block 1 carries the seal for its predecessor in its payload. The audit walks
+//! the chain backward and verifies each of these seals against the on-chain members at the time
+//! the sealed block was voted on.
+
+use std::collections::BTreeMap;
+
+use hex;
+
+use crate::config::parse_members;
+use crate::error::PbftError;
+use crate::host::{Block, BlockId, ConsensusHost};
+use crate::protos::pbft_message::PbftSeal;
+use crate::verification::{check_seal_with, SealVerdict};
+
+/// A problem with the seal for one block
+#[derive(Debug, Serialize)]
+pub struct SealIssue {
+    /// Number of the block the seal should prove
+    pub block_num: u64,
+    /// Hex-encoded ID of the block the seal should prove
+    pub block_id: String,
+    /// Hex-encoded ID of the block whose payload holds the seal
+    pub sealed_in: String,
+    /// Every problem found with the seal
+    pub errors: Vec<String>,
+}
+
+/// How often a member contributed to the seals it was eligible to contribute to
+#[derive(Debug, Default, Serialize)]
+pub struct MemberParticipation {
+    /// Number of audited seals for blocks voted on while this node was a member
+    pub eligible: u64,
+    /// Number of those seals with a valid Commit vote from this member
+    pub signed: u64,
+    /// Number of those seals built by this member (building a seal is an implicit vote)
+    pub built: u64,
+}
+
+/// The results of auditing a range of the chain
+#[derive(Debug, Default, Serialize)]
+pub struct AuditReport {
+    /// Number of the newest block whose seal was audited
+    pub newest_block_num: Option<u64>,
+    /// Number of the oldest block whose seal was audited
+    pub oldest_block_num: Option<u64>,
+    /// Number of seals audited
+    pub seals_checked: u64,
+    /// Seals that are present but invalid for a reason other than having too few votes
+    pub invalid: Vec<SealIssue>,
+    /// Blocks whose successor doesn't contain a seal
+    pub missing: Vec<SealIssue>,
+    /// Seals whose votes are all valid, but with fewer votes than required
+    pub under_signed: Vec<SealIssue>,
+    /// Participation of each member (by hex-encoded ID) in the audited seals
+    pub participation: BTreeMap<String, MemberParticipation>,
+}
+
+/// Walk the chain backward from the given block, verifying the seal for each block (which is
+/// stored in the payload of its successor) against the members that were active when the block
+/// was voted on. At most `max_seals` seals are checked if a limit is given; otherwise the audit
+/// continues until block 1.
+///
+/// Problems with individual seals are recorded in the report; an error is only returned if the
+/// chain itself can't be read from the host.
+pub fn audit_chain(
+    host: &mut dyn ConsensusHost,
+    head_id: BlockId,
+    max_seals: Option<u64>,
+) -> Result<AuditReport, PbftError> {
+    let mut report = AuditReport::default();
+    let mut successor = get_block(host, head_id)?;
+
+    while successor.block_num > 1 && max_seals.map_or(true, |max| report.seals_checked < max) {
+        let block = get_block(host, successor.previous_id.clone())?;
+        let issue = |errors| SealIssue {
+            block_num: block.block_num,
+            block_id: hex::encode(&block.block_id),
+            sealed_in: hex::encode(&successor.block_id),
+            errors,
+        };
+
+        report.seals_checked += 1;
+        report.newest_block_num.get_or_insert(block.block_num);
+        report.oldest_block_num = Some(block.block_num);
+
+        if successor.payload.is_empty() {
+            report.missing.push(issue(vec!["Block has no seal".into()]));
+            successor = block;
+            continue;
+        }
+
+        let seal = match protobuf::parse_from_bytes::<PbftSeal>(&successor.payload) {
+            Ok(seal) => seal,
+            Err(err) => {
+                report
+                    .missing
+                    .push(issue(vec![format!("Couldn't parse seal: {}", err)]));
+                successor = block;
+                continue;
+            }
+        };
+
+        // The seal must be verified against the members at the time the block was voted on,
+        // which are the members in the settings of the block's predecessor
+        let members = match get_members(host, block.previous_id.clone()) {
+            Ok(members) => members,
+            Err(err) => {
+                report.invalid.push(issue(vec![err]));
+                successor = block;
+                continue;
+            }
+        };
+
+        let verdict = check_seal_with(
+            &seal,
+            &block.block_id,
+            Some(&block.previous_id),
+            &members,
+            &|votes| host.authenticate_votes(votes),
+        );
+
+        for member in &members {
+            let participation = report.participation.entry(hex::encode(member)).or_default();
+            participation.eligible += 1;
+        }
+        // Votes in a seal for some other block don't count as participation in this block
+        if verdict.seal_block_id == verdict.block_id {
+            record_participation(&mut report, &verdict);
+        }
+
+        if !verdict.valid {
+            let under_signed = verdict.voter_count < verdict.required_votes as usize
+                && verdict.seal_block_id == verdict.block_id
+                && verdict.votes.iter().all(|vote| vote.error.is_none());
+            if under_signed {
+                report.under_signed.push(issue(verdict.errors));
+            } else {
+                report.invalid.push(issue(verdict.errors));
+            }
+        }
+
+        successor = block;
+    }
+
+    Ok(report)
+}
+
+/// Credit the seal's builder and each member with a valid vote in the seal
+fn record_participation(report: &mut AuditReport, verdict: &SealVerdict) {
+    if let Some(participation) = report.participation.get_mut(&verdict.seal_signer_id) {
+        participation.built += 1;
+    }
+
+    for vote in verdict.votes.iter().filter(|vote| vote.error.is_none()) {
+        if let Some(participation) = vote
+            .signer_id
+            .as_ref()
+            .and_then(|id| report.participation.get_mut(id))
+        {
+            participation.signed += 1;
+        }
+    }
+}
+
+fn get_block(host: &mut dyn ConsensusHost, block_id: BlockId) -> Result<Block, PbftError> {
+    host.get_blocks(vec![block_id.clone()])
+        .map_err(|err| PbftError::ServiceError("Couldn't get block for audit".into(), err))?
+        .remove(&block_id)
+        .ok_or_else(|| {
+            PbftError::InternalError(format!("Block not found: {}", hex::encode(&block_id)))
+        })
+}
+
+fn get_members(host: &mut dyn ConsensusHost, block_id: BlockId) -> Result<Vec<Vec<u8>>, String> {
+    let settings = host
+        .get_settings(
+            block_id.clone(),
+            vec![String::from("sawtooth.consensus.pbft.members")],
+        )
+        .map_err(|err| {
+            format!(
+                "Couldn't get members at {}: {}",
+                hex::encode(&block_id),
+                err
+            )
+        })?;
+
+    settings
+        .get("sawtooth.consensus.pbft.members")
+        .ok_or_else(|| format!("Members are not set at {}", hex::encode(&block_id)))
+        .and_then(|value| parse_members(value))
+        .map_err(|err| format!("Invalid members at {}: {}", hex::encode(&block_id), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::SawtoothHost;
+    use crate::message_type::PbftMessageType;
+    use crate::testkit::*;
+    use protobuf::Message;
+    use std::collections::HashMap;
+
+    /// To audit the chain, every block's seal (stored in the next block's payload) must be
+    /// verified against the members at the block's predecessor, since those were the members
+    /// that voted on the block. The report must separate seals that are missing, seals that have
+    /// too few votes, and seals that are otherwise invalid, and it must count how often each
+    /// member signed the seals it was eligible for.
+    ///
+    /// This test builds a chain of 7 blocks where:
+    ///
+    /// + Blocks 1, 2, and 6 have valid seals (block 6's is checked against a new member set)
+    /// + Block 3 has no seal
+    /// + Block 4's seal has too few votes
+    /// + Block 5's seal is for a different block
+    ///
+    /// and verifies that the audit finds each problem and reports participation correctly (votes
+    /// in block 5's seal aren't counted, since they are for another block). It also verifies that
+    /// the audit can be limited to the most recent seals.
+    #[test]
+    fn test_chain_audit() {
+        let key_pairs = mock_signer_network(5);
+        let members_setting = |signers: &[usize]| {
+            let mut settings = HashMap::new();
+            settings.insert(
+                "sawtooth.consensus.pbft.members".to_string(),
+                serde_json::to_string(
+                    &signers
+                        .iter()
+                        .map(|i| hex::encode(&key_pairs[*i].pub_key))
+                        .collect::<Vec<_>>(),
+                )
+                .unwrap(),
+            );
+            settings
+        };
+        let seal = |num: u8, block_id: u8, signers: &[usize]| {
+            mock_seal(
+                0,
+                num as u64,
+                vec![block_id],
+                &key_pairs[0],
+                signers
+                    .iter()
+                    .map(|i| {
+                        mock_vote(
+                            PbftMessageType::Commit,
+                            0,
+                            num as u64,
+                            vec![block_id],
+                            &key_pairs[*i],
+                        )
+                    })
+                    .collect(),
+            )
+            .write_to_bytes()
+            .unwrap()
+        };
+
+        let service = MockService::new(&mock_config(0));
+        // Nodes 0-3 are members until block 5, when node 4 replaces node 3
+        for num in 0..5 {
+            service.set_settings(vec![num], members_setting(&[0, 1, 2, 3]));
+        }
+        service.set_settings(vec![5], members_setting(&[0, 1, 2, 4]));
+
+        let payloads = vec![
+            vec![],
+            vec![],
+            seal(1, 1, &[1, 2]),
+            seal(2, 2, &[1, 2, 3]),
+            vec![],
+            seal(4, 4, &[1]),
+            seal(5, 4, &[1, 2]),
+            seal(6, 6, &[2, 4]),
+        ];
+        for (num, payload) in payloads.into_iter().enumerate() {
+            let mut block = mock_block(num as u8);
+            block.payload = payload;
+            service.add_block(block);
+        }
+        let mut host = SawtoothHost::new(Box::new(service));
+
+        let report = audit_chain(&mut host, vec![7], None).expect("Audit failed");
+        assert_eq!(6, report.seals_checked);
+        assert_eq!(Some(6), report.newest_block_num);
+        assert_eq!(Some(1), report.oldest_block_num);
+        assert_eq!(
+            vec![3],
+            report
+                .missing
+                .iter()
+                .map(|i| i.block_num)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![4],
+            report
+                .under_signed
+                .iter()
+                .map(|i| i.block_num)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![5],
+            report
+                .invalid
+                .iter()
+                .map(|i| i.block_num)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("06", report.invalid[0].sealed_in);
+
+        let participation = |i: usize| &report.participation[&hex::encode(&key_pairs[i].pub_key)];
+        assert_eq!(5, participation(0).eligible);
+        assert_eq!(4, participation(0).built);
+        assert_eq!(3, participation(1).signed);
+        assert_eq!(3, participation(2).signed);
+        assert_eq!(4, participation(3).eligible);
+        assert_eq!(1, participation(3).signed);
+        assert_eq!(1, participation(4).eligible);
+        assert_eq!(1, participation(4).signed);
+
+        // Only audit the two most recent seals
+        let report = audit_chain(&mut host, vec![7], Some(2)).expect("Audit failed");
+        assert_eq!(2, report.seals_checked);
+        assert_eq!(Some(5), report.oldest_block_num);
+
+        // The audit fails if the chain can't be read
+        assert!(audit_chain(&mut host, vec![8], None).is_err());
+    }
+}
@@ -21,17 +21,19 @@ use std::collections::HashMap;
 use std::time::Duration;
 
 use hex;
-use sawtooth_sdk::consensus::{
-    engine::{BlockId, PeerId},
-    service::Service,
-};
+use sawtooth_sdk::consensus::service::Service;
 use serde_json;
 
+use crate::host::{BlockId, PeerId};
+use crate::signature::{SignatureScheme, DEFAULT_VERIFICATION_CACHE_SIZE};
 use crate::timing::retry_until_ok;
 
+/// The clock that `PbftConfig::load_settings` waits on between retries
+pub use crate::timing::Clock;
+
 /// Contains the initial configuration loaded from on-chain settings and local configuration. The
 /// `members` list is required; all other settings are optional (defaults used in their absence)
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PbftConfig {
     // Members of the PBFT network
     pub members: Vec<PeerId>,
@@ -39,7 +41,9 @@ pub struct PbftConfig {
     /// How long to wait in between trying to publish blocks
     pub block_publishing_delay: Duration,
 
-    /// How long to wait for an update to arrive from the validator
+    /// How long to wait for an update to arrive from the validator before checking for admin
+    /// requests; without an admin socket, the engine waits until its next timeout or block
+    /// publishing attempt is due
     pub update_recv_timeout: Duration,
 
     /// The base time to use for retrying with exponential backoff
@@ -69,6 +73,76 @@ pub struct PbftConfig {
 
     /// Where to store PbftState ("memory" or "disk+/path/to/file")
     pub storage_location: String,
+
+    /// Where to publish consensus events ("file+/path/to/file" or "unix+/path/to/socket"); events
+    /// are not published if this is not set
+    pub event_stream_location: Option<String>,
+
+    /// Path of the Unix socket to answer admin requests on; the admin socket is disabled if this
+    /// is not set
+    pub admin_socket_path: Option<String>,
+
+    /// How many of the most recent blocks to track member participation over; participation is
+    /// logged each time this many blocks are committed (never, if this is 0)
+    pub participation_window: u64,
+
+    /// Members that take part in less than this fraction of the blocks in the participation
+    /// window are flagged
+    pub participation_threshold: f64,
+
+    /// Where to record the updates received from the validator and the results of service calls,
+    /// so the node's behavior can be replayed; nothing is recorded if this is not set
+    pub recording_path: Option<String>,
+
+    /// The signature scheme that the validators sign votes with; every node in the network must
+    /// use the same scheme
+    #[serde(default)]
+    pub signature_scheme: SignatureScheme,
+
+    /// How many valid vote signatures to remember, so votes that are checked again don't have
+    /// to be verified again; 0 disables the cache
+    #[serde(default = "default_verification_cache_size")]
+    pub verification_cache_size: usize,
+
+    /// How far ahead of the node's sequence number a PrePrepare, Prepare, Commit, or SealRequest
+    /// from another node may be for the PbftLog to accept it
+    #[serde(default = "default_message_seq_window")]
+    pub message_seq_window: u64,
+
+    /// How far ahead of the node's view a PrePrepare, Prepare, Commit, or ViewChange from another
+    /// node may be for the PbftLog to accept it
+    #[serde(default = "default_message_view_window")]
+    pub message_view_window: u64,
+
+    /// How many messages from any one signer the PbftLog holds at once
+    #[serde(default = "default_max_messages_per_signer")]
+    pub max_messages_per_signer: u64,
+
+    /// How many blocks behind the chain head a block may be for this node to build a finality
+    /// proof for it; finding the block's successor takes one block read per block between it and
+    /// the chain head
+    #[serde(default = "default_max_finality_proof_depth")]
+    pub max_finality_proof_depth: u64,
+}
+
+fn default_verification_cache_size() -> usize {
+    DEFAULT_VERIFICATION_CACHE_SIZE
+}
+
+fn default_message_seq_window() -> u64 {
+    100
+}
+
+fn default_message_view_window() -> u64 {
+    100
+}
+
+fn default_max_messages_per_signer() -> u64 {
+    1000
+}
+
+fn default_max_finality_proof_depth() -> u64 {
+    100
 }
 
 impl PbftConfig {
@@ -85,6 +159,17 @@ impl PbftConfig {
             forced_view_change_interval: 100,
             max_log_size: 10000,
             storage_location: "memory".into(),
+            event_stream_location: None,
+            admin_socket_path: None,
+            participation_window: 100,
+            participation_threshold: 0.5,
+            recording_path: None,
+            signature_scheme: SignatureScheme::default(),
+            verification_cache_size: DEFAULT_VERIFICATION_CACHE_SIZE,
+            message_seq_window: default_message_seq_window(),
+            message_view_window: default_message_view_window(),
+            max_messages_per_signer: default_max_messages_per_signer(),
+            max_finality_proof_depth: default_max_finality_proof_depth(),
         }
     }
 
@@ -101,9 +186,15 @@ impl PbftConfig {
     /// # Panics
     /// + If block publishing delay is greater than the idle timeout
     /// + If the `sawtooth.consensus.pbft.members` setting is not provided or is invalid
-    pub fn load_settings(&mut self, block_id: BlockId, service: &mut dyn Service) {
+    pub fn load_settings(
+        &mut self,
+        block_id: BlockId,
+        service: &mut dyn Service,
+        clock: &dyn Clock,
+    ) {
         debug!("Getting on-chain settings for config");
         let settings: HashMap<String, String> = retry_until_ok(
+            clock,
             self.exponential_retry_base,
             self.exponential_retry_max,
             || {
@@ -212,19 +303,24 @@ pub fn get_members_from_settings<S: std::hash::BuildHasher>(
         .get("sawtooth.consensus.pbft.members")
         .expect("'sawtooth.consensus.pbft.members' is empty; this setting must exist to use PBFT");
 
-    let members: Vec<String> = serde_json::from_str(members_setting_value).unwrap_or_else(|err| {
+    parse_members(members_setting_value).unwrap_or_else(|err| {
         panic!(
-            "Unable to parse value at 'sawtooth.consensus.pbft.members' due to error: {:?}",
+            "Unable to parse value at 'sawtooth.consensus.pbft.members' due to error: {}",
             err
         )
-    });
+    })
+}
+
+/// Parse a list of members in the format used by the `sawtooth.consensus.pbft.members` setting (a
+/// JSON list of hex-encoded public keys)
+pub fn parse_members(value: &str) -> Result<Vec<PeerId>, String> {
+    let members: Vec<String> =
+        serde_json::from_str(value).map_err(|err| format!("Invalid JSON list: {}", err))?;
 
     members
         .into_iter()
         .map(|s| {
-            hex::decode(s).unwrap_or_else(|err| {
-                panic!("Unable to parse PeerId from string due to error: {:?}", err)
-            })
+            hex::decode(&s).map_err(|err| format!("Unable to parse PeerId from {}: {}", s, err))
         })
         .collect()
 }
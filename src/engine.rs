@@ -17,25 +17,52 @@
 
 //! Entry point for the consensus algorithm, including the main event loop
 
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use hex;
+use protobuf::Message;
 use sawtooth_sdk::consensus::{engine::*, service::Service};
+use serde_json::{json, Value};
 
+use crate::admin::{AdminRequest, AdminSocket};
+use crate::audit::audit_chain;
 use crate::config::PbftConfig;
 use crate::error::PbftError;
-use crate::message_type::ParsedMessage;
+use crate::events::get_event_sink;
+use crate::message_type::{ParsedMessage, PbftMessageType};
 use crate::node::PbftNode;
+use crate::recording::{Recorder, RecordingClock, RecordingService};
 use crate::state::{PbftMode, PbftState};
 use crate::storage::get_storage;
-use crate::timing;
+use crate::timing::{self, system_clock, Clock, ClockRef, Ticker};
+
+/// The most updates that are handled between writes of the state (and checks for admin requests)
+const MAX_UPDATE_BATCH_SIZE: usize = 1000;
 
 pub struct PbftEngine {
     config: PbftConfig,
+    clock: ClockRef,
 }
 
 impl PbftEngine {
     pub fn new(config: PbftConfig) -> Self {
-        PbftEngine { config }
+        PbftEngine {
+            config,
+            clock: system_clock(),
+        }
+    }
+
+    /// Create an engine whose timeouts, block publishing, and retries are driven by the given
+    /// clock
+    #[cfg(test)]
+    pub fn with_clock(config: PbftConfig, clock: ClockRef) -> Self {
+        PbftEngine { config, clock }
     }
 }
 
@@ -56,10 +83,26 @@ impl Engine for PbftEngine {
 
         // Load on-chain settings
         self.config
-            .load_settings(chain_head.block_id.clone(), &mut *service);
+            .load_settings(chain_head.block_id.clone(), &mut *service, &*self.clock);
 
         info!("PBFT config loaded: {:?}", self.config);
 
+        // When recording, the node's clock only moves forward between updates, so the recording
+        // captures the time the node sees while handling each one
+        let (clock, recorder) = match &self.config.recording_path {
+            Some(path) => {
+                let clock = Arc::new(RecordingClock::new(self.clock.clone()));
+                match Recorder::create(path, clock.clone()) {
+                    Ok(recorder) => (clock as ClockRef, Some(Rc::new(RefCell::new(recorder)))),
+                    Err(err) => {
+                        error!("Failed to start recording; not recording: {}", err);
+                        (self.clock.clone(), None)
+                    }
+                }
+            }
+            None => (self.clock.clone(), None),
+        };
+
         let mut pbft_state = get_storage(&self.config.storage_location, || {
             PbftState::new(
                 local_peer_info.peer_id.clone(),
@@ -69,9 +112,26 @@ impl Engine for PbftEngine {
         })
         .unwrap_or_else(|err| panic!("Failed to load state due to error: {}", err));
 
+        // The clock isn't persisted, so a loaded state must be given it again
+        pbft_state.write().set_clock(clock.clone());
+
         info!("PBFT state created: {}", **pbft_state.read());
 
-        let mut block_publishing_ticker = timing::Ticker::new(self.config.block_publishing_delay);
+        let service: Box<dyn Service> = match &recorder {
+            Some(recorder) => {
+                recorder.borrow_mut().record_start(
+                    &self.config,
+                    &chain_head,
+                    &peers,
+                    &**pbft_state.read(),
+                );
+                Box::new(RecordingService::new(service, recorder.clone()))
+            }
+            None => service,
+        };
+
+        let mut block_publishing_ticker =
+            timing::Ticker::new(self.config.block_publishing_delay, clock.clone());
 
         let mut node = PbftNode::new(
             &self.config,
@@ -81,48 +141,78 @@ impl Engine for PbftEngine {
             &mut pbft_state.write(),
         );
 
+        if let Some(location) = &self.config.event_stream_location {
+            match get_event_sink(location) {
+                Ok(sink) => node.set_event_sink(sink),
+                Err(err) => error!(
+                    "Failed to create event sink; not publishing events: {}",
+                    err
+                ),
+            }
+        }
+
+        let mut admin_socket = match &self.config.admin_socket_path {
+            Some(path) => match AdminSocket::bind(path) {
+                Ok(socket) => Some(socket),
+                Err(err) => {
+                    error!(
+                        "Failed to create admin socket; not answering admin requests: {}",
+                        err
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+
         node.start_idle_timeout(&mut pbft_state.write());
 
-        // Main event loop; keep going until PBFT receives a Shutdown message or is disconnected
+        // Main event loop; keep going until PBFT receives a Shutdown message or is disconnected.
+        // Between updates, the loop sleeps until the next timeout or block publishing attempt is
+        // due, or until it's time to check for admin requests.
         loop {
-            let incoming_message = updates.recv_timeout(self.config.update_recv_timeout);
+            let deadline = next_deadline(&node, &**pbft_state.read(), &block_publishing_ticker);
+            let mut wait = clock.time_until(deadline);
+            if admin_socket.is_some() {
+                wait = cmp::min(wait, self.config.update_recv_timeout);
+            }
+
+            // Handle every update that has arrived under a single write of the state, so it is
+            // only persisted once for the whole batch
+            let batch = prioritize_updates(receive_updates(&updates, wait, MAX_UPDATE_BATCH_SIZE));
             let state = &mut **pbft_state.write();
 
-            trace!("{} received message {:?}", state, incoming_message);
+            let mut running = true;
+            for incoming_message in batch {
+                trace!("{} received message {:?}", state, incoming_message);
 
-            match handle_update(&mut node, incoming_message, state) {
-                Ok(again) => {
-                    if !again {
-                        break;
-                    }
+                if let Some(recorder) = &recorder {
+                    recorder.borrow_mut().record_update(&incoming_message);
                 }
-                Err(err) => log_any_error(Err(err)),
-            }
-
-            // If the block publishing delay has passed, attempt to publish a block
-            block_publishing_ticker.tick(|| log_any_error(node.try_publish(state)));
 
-            // If the idle timeout has expired, initiate a view change
-            if node.check_idle_timeout_expired(state) {
-                warn!("Idle timeout expired; proposing view change");
-                log_any_error(node.start_view_change(state, state.view + 1));
+                if !process_update(
+                    &mut node,
+                    incoming_message,
+                    state,
+                    &mut block_publishing_ticker,
+                ) {
+                    running = false;
+                    break;
+                }
             }
-
-            // If the commit timeout has expired, initiate a view change
-            if node.check_commit_timeout_expired(state) {
-                warn!("Commit timeout expired; proposing view change");
-                log_any_error(node.start_view_change(state, state.view + 1));
+            if !running {
+                break;
             }
 
-            // Check the view change timeout if the node is view changing so we can start a new
-            // view change if we don't get a NewView in time
-            if let PbftMode::ViewChanging(v) = state.mode {
-                if node.check_view_change_timeout_expired(state) {
-                    warn!(
-                        "View change timeout expired; proposing view change for view {}",
-                        v + 1
-                    );
-                    log_any_error(node.start_view_change(state, v + 1));
+            // Answer any admin requests that have been received; they don't change the node's
+            // state, so they aren't recorded
+            if let Some(socket) = admin_socket.as_mut() {
+                if let Some(recorder) = &recorder {
+                    recorder.borrow_mut().set_paused(true);
+                }
+                socket.poll(|request| handle_admin_request(&mut node, request, state));
+                if let Some(recorder) = &recorder {
+                    recorder.borrow_mut().set_paused(false);
                 }
             }
         }
@@ -143,13 +233,167 @@ impl Engine for PbftEngine {
     }
 }
 
-fn handle_update(
+fn handle_admin_request(
+    node: &mut PbftNode,
+    request: AdminRequest,
+    state: &PbftState,
+) -> Result<Value, String> {
+    match request {
+        AdminRequest::FinalityProof(block_id) => {
+            let proof = node
+                .get_finality_proof(&block_id, state)
+                .map_err(|err| err.to_string())?;
+            let proof_bytes = proof
+                .write_to_bytes()
+                .map_err(|err| format!("Couldn't serialize finality proof: {}", err))?;
+
+            Ok(json!({
+                "proof": serde_json::to_value(&proof).map_err(|err| err.to_string())?,
+                "proof_bytes": hex::encode(proof_bytes),
+            }))
+        }
+        AdminRequest::Audit(max_seals) => {
+            info!("Auditing chain seals (max: {})", max_seals);
+            let report = audit_chain(&mut *node.host, state.chain_head.clone(), Some(max_seals))
+                .map_err(|err| err.to_string())?;
+            serde_json::to_value(&report).map_err(|err| err.to_string())
+        }
+        AdminRequest::Participation => {
+            serde_json::to_value(&node.participation_report(state)).map_err(|err| err.to_string())
+        }
+        AdminRequest::VerificationCache => node
+            .host
+            .verification_cache_stats()
+            .ok_or_else(|| "The host doesn't cache verified signatures".to_string())
+            .and_then(|stats| serde_json::to_value(&stats).map_err(|err| err.to_string())),
+        AdminRequest::MessageLog => Ok(json!({
+            "messages": node.msg_log.len(),
+            "rejected": serde_json::to_value(node.msg_log.rejected_messages())
+                .map_err(|err| err.to_string())?,
+        })),
+    }
+}
+
+/// Wait up to `wait` for an update from the validator, then take any others that have already
+/// arrived, up to `max_batch_size` updates in all
+///
+/// If no update arrives in time, the batch is just the timeout (or disconnection) error.
+fn receive_updates(
+    updates: &Receiver<Update>,
+    wait: Duration,
+    max_batch_size: usize,
+) -> Vec<Result<Update, RecvTimeoutError>> {
+    let mut batch = vec![updates.recv_timeout(wait)];
+    if batch[0].is_ok() {
+        while batch.len() < max_batch_size {
+            match updates.try_recv() {
+                Ok(update) => batch.push(Ok(update)),
+                // A disconnection is noticed by the next wait
+                Err(_) => break,
+            }
+        }
+    }
+    batch
+}
+
+/// Reorder a batch of updates so the messages that change views and let nodes catch up
+/// (ViewChange, NewView, and Seal messages) are handled before the other peer messages around them
+///
+/// During a storm of normal-case messages, a view change shouldn't have to wait behind messages
+/// that it will make irrelevant. However, updates that aren't peer messages (such as block
+/// updates) act as barriers: messages are only reordered within each run of peer messages between
+/// them, so no message is moved past a block update. A sender's messages are never reordered among
+/// themselves either: any of a sender's messages that arrived before one of its priority messages
+/// are moved ahead along with it. All other updates keep their order.
+fn prioritize_updates(
+    batch: Vec<Result<Update, RecvTimeoutError>>,
+) -> Vec<Result<Update, RecvTimeoutError>> {
+    let mut ordered = Vec::with_capacity(batch.len());
+    let mut run = vec![];
+    for update in batch {
+        match update {
+            Ok(Update::PeerMessage(_, _)) => run.push(update),
+            barrier => {
+                ordered.extend(prioritize_messages(std::mem::replace(&mut run, vec![])));
+                ordered.push(barrier);
+            }
+        }
+    }
+    ordered.extend(prioritize_messages(run));
+    ordered
+}
+
+/// Reorder a run of peer messages so each sender's priority messages (and any of its messages
+/// before them) come first; see `prioritize_updates`
+fn prioritize_messages(
+    run: Vec<Result<Update, RecvTimeoutError>>,
+) -> Vec<Result<Update, RecvTimeoutError>> {
+    // The position of each sender's last priority message in the run
+    let mut last_priority = HashMap::new();
+    for (position, update) in run.iter().enumerate() {
+        if let Ok(Update::PeerMessage(message, _)) = update {
+            match PbftMessageType::from(message.header.message_type.as_str()) {
+                PbftMessageType::ViewChange | PbftMessageType::NewView | PbftMessageType::Seal => {
+                    last_priority.insert(message.header.signer_id.clone(), position);
+                }
+                _ => {}
+            }
+        }
+    }
+    if last_priority.is_empty() {
+        return run;
+    }
+
+    let (priority, normal): (Vec<_>, Vec<_>) =
+        run.into_iter()
+            .enumerate()
+            .partition(|(position, update)| match update {
+                Ok(Update::PeerMessage(message, _)) => last_priority
+                    .get(&message.header.signer_id)
+                    .map_or(false, |last| position <= last),
+                _ => false,
+            });
+    priority
+        .into_iter()
+        .chain(normal)
+        .map(|(_, update)| update)
+        .collect()
+}
+
+/// Handle an update (or the lack of one) from the validator, then publish a block and start a
+/// view change if it's time to; returns `false` once the engine should stop
+pub(crate) fn process_update(
+    node: &mut PbftNode,
+    incoming_message: Result<Update, RecvTimeoutError>,
+    state: &mut PbftState,
+    block_publishing_ticker: &mut Ticker,
+) -> bool {
+    match handle_update(node, incoming_message, state) {
+        Ok(again) => {
+            if !again {
+                return false;
+            }
+        }
+        Err(err) => log_any_error(Err(err)),
+    }
+
+    // If the block publishing delay has passed, attempt to publish a block
+    block_publishing_ticker.tick(|| log_any_error(node.try_publish(state)));
+
+    check_timeouts(node, state);
+
+    true
+}
+
+/// Handle a single update (or the lack of one) from the validator; returns `false` once the
+/// engine should stop
+pub(crate) fn handle_update(
     node: &mut PbftNode,
     incoming_message: Result<Update, RecvTimeoutError>,
     state: &mut PbftState,
 ) -> Result<bool, PbftError> {
     match incoming_message {
-        Ok(Update::BlockNew(block)) => node.on_block_new(block, state)?,
+        Ok(Update::BlockNew(block)) => node.on_block_new(block.into(), state)?,
         Ok(Update::BlockValid(block_id)) => node.on_block_valid(block_id, state)?,
         Ok(Update::BlockInvalid(block_id)) => node.on_block_invalid(block_id)?,
         Ok(Update::BlockCommit(block_id)) => node.on_block_commit(block_id, state)?,
@@ -192,16 +436,64 @@ fn handle_update(
     Ok(true)
 }
 
-#[cfg(test)]
-pub fn test_handle_update(
-    node: &mut PbftNode,
-    incoming_message: Result<Update, RecvTimeoutError>,
-    state: &mut PbftState,
-) -> Result<bool, PbftError> {
-    handle_update(node, incoming_message, state)
+/// The next time at which the engine has something to do even if no update arrives: the
+/// earliest of the running timeouts, the next block publishing attempt, and the next retry of a
+/// read from the validator
+///
+/// These are the only things `process_update` does without an update, so the engine can sleep
+/// until this time unless an update arrives first.
+pub(crate) fn next_deadline(
+    node: &PbftNode,
+    state: &PbftState,
+    block_publishing_ticker: &Ticker,
+) -> Instant {
+    let mut deadlines = vec![
+        state.idle_timeout.deadline(),
+        state.commit_timeout.deadline(),
+        node.next_member_read(),
+    ];
+    // The view change timeout is only checked while view changing
+    if let PbftMode::ViewChanging(_) = state.mode {
+        deadlines.push(state.view_change_timeout.deadline());
+    }
+
+    deadlines
+        .into_iter()
+        .flatten()
+        .fold(block_publishing_ticker.next_tick(), cmp::min)
 }
 
-fn log_any_error(res: Result<(), PbftError>) {
+/// Retry any reads from the validator that are due, then start a view change if any of the
+/// node's timeouts have expired
+pub(crate) fn check_timeouts(node: &mut PbftNode, state: &mut PbftState) {
+    node.retry_member_reads(state);
+
+    // If the idle timeout has expired, initiate a view change
+    if node.check_idle_timeout_expired(state) {
+        warn!("Idle timeout expired; proposing view change");
+        log_any_error(node.start_view_change(state, state.view + 1));
+    }
+
+    // If the commit timeout has expired, initiate a view change
+    if node.check_commit_timeout_expired(state) {
+        warn!("Commit timeout expired; proposing view change");
+        log_any_error(node.start_view_change(state, state.view + 1));
+    }
+
+    // Check the view change timeout if the node is view changing so we can start a new
+    // view change if we don't get a NewView in time
+    if let PbftMode::ViewChanging(v) = state.mode {
+        if node.check_view_change_timeout_expired(state) {
+            warn!(
+                "View change timeout expired; proposing view change for view {}",
+                v + 1
+            );
+            log_any_error(node.start_view_change(state, v + 1));
+        }
+    }
+}
+
+pub(crate) fn log_any_error(res: Result<(), PbftError>) {
     if let Err(e) = res {
         // Treat errors that result from other nodes' messages as warnings
         match e {
@@ -212,3 +504,273 @@ fn log_any_error(res: Result<(), PbftError>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::replay;
+    use crate::stringify_func_call;
+    use crate::testkit::{mock_block, mock_config, mock_node, MockService};
+    use crate::timing::ManualClock;
+    use rand::distributions::Alphanumeric;
+    use rand::{thread_rng, Rng};
+    use std::fs;
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Run an engine with the given config, clock, and service in its own thread, as node 1 of
+    /// the network
+    fn spawn_engine(
+        config: PbftConfig,
+        clock: &ManualClock,
+        service: &MockService,
+        receiver: Receiver<Update>,
+    ) -> thread::JoinHandle<Result<(), Error>> {
+        let clock = clock.clone();
+        let service = service.clone();
+        thread::spawn(move || {
+            PbftEngine::with_clock(config, Arc::new(clock)).start(
+                receiver,
+                Box::new(service),
+                StartupState {
+                    chain_head: mock_block(0).into(),
+                    peers: vec![],
+                    local_peer_info: PeerInfo { peer_id: vec![1] },
+                },
+            )
+        })
+    }
+
+    /// Wait up to 5 seconds (of real time) for the engine to broadcast a ViewChange
+    fn wait_for_view_change(service: &MockService) -> bool {
+        let view_change_started =
+            || service.was_called_with_args(stringify_func_call!("broadcast", "ViewChange"));
+        let mut waited = 0;
+        while !view_change_started() && waited < 5000 {
+            thread::sleep(Duration::from_millis(10));
+            waited += 10;
+        }
+        view_change_started()
+    }
+
+    /// The engine's timeouts must follow its clock rather than real time: a node that never
+    /// hears from the primary must not start a view change, however much real time passes, until
+    /// the idle timeout has passed on the clock; then it must start one.
+    #[test]
+    fn test_engine_timeouts_follow_clock() {
+        let clock = ManualClock::new();
+        let config = mock_config(4);
+        let idle_timeout = config.idle_timeout;
+        let service = MockService::new(&config);
+        let (sender, receiver) = channel();
+
+        let engine_thread = spawn_engine(config, &clock, &service, receiver);
+
+        // Let the engine start up and run its loop for a while in real time
+        thread::sleep(Duration::from_millis(200));
+        assert!(!service.was_called("broadcast"));
+
+        clock.advance(idle_timeout + Duration::from_millis(1));
+        assert!(wait_for_view_change(&service));
+
+        sender.send(Update::Shutdown).unwrap();
+        assert!(engine_thread.join().unwrap().is_ok());
+    }
+
+    /// The engine must sleep until the earliest of its running timers: the block publishing
+    /// ticker while no timeouts are running, then whichever running timeout expires first, with
+    /// the view change timeout only counting while the node is view changing.
+    #[test]
+    fn test_next_deadline() {
+        let clock = ManualClock::new();
+        let mut config = mock_config(4);
+        config.block_publishing_delay = Duration::from_secs(10);
+        config.idle_timeout = Duration::from_millis(1000);
+        config.commit_timeout = Duration::from_millis(500);
+        config.view_change_duration = Duration::from_millis(200);
+        let (mut node, mut state, _) = mock_node(&config, vec![1], mock_block(0));
+        state.set_clock(Arc::new(clock.clone()));
+        let ticker = Ticker::new(config.block_publishing_delay, Arc::new(clock.clone()));
+        let start = clock.now();
+
+        assert_eq!(
+            start + Duration::from_secs(10),
+            next_deadline(&node, &state, &ticker)
+        );
+
+        clock.advance(Duration::from_millis(100));
+        node.start_idle_timeout(&mut state);
+        assert_eq!(
+            start + Duration::from_millis(1100),
+            next_deadline(&node, &state, &ticker)
+        );
+
+        node.start_commit_timeout(&mut state);
+        assert_eq!(
+            start + Duration::from_millis(600),
+            next_deadline(&node, &state, &ticker)
+        );
+
+        state.view_change_timeout.start();
+        assert_eq!(
+            start + Duration::from_millis(600),
+            next_deadline(&node, &state, &ticker)
+        );
+        state.mode = PbftMode::ViewChanging(1);
+        assert_eq!(
+            start + Duration::from_millis(300),
+            next_deadline(&node, &state, &ticker)
+        );
+    }
+
+    /// All updates that have already arrived must be received as one batch, in order and up to
+    /// the maximum batch size; without any, the batch must only hold the timeout, and once the
+    /// validator disconnects, the disconnection must be received.
+    #[test]
+    fn test_receive_updates() {
+        let (sender, receiver) = channel();
+        let wait = Duration::from_millis(1);
+
+        match receive_updates(&receiver, wait, 10).as_slice() {
+            [Err(RecvTimeoutError::Timeout)] => {}
+            batch => panic!("Unexpected batch: {:?}", batch),
+        }
+
+        for num in 1..=3 {
+            sender
+                .send(Update::BlockNew(mock_block(num).into()))
+                .unwrap();
+        }
+        sender.send(Update::Shutdown).unwrap();
+        let batch = receive_updates(&receiver, wait, 3);
+        let block_nums: Vec<u64> = batch
+            .iter()
+            .map(|update| match update {
+                Ok(Update::BlockNew(block)) => block.block_num,
+                update => panic!("Unexpected update: {:?}", update),
+            })
+            .collect();
+        assert_eq!(vec![1, 2, 3], block_nums);
+
+        drop(sender);
+        match receive_updates(&receiver, wait, 10).as_slice() {
+            [Ok(Update::Shutdown)] => {}
+            batch => panic!("Unexpected batch: {:?}", batch),
+        }
+        match receive_updates(&receiver, wait, 10).as_slice() {
+            [Err(RecvTimeoutError::Disconnected)] => {}
+            batch => panic!("Unexpected batch: {:?}", batch),
+        }
+    }
+
+    /// ViewChange, NewView, and Seal messages must be moved ahead of the other peer messages
+    /// between the same block updates, taking any earlier messages from the same sender with
+    /// them; block updates must keep their positions, and everything else must keep its order.
+    #[test]
+    fn test_prioritize_updates() {
+        let message = |signer: u8, message_type: &str| {
+            let mut message = PeerMessage::default();
+            message.header.signer_id = vec![signer];
+            message.header.message_type = message_type.into();
+            Ok(Update::PeerMessage(message, vec![signer]))
+        };
+        let describe = |update: &Result<Update, RecvTimeoutError>| match update {
+            Ok(Update::PeerMessage(message, _)) => format!(
+                "{}:{}",
+                message.header.signer_id[0], message.header.message_type
+            ),
+            Ok(Update::BlockNew(block)) => format!("BlockNew:{}", block.block_num),
+            Ok(Update::BlockCommit(block_id)) => format!("BlockCommit:{}", block_id[0]),
+            update => panic!("Unexpected update: {:?}", update),
+        };
+
+        let batch = vec![
+            message(1, "Prepare"),
+            message(2, "Commit"),
+            Ok(Update::BlockNew(mock_block(1).into())),
+            message(2, "Prepare"),
+            message(1, "ViewChange"),
+            Ok(Update::BlockCommit(vec![1])),
+            message(3, "Prepare"),
+            message(1, "Commit"),
+            message(3, "NewView"),
+            message(0, "Seal"),
+        ];
+        let ordered: Vec<String> = prioritize_updates(batch).iter().map(describe).collect();
+        assert_eq!(
+            vec![
+                "1:Prepare",
+                "2:Commit",
+                "BlockNew:1",
+                "1:ViewChange",
+                "2:Prepare",
+                "BlockCommit:1",
+                "3:Prepare",
+                "3:NewView",
+                "0:Seal",
+                "1:Commit",
+            ],
+            ordered
+        );
+
+        // Without any priority messages, the batch is left as it is
+        let batch = vec![message(2, "Commit"), message(1, "Prepare")];
+        let ordered: Vec<String> = prioritize_updates(batch).iter().map(describe).collect();
+        assert_eq!(vec!["2:Commit", "1:Prepare"], ordered);
+    }
+
+    /// A recording of the engine must replay to the same state. The recorded node is asked to
+    /// validate a block and then, once the idle timeout has passed on its clock, starts a view
+    /// change; the replayed node must make the same calls in response to the same updates and
+    /// end up view changing too. If the replayed node makes a call that isn't in the recording,
+    /// the replay must report that it diverged.
+    #[test]
+    fn test_record_and_replay() {
+        let random_path = || {
+            String::from("/tmp/")
+                + &thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(10)
+                    .collect::<String>()
+        };
+        let recording_path = random_path();
+
+        let clock = ManualClock::new();
+        let mut config = mock_config(4);
+        config.recording_path = Some(recording_path.clone());
+        let idle_timeout = config.idle_timeout;
+        let service = MockService::new(&config);
+        let (sender, receiver) = channel();
+
+        let engine_thread = spawn_engine(config, &clock, &service, receiver);
+
+        sender.send(Update::BlockNew(mock_block(1).into())).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        clock.advance(idle_timeout + Duration::from_millis(1));
+        assert!(wait_for_view_change(&service));
+
+        sender.send(Update::Shutdown).unwrap();
+        assert!(engine_thread.join().unwrap().is_ok());
+
+        let replayed = replay(&recording_path).unwrap();
+        assert!(replayed.calls > 0);
+        assert_eq!(PbftMode::ViewChanging(1), replayed.state.mode);
+        assert_eq!(0, replayed.state.view);
+
+        // Without the recorded broadcasts, the replayed node's ViewChange isn't expected
+        let recording = fs::read_to_string(&recording_path).unwrap();
+        let tampered: Vec<&str> = recording
+            .lines()
+            .filter(|line| !line.contains(r#""method":"broadcast""#))
+            .collect();
+        fs::write(&recording_path, tampered.join("\n")).unwrap();
+        let err = replay(&recording_path)
+            .err()
+            .expect("Replay didn't diverge");
+        assert!(err.contains("diverged"), err);
+
+        fs::remove_file(recording_path).unwrap();
+    }
+}
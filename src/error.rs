@@ -21,7 +21,8 @@ use std::error::Error;
 use std::fmt;
 
 use protobuf::error::ProtobufError;
-use sawtooth_sdk::consensus::engine::Error as ServError;
+
+use crate::host::HostError;
 
 /// Errors that might occur in a PbftNode
 #[derive(Debug)]
@@ -30,8 +31,8 @@ pub enum PbftError {
     /// `ProtobufError`)
     SerializationError(String, ProtobufError),
 
-    /// An error occurred while making a call to the consensus service (description, `ServError`)
-    ServiceError(String, ServError),
+    /// An error occurred while making a call to the host (description, `HostError`)
+    ServiceError(String, HostError),
 
     /// An error occurred while verifying a cryptographic signature
     SigningError(String),
@@ -42,6 +43,9 @@ pub enum PbftError {
     /// An invalid message was received
     InvalidMessage(String),
 
+    /// The members of a block couldn't be read from the host yet; the read is retried later
+    MembersUnavailable(String),
+
     /// Internal PBFT error (description)
     InternalError(String),
 }
@@ -55,6 +59,7 @@ impl Error for PbftError {
             SigningError(_) => "SigningError",
             FaultyPrimary(_) => "FaultyPrimary",
             InvalidMessage(_) => "InvalidMessage",
+            MembersUnavailable(_) => "MembersUnavailable",
             InternalError(_) => "InternalError",
         }
     }
@@ -73,6 +78,7 @@ impl fmt::Display for PbftError {
                 description
             ),
             PbftError::InvalidMessage(description) => write!(f, "{}", description),
+            PbftError::MembersUnavailable(description) => write!(f, "{}", description),
             PbftError::InternalError(description) => write!(f, "{}", description),
         }
     }
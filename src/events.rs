@@ -0,0 +1,392 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Stream of consensus events for external subscribers
+//!
+//! Events are serialized as JSON, one event per line, and written to the selected sink.
+
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+/// How many bytes of events may wait to be written to a subscriber that isn't keeping up before
+/// it is disconnected
+const MAX_PENDING_BYTES: usize = 64 * 1024;
+
+/// Notable changes in the consensus process that external services may want to react to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event")]
+pub enum ConsensusEvent {
+    /// A block was committed and is now final. `seal` is the hex-encoded `PbftSeal` proving the
+    /// commit, if this node had the votes required to build one.
+    BlockFinalized {
+        block_id: String,
+        block_num: u64,
+        view: u64,
+        seal: Option<String>,
+    },
+    /// This node started changing from `from_view` to `to_view`
+    ViewChangeStarted { from_view: u64, to_view: u64 },
+    /// This node accepted a NewView and moved to `view`
+    ViewChangeCompleted { view: u64, primary: String },
+    /// This node fell behind and started committing blocks using seals from the network
+    CatchupStarted { seq_num: u64, block_id: String },
+    /// This node committed the last block it was catching up on
+    CatchupFinished { seq_num: u64, block_id: String },
+    /// The primary for `view` sent messages that prove it is faulty
+    FaultyPrimaryDetected {
+        view: u64,
+        primary: String,
+        reason: String,
+    },
+}
+
+/// A consensus event along with the time it was published
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    /// Milliseconds since the Unix epoch
+    timestamp: u64,
+    #[serde(flatten)]
+    event: &'a ConsensusEvent,
+}
+
+/// Destination for consensus events
+pub trait EventSink {
+    fn publish(&mut self, event: &ConsensusEvent) -> Result<(), String>;
+}
+
+/// Serialize an event as a single line of JSON (including the trailing newline)
+fn to_json_line(event: &ConsensusEvent) -> Result<Vec<u8>, String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() * 1000 + u64::from(since_epoch.subsec_millis()))
+        .unwrap_or(0);
+
+    let mut line = serde_json::to_vec(&EventRecord { timestamp, event })
+        .map_err(|err| format!("Couldn't serialize event: {}", err))?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Appends events to a file as JSON lines
+pub struct JsonLinesFileSink {
+    file: File,
+}
+
+impl JsonLinesFileSink {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| format!("Couldn't open event file: {}", err))?;
+
+        Ok(Self { file })
+    }
+}
+
+impl EventSink for JsonLinesFileSink {
+    fn publish(&mut self, event: &ConsensusEvent) -> Result<(), String> {
+        let line = to_json_line(event)?;
+        self.file
+            .write_all(&line)
+            .and_then(|_| self.file.flush())
+            .map_err(|err| format!("Couldn't write event to file: {}", err))
+    }
+}
+
+/// Streams events as JSON lines to every client connected to a local Unix socket
+///
+/// Clients only receive events published after they connect. The engine never waits on a client:
+/// events that a client's socket won't take yet are kept and written once it will, and a client
+/// that falls too far behind is disconnected. Clients only ever receive whole lines.
+pub struct UnixSocketSink {
+    listener: UnixListener,
+    subscribers: Vec<Subscriber>,
+}
+
+/// A client of the event socket, along with the events that haven't been written to it yet
+struct Subscriber {
+    stream: UnixStream,
+    /// Whole lines waiting to be written; the first may already be partly written
+    pending: Vec<u8>,
+    /// Whether part of the first line in `pending` has already been written
+    mid_line: bool,
+    /// Whether the subscriber has fallen too far behind; it is disconnected once the line it
+    /// is in the middle of has been written
+    lagging: bool,
+}
+
+impl Subscriber {
+    fn new(stream: UnixStream) -> Self {
+        Subscriber {
+            stream,
+            pending: Vec::new(),
+            mid_line: false,
+            lagging: false,
+        }
+    }
+
+    /// Queue a line to be written to the subscriber
+    fn queue(&mut self, line: &[u8]) {
+        if self.lagging {
+            return;
+        }
+        if self.pending.len() + line.len() > MAX_PENDING_BYTES {
+            debug!("Event subscriber can't keep up; disconnecting it");
+            self.lagging = true;
+            // Only finish the line the subscriber is in the middle of
+            let end = if self.mid_line {
+                self.pending
+                    .iter()
+                    .position(|byte| *byte == b'\n')
+                    .map_or(0, |newline| newline + 1)
+            } else {
+                0
+            };
+            self.pending.truncate(end);
+        } else {
+            self.pending.extend_from_slice(line);
+        }
+    }
+
+    /// Write as much of the pending lines as the socket takes without blocking; returns `false`
+    /// once the subscriber should be disconnected
+    fn flush(&mut self) -> bool {
+        while !self.pending.is_empty() {
+            match self.stream.write(&self.pending) {
+                Ok(0) => return false,
+                Ok(written) => {
+                    self.mid_line = self.pending[written - 1] != b'\n';
+                    self.pending.drain(..written);
+                }
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return true,
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => {
+                    debug!("Dropping event subscriber due to error: {}", err);
+                    return false;
+                }
+            }
+        }
+        !self.lagging
+    }
+}
+
+impl UnixSocketSink {
+    pub fn bind<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        // Remove a socket left behind by a previous run, since it would prevent binding
+        if path.as_ref().exists() {
+            remove_file(&path).map_err(|err| format!("Couldn't remove old socket: {}", err))?;
+        }
+
+        let listener = UnixListener::bind(&path)
+            .map_err(|err| format!("Couldn't bind event socket: {}", err))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| format!("Couldn't configure event socket: {}", err))?;
+
+        Ok(Self {
+            listener,
+            subscribers: Vec::new(),
+        })
+    }
+
+    /// Add any clients that have connected since the last event was published
+    fn accept_subscribers(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match stream.set_nonblocking(true) {
+                    Ok(_) => self.subscribers.push(Subscriber::new(stream)),
+                    Err(err) => warn!("Couldn't configure event subscriber: {}", err),
+                },
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("Couldn't accept event subscriber: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl EventSink for UnixSocketSink {
+    fn publish(&mut self, event: &ConsensusEvent) -> Result<(), String> {
+        self.accept_subscribers();
+
+        let line = to_json_line(event)?;
+        self.subscribers = self
+            .subscribers
+            .drain(..)
+            .filter_map(|mut subscriber| {
+                subscriber.queue(&line);
+                if subscriber.flush() {
+                    Some(subscriber)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        Ok(())
+    }
+}
+
+/// Given a location string, returns the appropriate event sink
+///
+/// Accepts `"file+/path/to/file"` or `"unix+/path/to/socket"` as location values
+pub fn get_event_sink(location: &str) -> Result<Box<dyn EventSink>, String> {
+    let split = location.splitn(2, '+').collect::<Vec<_>>();
+
+    if split.len() != 2 {
+        return Err(format!("Invalid location: {}", location));
+    }
+
+    match split[0] {
+        "file" => Ok(Box::new(JsonLinesFileSink::from_path(split[1])?)),
+        "unix" => Ok(Box::new(UnixSocketSink::bind(split[1])?)),
+        _ => Err(format!("Unknown event sink type: {}", location)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read_to_string;
+    use std::io::{BufRead, BufReader, Read};
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    /// Events written to a file must be separate lines of JSON, each tagged with the event type
+    /// and a timestamp, so that subscribers can tail the file and parse it line-by-line.
+    #[test]
+    fn test_file_sink() {
+        let dir = tempdir().unwrap();
+        let filename = dir.path().join("events");
+        let mut sink = get_event_sink(&format!("file+{}", filename.display())).unwrap();
+
+        sink.publish(&ConsensusEvent::ViewChangeStarted {
+            from_view: 0,
+            to_view: 1,
+        })
+        .unwrap();
+        sink.publish(&ConsensusEvent::ViewChangeCompleted {
+            view: 1,
+            primary: "01".into(),
+        })
+        .unwrap();
+
+        let contents = read_to_string(&filename).unwrap();
+        let lines = contents
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(2, lines.len());
+        assert_eq!("ViewChangeStarted", lines[0]["event"]);
+        assert_eq!(1, lines[0]["to_view"]);
+        assert!(lines[0]["timestamp"].is_u64());
+        assert_eq!("ViewChangeCompleted", lines[1]["event"]);
+        assert_eq!("01", lines[1]["primary"]);
+    }
+
+    /// Clients connected to the event socket must receive every event published after they
+    /// connect, and a client disconnecting must not cause publishing to fail.
+    #[test]
+    fn test_unix_socket_sink() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("events.sock");
+        let mut sink = get_event_sink(&format!("unix+{}", path.display())).unwrap();
+
+        let client = UnixStream::connect(&path).unwrap();
+        sink.publish(&ConsensusEvent::CatchupStarted {
+            seq_num: 5,
+            block_id: "05".into(),
+        })
+        .unwrap();
+
+        let mut line = String::new();
+        BufReader::new(&client).read_line(&mut line).unwrap();
+        let value = serde_json::from_str::<serde_json::Value>(&line).unwrap();
+        assert_eq!("CatchupStarted", value["event"]);
+        assert_eq!(5, value["seq_num"]);
+
+        drop(client);
+        assert!(sink
+            .publish(&ConsensusEvent::CatchupFinished {
+                seq_num: 5,
+                block_id: "05".into(),
+            })
+            .is_ok());
+    }
+
+    /// A client that doesn't read the events must be disconnected once too many are waiting for
+    /// it, without the engine waiting on it; everything it was sent must be whole lines.
+    #[test]
+    fn test_unix_socket_sink_slow_subscriber() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("events.sock");
+        let mut sink = UnixSocketSink::bind(&path).unwrap();
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        let event = ConsensusEvent::FaultyPrimaryDetected {
+            view: 0,
+            primary: "00".into(),
+            reason: "x".repeat(100),
+        };
+        sink.publish(&event).unwrap();
+        assert_eq!(1, sink.subscribers.len());
+
+        // Publish until the client has fallen too far behind
+        while sink
+            .subscribers
+            .first()
+            .map_or(false, |subscriber| !subscriber.lagging)
+        {
+            sink.publish(&event).unwrap();
+        }
+
+        // Once the client reads again, it gets the rest of the line it was in the middle of and
+        // is then disconnected
+        let reader = thread::spawn(move || {
+            let mut received = String::new();
+            client.read_to_string(&mut received).unwrap();
+            received
+        });
+        while !sink.subscribers.is_empty() {
+            sink.publish(&event).unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let received = reader.join().unwrap();
+        assert!(received.ends_with('\n'));
+        for line in received.lines() {
+            let value = serde_json::from_str::<serde_json::Value>(line).unwrap();
+            assert_eq!("FaultyPrimaryDetected", value["event"]);
+        }
+    }
+
+    /// Only the supported sink types can be selected
+    #[test]
+    fn test_get_event_sink() {
+        assert!(get_event_sink("memory").is_err());
+        assert!(get_event_sink("tcp+localhost:1234").is_err());
+    }
+}
@@ -0,0 +1,394 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! The interface between the PBFT consensus core and the system it runs on
+//!
+//! A `PbftNode` doesn't talk to a Sawtooth validator directly; everything it needs from the
+//! outside world (sending messages to other members, building, checking, and committing blocks,
+//! reading on-chain settings, and checking the signatures of votes) goes through a
+//! `ConsensusHost`. `SawtoothHost` adapts a Sawtooth consensus `Service` to this interface, so
+//! the same consensus logic can be driven by other ledgers and by tests.
+//!
+//! Blocks, block IDs, peer IDs, and host errors are represented with this module's types rather
+//! than those of the Sawtooth SDK; `SawtoothHost` converts between the two, and it is the only part
+//! of the host interface that knows how a Sawtooth validator formats and signs its messages.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use hex;
+use rayon::prelude::*;
+use sawtooth_sdk::consensus::engine as sdk;
+use sawtooth_sdk::consensus::service::Service;
+use sawtooth_sdk::messages::consensus::ConsensusPeerMessageHeader;
+
+use crate::error::PbftError;
+use crate::hash::verify_sha512;
+use crate::protos::pbft_message::{PbftMessage, PbftSignedVote};
+use crate::signature::{
+    CachingVerifier, SignatureScheme, VerificationCacheStats, VoteVerifier,
+    DEFAULT_VERIFICATION_CACHE_SIZE,
+};
+
+/// The ID of a block
+pub type BlockId = Vec<u8>;
+
+/// The ID of a member of the network (the public key it signs its messages with)
+pub type PeerId = Vec<u8>;
+
+/// A block, as provided by the host
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Block {
+    pub block_id: BlockId,
+    pub previous_id: BlockId,
+    /// The member that published the block
+    pub signer_id: PeerId,
+    pub block_num: u64,
+    /// The consensus data the block was finalized with
+    pub payload: Vec<u8>,
+    /// The host's summary of the block's contents
+    pub summary: Vec<u8>,
+}
+
+/// Errors that a host may return from its calls
+#[derive(Debug)]
+pub enum HostError {
+    /// The block that is being built can't be finalized yet
+    BlockNotReady,
+
+    /// The host couldn't complete the call (description)
+    RequestFailed(String),
+}
+
+impl Error for HostError {
+    fn description(&self) -> &str {
+        match self {
+            HostError::BlockNotReady => "BlockNotReady",
+            HostError::RequestFailed(_) => "RequestFailed",
+        }
+    }
+}
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HostError::BlockNotReady => write!(f, "Block is not ready to be finalized"),
+            HostError::RequestFailed(description) => write!(f, "{}", description),
+        }
+    }
+}
+
+/// Everything the PBFT consensus core needs from the system it runs on
+pub trait ConsensusHost {
+    /// Send a consensus message to a single member
+    fn send_to(
+        &mut self,
+        peer: &PeerId,
+        message_type: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), HostError>;
+
+    /// Send a consensus message to all other members
+    fn broadcast(&mut self, message_type: &str, payload: Vec<u8>) -> Result<(), HostError>;
+
+    /// Start building a block on top of the given block (or the chain head if `None`)
+    fn initialize_block(&mut self, previous_id: Option<BlockId>) -> Result<(), HostError>;
+
+    /// Check whether the block that is being built can be finalized; returns the block's
+    /// summary if it can
+    fn summarize_block(&mut self) -> Result<Vec<u8>, HostError>;
+
+    /// Finish building the block, with the given consensus data, and publish it
+    fn finalize_block(&mut self, data: Vec<u8>) -> Result<BlockId, HostError>;
+
+    /// Stop building the block that is being built
+    fn cancel_block(&mut self) -> Result<(), HostError>;
+
+    /// Start validating the given blocks; the host reports back whether each is valid
+    fn check_blocks(&mut self, priority: Vec<BlockId>) -> Result<(), HostError>;
+
+    /// Commit the block, making it the new chain head
+    fn commit_block(&mut self, block_id: BlockId) -> Result<(), HostError>;
+
+    /// Give up on a block that won't be committed, without marking it as invalid
+    fn ignore_block(&mut self, block_id: BlockId) -> Result<(), HostError>;
+
+    /// Mark the block as invalid
+    fn fail_block(&mut self, block_id: BlockId) -> Result<(), HostError>;
+
+    /// Get the blocks with the given IDs; blocks that the host doesn't have are left out
+    fn get_blocks(&mut self, block_ids: Vec<BlockId>)
+        -> Result<HashMap<BlockId, Block>, HostError>;
+
+    /// Read the given on-chain settings as of the given block; settings that aren't set are left
+    /// out
+    fn get_settings(
+        &mut self,
+        block_id: BlockId,
+        keys: Vec<String>,
+    ) -> Result<HashMap<String, String>, HostError>;
+
+    /// Check that the vote was signed by the member its header names and that the header covers
+    /// the vote's message; returns the ID of the vote's signer
+    fn authenticate_vote(&self, vote: &PbftSignedVote) -> Result<PeerId, PbftError>;
+
+    /// Authenticate each of the votes like `authenticate_vote` does, returning the results in the
+    /// same order as the votes and stopping after the first vote that fails; used for the votes
+    /// of seals and NewView messages, so hosts can check their signatures in parallel
+    fn authenticate_votes(&self, votes: &[PbftSignedVote]) -> Vec<Result<PeerId, PbftError>> {
+        let mut results = Vec::with_capacity(votes.len());
+        for vote in votes {
+            let result = self.authenticate_vote(vote);
+            let failed = result.is_err();
+            results.push(result);
+            if failed {
+                break;
+            }
+        }
+        results
+    }
+
+    /// Get the performance of the host's cache of verified vote signatures, if it has one
+    fn verification_cache_stats(&self) -> Option<VerificationCacheStats> {
+        None
+    }
+}
+
+/// A `ConsensusHost` backed by a Sawtooth validator's consensus `Service`
+///
+/// Votes are authenticated the way the validator signs peer messages (see
+/// `SawtoothHost::authenticate_vote_with`), with the signature scheme of the validators' keys.
+/// Valid signatures are cached, since the same votes are checked again as they're passed along in
+/// NewView messages and seals, and the votes of a seal or NewView are checked in parallel.
+pub struct SawtoothHost {
+    service: Box<dyn Service>,
+    verifier: CachingVerifier,
+}
+
+impl SawtoothHost {
+    /// Create a host for validators that sign with secp256k1 keys
+    pub fn new(service: Box<dyn Service>) -> Self {
+        Self::with_signature_scheme(service, SignatureScheme::default())
+    }
+
+    /// Create a host for validators that sign with the given signature scheme
+    pub fn with_signature_scheme(service: Box<dyn Service>, scheme: SignatureScheme) -> Self {
+        Self::with_verifier(service, scheme.verifier(), DEFAULT_VERIFICATION_CACHE_SIZE)
+    }
+
+    /// Create a host that checks vote signatures with the given verifier, caching up to
+    /// `cache_size` valid signatures (none if `cache_size` is 0)
+    pub fn with_verifier(
+        service: Box<dyn Service>,
+        verifier: Box<dyn VoteVerifier>,
+        cache_size: usize,
+    ) -> Self {
+        SawtoothHost {
+            service,
+            verifier: CachingVerifier::new(verifier, cache_size),
+        }
+    }
+
+    /// Check that a vote was signed the way a Sawtooth validator signs peer messages: the vote's
+    /// header is a `ConsensusPeerMessageHeader` signed by its signer with the given scheme, and it
+    /// contains the SHA-512 hash of the vote's message
+    ///
+    /// The header's signer is compared with the signer of the vote's message before the signature
+    /// is checked, since checking the signature is by far the most expensive part.
+    pub fn authenticate_vote_with(
+        vote: &PbftSignedVote,
+        verifier: &dyn VoteVerifier,
+    ) -> Result<PeerId, PbftError> {
+        let header: ConsensusPeerMessageHeader =
+            protobuf::parse_from_bytes(&vote.get_header_bytes()).map_err(|err| {
+                PbftError::SerializationError("Error parsing header from vote".into(), err)
+            })?;
+        let pbft_message: PbftMessage = protobuf::parse_from_bytes(&vote.get_message_bytes())
+            .map_err(|err| {
+                PbftError::SerializationError("Error parsing PbftMessage from vote".into(), err)
+            })?;
+
+        if header.signer_id != pbft_message.get_info().get_signer_id() {
+            return Err(PbftError::InvalidMessage(format!(
+                "Received a vote where PbftMessage's signer ID ({:?}) and PeerMessage's signer ID \
+                 ({:?}) don't match",
+                pbft_message.get_info().get_signer_id(),
+                header.signer_id
+            )));
+        }
+
+        verifier
+            .verify(
+                &header.signer_id,
+                vote.get_header_bytes(),
+                vote.get_header_signature(),
+            )
+            .map_err(|err| {
+                PbftError::SigningError(format!(
+                    "Vote ({}) from {} failed signature verification: {}",
+                    vote,
+                    hex::encode(&header.signer_id),
+                    err
+                ))
+            })?;
+
+        verify_sha512(vote.get_message_bytes(), header.get_content_sha512())?;
+
+        Ok(header.signer_id.to_vec())
+    }
+
+    /// Authenticate each of the votes with `authenticate_vote_with`, stopping after the first
+    /// vote that fails
+    ///
+    /// The votes are checked in parallel, in chunks of one vote per worker thread, so at most one
+    /// chunk of signatures is checked after the first invalid one.
+    pub fn authenticate_votes_with(
+        votes: &[PbftSignedVote],
+        verifier: &dyn VoteVerifier,
+    ) -> Vec<Result<PeerId, PbftError>> {
+        let mut results = Vec::with_capacity(votes.len());
+        for chunk in votes.chunks(rayon::current_num_threads().max(1)) {
+            let chunk_results = chunk
+                .par_iter()
+                .map(|vote| Self::authenticate_vote_with(vote, verifier))
+                .collect::<Vec<_>>();
+            match chunk_results.iter().position(Result::is_err) {
+                Some(failed) => {
+                    results.extend(chunk_results.into_iter().take(failed + 1));
+                    break;
+                }
+                None => results.extend(chunk_results),
+            }
+        }
+        results
+    }
+}
+
+impl From<sdk::Block> for Block {
+    fn from(block: sdk::Block) -> Self {
+        Block {
+            block_id: block.block_id,
+            previous_id: block.previous_id,
+            signer_id: block.signer_id,
+            block_num: block.block_num,
+            payload: block.payload,
+            summary: block.summary,
+        }
+    }
+}
+
+impl From<Block> for sdk::Block {
+    fn from(block: Block) -> Self {
+        sdk::Block {
+            block_id: block.block_id,
+            previous_id: block.previous_id,
+            signer_id: block.signer_id,
+            block_num: block.block_num,
+            payload: block.payload,
+            summary: block.summary,
+        }
+    }
+}
+
+impl From<sdk::Error> for HostError {
+    fn from(err: sdk::Error) -> Self {
+        match err {
+            sdk::Error::BlockNotReady => HostError::BlockNotReady,
+            err => HostError::RequestFailed(err.to_string()),
+        }
+    }
+}
+
+impl ConsensusHost for SawtoothHost {
+    fn send_to(
+        &mut self,
+        peer: &PeerId,
+        message_type: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), HostError> {
+        Ok(self.service.send_to(peer, message_type, payload)?)
+    }
+
+    fn broadcast(&mut self, message_type: &str, payload: Vec<u8>) -> Result<(), HostError> {
+        Ok(self.service.broadcast(message_type, payload)?)
+    }
+
+    fn initialize_block(&mut self, previous_id: Option<BlockId>) -> Result<(), HostError> {
+        Ok(self.service.initialize_block(previous_id)?)
+    }
+
+    fn summarize_block(&mut self) -> Result<Vec<u8>, HostError> {
+        Ok(self.service.summarize_block()?)
+    }
+
+    fn finalize_block(&mut self, data: Vec<u8>) -> Result<BlockId, HostError> {
+        Ok(self.service.finalize_block(data)?)
+    }
+
+    fn cancel_block(&mut self) -> Result<(), HostError> {
+        Ok(self.service.cancel_block()?)
+    }
+
+    fn check_blocks(&mut self, priority: Vec<BlockId>) -> Result<(), HostError> {
+        Ok(self.service.check_blocks(priority)?)
+    }
+
+    fn commit_block(&mut self, block_id: BlockId) -> Result<(), HostError> {
+        Ok(self.service.commit_block(block_id)?)
+    }
+
+    fn ignore_block(&mut self, block_id: BlockId) -> Result<(), HostError> {
+        Ok(self.service.ignore_block(block_id)?)
+    }
+
+    fn fail_block(&mut self, block_id: BlockId) -> Result<(), HostError> {
+        Ok(self.service.fail_block(block_id)?)
+    }
+
+    fn get_blocks(
+        &mut self,
+        block_ids: Vec<BlockId>,
+    ) -> Result<HashMap<BlockId, Block>, HostError> {
+        Ok(self
+            .service
+            .get_blocks(block_ids)?
+            .into_iter()
+            .map(|(block_id, block)| (block_id, block.into()))
+            .collect())
+    }
+
+    fn get_settings(
+        &mut self,
+        block_id: BlockId,
+        keys: Vec<String>,
+    ) -> Result<HashMap<String, String>, HostError> {
+        Ok(self.service.get_settings(block_id, keys)?)
+    }
+
+    fn authenticate_vote(&self, vote: &PbftSignedVote) -> Result<PeerId, PbftError> {
+        Self::authenticate_vote_with(vote, &self.verifier)
+    }
+
+    fn authenticate_votes(&self, votes: &[PbftSignedVote]) -> Vec<Result<PeerId, PbftError>> {
+        Self::authenticate_votes_with(votes, &self.verifier)
+    }
+
+    fn verification_cache_stats(&self) -> Option<VerificationCacheStats> {
+        Some(self.verifier.stats())
+    }
+}
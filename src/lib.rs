@@ -0,0 +1,99 @@
+/*
+ * Copyright 2018 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Implementation of the [PBFT consensus
+//! algorithm](https://www.usenix.org/legacy/events/osdi99/full_papers/castro/castro_html/castro.html),
+//! modified for use with Hyperledger Sawtooth.
+//!
+//! The `pbft-engine` binary is a thin command-line wrapper around this library: it parses the
+//! configuration, sets up logging, and runs a `PbftEngine` against the validator. Other tools can
+//! use the same library to verify consensus seals or to run PBFT on a different host.
+//!
+//! # Stability
+//!
+//! The library follows semantic versioning for the following modules, and for the types that are
+//! re-exported at the crate root:
+//!
+//! + `config`: engine configuration and on-chain settings
+//! + `host`: the `ConsensusHost` interface to the system a node runs on, and its Sawtooth adapter
+//! + `verification`: verification of signed votes and seals
+//!
+//! along with the modules whose types those APIs use:
+//!
+//! + `error`: the `PbftError` type
+//! + `protos`: the protobuf messages that PBFT sends and stores on chain
+//! + `signature`: the signature schemes that votes can be signed with
+//!
+//! Breaking changes to any of these only happen in a new major version; additions may happen in
+//! any minor version.
+//!
+//! The other modules are the engine's internals. Some of them are public so that the
+//! `pbft-engine` binary, the benchmarks, and the fuzz targets can reach them, but they are hidden
+//! from the documentation and may change in any release. The `simulation`, `byzantine` (part of
+//! `simulation`), and `testkit` modules, which are only compiled with the cargo features of the
+//! same names, are tools for testing and are not covered by semantic versioning either.
+
+#![allow(unknown_lints)]
+
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+
+mod admin;
+#[doc(hidden)]
+pub mod audit;
+pub mod config;
+#[doc(hidden)]
+pub mod engine;
+pub mod error;
+#[doc(hidden)]
+pub mod events;
+#[doc(hidden)]
+pub mod hash;
+pub mod host;
+#[doc(hidden)]
+pub mod membership;
+mod message_extensions;
+#[doc(hidden)]
+pub mod message_log;
+#[doc(hidden)]
+pub mod message_type;
+#[doc(hidden)]
+pub mod node;
+#[doc(hidden)]
+pub mod participation;
+pub mod protos;
+#[doc(hidden)]
+pub mod recording;
+pub mod signature;
+#[cfg(any(test, feature = "simulation"))]
+pub mod simulation;
+#[doc(hidden)]
+pub mod state;
+#[doc(hidden)]
+pub mod storage;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+#[doc(hidden)]
+pub mod timing;
+pub mod verification;
+
+pub use crate::config::PbftConfig;
+pub use crate::engine::PbftEngine;
+pub use crate::error::PbftError;
+pub use crate::host::{ConsensusHost, SawtoothHost};
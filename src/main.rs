@@ -15,40 +15,25 @@
  * -----------------------------------------------------------------------------
  */
 
-//! Implementation of the [PBFT consensus
-//! algorithm](https://www.usenix.org/legacy/events/osdi99/full_papers/castro/castro_html/castro.html),
-//! modified for use with Hyperledger Sawtooth.
+//! The `pbft-engine` binary, which runs PBFT for a Sawtooth validator
 
 #![allow(unknown_lints)]
 
 #[macro_use]
 extern crate log;
-#[macro_use]
-extern crate serde_derive;
 
+use std::fs;
 use std::process;
 use std::time::Duration;
 
-use clap::{clap_app, crate_version};
+use clap::{clap_app, crate_version, App, Arg, ArgGroup, ArgMatches, SubCommand};
 use log4rs::append::console::ConsoleAppender;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use sawtooth_sdk::consensus::zmq_driver::ZmqDriver;
 
-pub mod config;
-pub mod engine;
-pub mod error;
-pub mod hash;
-pub mod message_extensions;
-pub mod message_log;
-pub mod message_type;
-pub mod node;
-mod protos;
-pub mod state;
-pub mod storage;
-#[cfg(test)]
-pub mod test_helpers;
-pub mod timing;
+use sawtooth_pbft_engine::signature::SignatureScheme;
+use sawtooth_pbft_engine::{config, engine, recording, verification};
 
 fn main() {
     let args = parse_args();
@@ -87,6 +72,10 @@ fn main() {
 
     info!("Sawtooth PBFT Engine ({})", env!("CARGO_PKG_VERSION"));
 
+    if let Some(path) = args.replay {
+        run_replay(&path);
+    }
+
     let mut pbft_config = config::PbftConfig::default();
     if let Some(base) = args.exponential_retry_base {
         pbft_config.exponential_retry_base = Duration::from_millis(base);
@@ -103,6 +92,33 @@ fn main() {
     if let Some(max_log_size) = args.max_log_size {
         pbft_config.max_log_size = max_log_size;
     }
+    if let Some(window) = args.participation_window {
+        pbft_config.participation_window = window;
+    }
+    if let Some(threshold) = args.participation_threshold {
+        pbft_config.participation_threshold = threshold;
+    }
+    pbft_config.event_stream_location = args.event_stream_location;
+    pbft_config.admin_socket_path = args.admin_socket_path;
+    pbft_config.recording_path = args.recording_path;
+    if let Some(scheme) = args.signature_scheme {
+        pbft_config.signature_scheme = scheme;
+    }
+    if let Some(size) = args.verification_cache_size {
+        pbft_config.verification_cache_size = size;
+    }
+    if let Some(window) = args.message_seq_window {
+        pbft_config.message_seq_window = window;
+    }
+    if let Some(window) = args.message_view_window {
+        pbft_config.message_view_window = window;
+    }
+    if let Some(quota) = args.max_messages_per_signer {
+        pbft_config.max_messages_per_signer = quota;
+    }
+    if let Some(depth) = args.max_finality_proof_depth {
+        pbft_config.max_finality_proof_depth = depth;
+    }
 
     let pbft_engine = engine::PbftEngine::new(pbft_config);
 
@@ -147,13 +163,48 @@ fn parse_args() -> PbftCliArgs {
         (@arg exponential_retry_max: -m --("exponential-retry-max") +takes_value
          "max timeout for exponential backoff (default 60000 ms)")
         (@arg update_recv_timeout: -u --("update-recv-timeout") +takes_value
-         "timeout for receiving an update from the validator (default 10 ms)")
+         "how often to check for admin requests while waiting for updates (default 10 ms)")
         (@arg max_log_size: -l --("max-log-size") +takes_value
          "how large the PBFT log is allowed to get before being pruned (default 10000 messages)")
         (@arg storage_location: -s --("storage-location") +takes_value
-         "where to store PBFT's state ('memory' or 'disk+/path/to/file'; default 'memory')"))
+         "where to store PBFT's state ('memory' or 'disk+/path/to/file'; default 'memory')")
+        (@arg event_stream_location: -e --("event-stream") +takes_value
+         "where to publish consensus events ('file+/path/to/file' or 'unix+/path/to/socket')")
+        (@arg admin_socket_path: -a --("admin-socket") +takes_value
+         "path of the Unix socket to answer admin requests on (disabled by default)")
+        (@arg participation_window: --("participation-window") +takes_value
+         "how many recent blocks to track member participation over (default 100; 0 disables \
+          participation logging)")
+        (@arg participation_threshold: --("participation-threshold") +takes_value
+         "fraction of blocks a member must take part in to not be flagged (default 0.5)")
+        (@arg recording_path: --record +takes_value
+         "record updates from the validator and service call results to this file for replay")
+        (@arg signature_scheme: --("signature-scheme") +takes_value
+         "signature scheme the validators sign with ('secp256k1' or 'ed25519'; default \
+          'secp256k1')")
+        (@arg verification_cache_size: --("verification-cache-size") +takes_value
+         "how many valid vote signatures to cache (default 1024; 0 disables the cache)")
+        (@arg message_seq_window: --("message-seq-window") +takes_value
+         "how many sequence numbers ahead of the node's other nodes' messages may be \
+          (default 100)")
+        (@arg message_view_window: --("message-view-window") +takes_value
+         "how many views ahead of the node's other nodes' normal-case messages and view \
+          changes may be (default 100)")
+        (@arg max_messages_per_signer: --("max-messages-per-signer") +takes_value
+         "how many messages from a single signer the log holds at once (default 1000)")
+        (@arg max_finality_proof_depth: --("max-finality-proof-depth") +takes_value
+         "how many blocks behind the chain head a block may be for the admin socket to build a \
+          finality proof for it (default 100)")
+        (@subcommand replay =>
+         (about: "replay a recording made with --record")
+         (@arg recording: +required "path of the recording")))
+    .subcommand(verify_seal_subcommand())
     .get_matches();
 
+    if let Some(verify_seal_matches) = matches.subcommand_matches("verify-seal") {
+        run_verify_seal(verify_seal_matches);
+    }
+
     let log_config = matches.value_of("logconfig").map(|s| s.into());
 
     let log_level = match matches.occurrences_of("verbose") {
@@ -189,7 +240,50 @@ fn parse_args() -> PbftCliArgs {
         .unwrap_or("")
         .parse::<u64>()
         .ok();
+    let participation_window = matches
+        .value_of("participation_window")
+        .unwrap_or("")
+        .parse::<u64>()
+        .ok();
+    let participation_threshold = matches
+        .value_of("participation_threshold")
+        .unwrap_or("")
+        .parse::<f64>()
+        .ok();
     let storage_location = matches.value_of("storage_location").map(String::from);
+    let event_stream_location = matches.value_of("event_stream_location").map(String::from);
+    let admin_socket_path = matches.value_of("admin_socket_path").map(String::from);
+    let recording_path = matches.value_of("recording_path").map(String::from);
+    let signature_scheme = parse_signature_scheme(&matches);
+    let verification_cache_size = matches
+        .value_of("verification_cache_size")
+        .unwrap_or("")
+        .parse::<usize>()
+        .ok();
+    let message_seq_window = matches
+        .value_of("message_seq_window")
+        .unwrap_or("")
+        .parse::<u64>()
+        .ok();
+    let message_view_window = matches
+        .value_of("message_view_window")
+        .unwrap_or("")
+        .parse::<u64>()
+        .ok();
+    let max_messages_per_signer = matches
+        .value_of("max_messages_per_signer")
+        .unwrap_or("")
+        .parse::<u64>()
+        .ok();
+    let max_finality_proof_depth = matches
+        .value_of("max_finality_proof_depth")
+        .unwrap_or("")
+        .parse::<u64>()
+        .ok();
+    let replay = matches
+        .subcommand_matches("replay")
+        .and_then(|replay_matches| replay_matches.value_of("recording"))
+        .map(String::from);
 
     PbftCliArgs {
         log_config,
@@ -199,10 +293,32 @@ fn parse_args() -> PbftCliArgs {
         exponential_retry_max,
         update_recv_timeout,
         max_log_size,
+        participation_window,
+        participation_threshold,
         storage_location,
+        event_stream_location,
+        admin_socket_path,
+        recording_path,
+        signature_scheme,
+        verification_cache_size,
+        message_seq_window,
+        message_view_window,
+        max_messages_per_signer,
+        max_finality_proof_depth,
+        replay,
     }
 }
 
+/// Parse the `signature_scheme` argument, exiting if it is invalid
+fn parse_signature_scheme(matches: &ArgMatches) -> Option<SignatureScheme> {
+    matches.value_of("signature_scheme").map(|scheme| {
+        scheme.parse().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(2)
+        })
+    })
+}
+
 #[derive(Clone)]
 pub struct PbftCliArgs {
     log_config: Option<String>,
@@ -212,5 +328,185 @@ pub struct PbftCliArgs {
     exponential_retry_max: Option<u64>,
     update_recv_timeout: Option<u64>,
     max_log_size: Option<u64>,
+    participation_window: Option<u64>,
+    participation_threshold: Option<f64>,
     storage_location: Option<String>,
+    event_stream_location: Option<String>,
+    admin_socket_path: Option<String>,
+    recording_path: Option<String>,
+    signature_scheme: Option<SignatureScheme>,
+    verification_cache_size: Option<usize>,
+    message_seq_window: Option<u64>,
+    message_view_window: Option<u64>,
+    max_messages_per_signer: Option<u64>,
+    max_finality_proof_depth: Option<u64>,
+    replay: Option<String>,
+}
+
+fn verify_seal_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("verify-seal")
+        .about("verify a consensus seal without a validator")
+        .arg(
+            Arg::with_name("seal")
+                .long("seal")
+                .takes_value(true)
+                .value_name("HEX")
+                .help("hex-encoded PbftSeal"),
+        )
+        .arg(
+            Arg::with_name("seal_file")
+                .long("seal-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("file containing the PbftSeal (raw or hex-encoded)"),
+        )
+        .group(
+            ArgGroup::with_name("seal_input")
+                .args(&["seal", "seal_file"])
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("block_id")
+                .long("block-id")
+                .takes_value(true)
+                .value_name("HEX")
+                .required(true)
+                .help("ID of the block the seal should prove"),
+        )
+        .arg(
+            Arg::with_name("previous_id")
+                .long("previous-id")
+                .takes_value(true)
+                .value_name("HEX")
+                .help("ID of the proven block's predecessor, which the members were taken from"),
+        )
+        .arg(
+            Arg::with_name("members")
+                .long("members")
+                .takes_value(true)
+                .value_name("JSON")
+                .help("members to verify the seal against, as a JSON list of hex public keys"),
+        )
+        .arg(
+            Arg::with_name("members_file")
+                .long("members-file")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("file containing the members as a JSON list of hex public keys"),
+        )
+        .group(
+            ArgGroup::with_name("members_input")
+                .args(&["members", "members_file"])
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("signature_scheme")
+                .long("signature-scheme")
+                .takes_value(true)
+                .value_name("SCHEME")
+                .help("signature scheme the votes are signed with ('secp256k1' or 'ed25519')"),
+        )
+}
+
+/// Check the seal given to the `verify-seal` subcommand, print the verdict as JSON, and exit with
+/// status 0 if the seal is valid, 1 if it is invalid, or 2 if the input couldn't be read
+fn run_verify_seal(matches: &ArgMatches) -> ! {
+    let verifier = parse_signature_scheme(matches)
+        .unwrap_or_default()
+        .verifier();
+    let verdict = read_verify_seal_input(matches).and_then(|input| {
+        verification::check_serialized_seal(
+            &input.seal,
+            &input.block_id,
+            input.previous_id.as_ref().map(Vec::as_slice),
+            &input.members,
+            &*verifier,
+        )
+        .map_err(|err| err.to_string())
+    });
+
+    match verdict {
+        Ok(verdict) => {
+            match serde_json::to_string_pretty(&verdict) {
+                Ok(json) => println!("{}", json),
+                Err(err) => {
+                    eprintln!("Couldn't serialize verdict: {}", err);
+                    process::exit(2)
+                }
+            }
+            process::exit(if verdict.valid { 0 } else { 1 })
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2)
+        }
+    }
+}
+
+/// Replay the recording given to the `replay` subcommand, print a summary of the node's final
+/// state, and exit with status 0 if the replay matched the recording or 1 if it didn't
+fn run_replay(path: &str) -> ! {
+    match recording::replay(path) {
+        Ok(replay) => {
+            println!(
+                "Replayed {} updates and {} service calls without diverging",
+                replay.updates, replay.calls
+            );
+            println!("Final state: {}", replay.state);
+            process::exit(0)
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    }
+}
+
+/// The decoded input to the `verify-seal` subcommand
+struct VerifySealInput {
+    seal: Vec<u8>,
+    block_id: Vec<u8>,
+    previous_id: Option<Vec<u8>>,
+    members: Vec<Vec<u8>>,
+}
+
+fn read_verify_seal_input(matches: &ArgMatches) -> Result<VerifySealInput, String> {
+    let seal = match matches.value_of("seal") {
+        Some(seal) => decode_hex_arg("seal", seal)?,
+        None => {
+            let path = matches.value_of("seal_file").unwrap_or_default();
+            let contents =
+                fs::read(path).map_err(|err| format!("Couldn't read seal file: {}", err))?;
+            // Accept hex-encoded files, falling back to treating the file as the raw seal
+            std::str::from_utf8(&contents)
+                .ok()
+                .and_then(|text| hex::decode(text.trim()).ok())
+                .unwrap_or(contents)
+        }
+    };
+
+    let block_id = decode_hex_arg("block ID", matches.value_of("block_id").unwrap_or_default())?;
+    let previous_id = match matches.value_of("previous_id") {
+        Some(previous_id) => Some(decode_hex_arg("previous ID", previous_id)?),
+        None => None,
+    };
+
+    let members = match matches.value_of("members") {
+        Some(members) => members.to_string(),
+        None => fs::read_to_string(matches.value_of("members_file").unwrap_or_default())
+            .map_err(|err| format!("Couldn't read members file: {}", err))?,
+    };
+    let members =
+        config::parse_members(&members).map_err(|err| format!("Invalid members: {}", err))?;
+
+    Ok(VerifySealInput {
+        seal,
+        block_id,
+        previous_id,
+        members,
+    })
+}
+
+fn decode_hex_arg(name: &str, value: &str) -> Result<Vec<u8>, String> {
+    hex::decode(value.trim()).map_err(|err| format!("Invalid {}: {}", name, err))
 }
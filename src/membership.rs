@@ -0,0 +1,285 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! History of the on-chain member lists of recently committed blocks
+//!
+//! A seal must be verified against the members in the settings of the block before the one it
+//! proves. That block has almost always been committed by this node already, and the node read
+//! its members when it committed it, so remembering the members of committed blocks saves asking
+//! the validator for them again.
+//!
+//! The history is kept in the `PbftState`, but since the state is written on every change, only
+//! the newest few blocks are persisted with it: enough for a restarted node to verify the seals
+//! of the next blocks it commits. Older members are read from the validator again if needed.
+//! Since consecutive blocks usually have the same members, each distinct member list is only
+//! stored once for a run of blocks.
+
+use std::collections::VecDeque;
+
+use hex;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::host::{BlockId, PeerId};
+
+/// How many of the most recently committed blocks' member lists are remembered
+pub const MEMBER_HISTORY_SIZE: usize = 256;
+
+/// How many of the most recently committed blocks' member lists are persisted; the seal of the
+/// next block proves the chain head and is verified against the members of the block before it
+pub const PERSISTED_MEMBER_HISTORY_SIZE: usize = 2;
+
+/// Consecutive committed blocks that all have the same members
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct MemberRun {
+    members: Vec<PeerId>,
+    /// Block numbers and IDs, in the order the blocks were committed
+    blocks: VecDeque<(u64, BlockId)>,
+}
+
+/// The members of the most recently committed blocks, by block ID
+///
+/// Only the newest `PERSISTED_MEMBER_HISTORY_SIZE` blocks are serialized.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct MemberHistory {
+    /// Oldest first
+    runs: VecDeque<MemberRun>,
+}
+
+impl MemberHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the members in the settings of the committed block with the given ID, if it is
+    /// remembered
+    pub fn get(&self, block_id: &[u8]) -> Option<&[PeerId]> {
+        self.runs
+            .iter()
+            .rev()
+            .find(|run| run.blocks.iter().any(|(_, id)| id.as_slice() == block_id))
+            .map(|run| run.members.as_slice())
+    }
+
+    /// Number of blocks whose members are remembered
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|run| run.blocks.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Remember the members of a block that was just committed on top of `previous_id`
+    ///
+    /// Blocks are only committed on top of the last committed block, so any remembered blocks at
+    /// the same height or higher, or any that the new block doesn't build on, are from a fork
+    /// that was abandoned (for instance, if the state was persisted on a node whose chain was
+    /// later replaced); they are forgotten.
+    pub fn record(
+        &mut self,
+        block_num: u64,
+        block_id: BlockId,
+        previous_id: &[u8],
+        members: Vec<PeerId>,
+    ) {
+        self.forget_from(block_num);
+
+        let builds_on_history = match self.newest() {
+            Some((num, id)) => num + 1 != block_num || id.as_slice() == previous_id,
+            None => true,
+        };
+        if !builds_on_history {
+            debug!(
+                "Block {} doesn't build on the last remembered block; forgetting member history",
+                hex::encode(&block_id)
+            );
+            self.runs.clear();
+        }
+
+        match self.runs.back_mut() {
+            Some(run) if run.members == members => run.blocks.push_back((block_num, block_id)),
+            _ => self.runs.push_back(MemberRun {
+                members,
+                blocks: vec![(block_num, block_id)].into(),
+            }),
+        }
+
+        while self.len() > MEMBER_HISTORY_SIZE {
+            self.forget_oldest();
+        }
+    }
+
+    /// The number and ID of the most recently committed block that is remembered
+    fn newest(&self) -> Option<(u64, &BlockId)> {
+        self.runs
+            .back()
+            .and_then(|run| run.blocks.back())
+            .map(|(num, id)| (*num, id))
+    }
+
+    /// Forget all blocks with the given block number or higher
+    fn forget_from(&mut self, block_num: u64) {
+        while let Some(run) = self.runs.back_mut() {
+            while run
+                .blocks
+                .back()
+                .map_or(false, |(num, _)| *num >= block_num)
+            {
+                run.blocks.pop_back();
+            }
+            if !run.blocks.is_empty() {
+                break;
+            }
+            self.runs.pop_back();
+        }
+    }
+
+    /// The runs of the newest `count` remembered blocks, oldest first
+    fn newest_runs(&self, count: usize) -> VecDeque<MemberRun> {
+        let mut runs = VecDeque::new();
+        let mut remaining = count;
+        for run in self.runs.iter().rev() {
+            if remaining == 0 {
+                break;
+            }
+            let skip = run.blocks.len().saturating_sub(remaining);
+            let blocks: VecDeque<_> = run.blocks.iter().skip(skip).cloned().collect();
+            remaining -= blocks.len();
+            runs.push_front(MemberRun {
+                members: run.members.clone(),
+                blocks,
+            });
+        }
+        runs
+    }
+
+    fn forget_oldest(&mut self) {
+        if let Some(run) = self.runs.front_mut() {
+            run.blocks.pop_front();
+            if run.blocks.is_empty() {
+                self.runs.pop_front();
+            }
+        }
+    }
+}
+
+impl Serialize for MemberHistory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut history = serializer.serialize_struct("MemberHistory", 1)?;
+        history.serialize_field("runs", &self.newest_runs(PERSISTED_MEMBER_HISTORY_SIZE))?;
+        history.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The members of each recorded block must be found by the block's ID, and consecutive
+    /// blocks with the same members must share a single copy of the member list.
+    #[test]
+    fn test_member_history_lookup() {
+        let mut history = MemberHistory::new();
+        assert!(history.get(&[1]).is_none());
+
+        history.record(1, vec![1], &[0], vec![vec![0], vec![1]]);
+        history.record(2, vec![2], &[1], vec![vec![0], vec![1]]);
+        history.record(3, vec![3], &[2], vec![vec![0], vec![1], vec![2]]);
+
+        assert_eq!(Some(&[vec![0], vec![1]][..]), history.get(&[1]));
+        assert_eq!(Some(&[vec![0], vec![1]][..]), history.get(&[2]));
+        assert_eq!(Some(&[vec![0], vec![1], vec![2]][..]), history.get(&[3]));
+        assert!(history.get(&[4]).is_none());
+        assert_eq!(3, history.len());
+        assert_eq!(2, history.runs.len());
+
+        // The persisted blocks may span runs
+        let persisted = serde_json::to_string(&history).expect("Failed to serialize history");
+        let loaded: MemberHistory =
+            serde_json::from_str(&persisted).expect("Failed to deserialize history");
+        assert!(loaded.get(&[1]).is_none());
+        assert_eq!(history.get(&[2]), loaded.get(&[2]));
+        assert_eq!(history.get(&[3]), loaded.get(&[3]));
+    }
+
+    /// Blocks from abandoned forks must be forgotten: recording a block forgets any blocks at its
+    /// height or higher, and recording a block that doesn't build on the newest remembered block
+    /// forgets the whole history.
+    #[test]
+    fn test_member_history_forks() {
+        let mut history = MemberHistory::new();
+        history.record(1, vec![1], &[0], vec![vec![0]]);
+        history.record(2, vec![2], &[1], vec![vec![0]]);
+        history.record(3, vec![3], &[2], vec![vec![1]]);
+
+        // A different block 2 replaces blocks 2 and 3
+        history.record(2, vec![22], &[1], vec![vec![2]]);
+        assert!(history.get(&[2]).is_none());
+        assert!(history.get(&[3]).is_none());
+        assert_eq!(Some(&[vec![0]][..]), history.get(&[1]));
+        assert_eq!(Some(&[vec![2]][..]), history.get(&[22]));
+
+        // A block 3 that isn't built on block 22 means the whole history is from another chain
+        history.record(3, vec![33], &[2], vec![vec![2]]);
+        assert!(history.get(&[1]).is_none());
+        assert!(history.get(&[22]).is_none());
+        assert_eq!(Some(&[vec![2]][..]), history.get(&[33]));
+        assert_eq!(1, history.len());
+
+        // After a gap, the new block's predecessor isn't known, so the history is kept
+        history.record(10, vec![10], &[9], vec![vec![2]]);
+        assert_eq!(2, history.len());
+    }
+
+    /// Only the most recent `MEMBER_HISTORY_SIZE` blocks must be remembered, and only the most
+    /// recent `PERSISTED_MEMBER_HISTORY_SIZE` of them must be persisted.
+    #[test]
+    fn test_member_history_pruning_and_persistence() {
+        let mut history = MemberHistory::new();
+        let total = MEMBER_HISTORY_SIZE as u64 + 10;
+        for num in 1..=total {
+            let members = vec![vec![(num / 100) as u8]];
+            history.record(
+                num,
+                num.to_be_bytes().to_vec(),
+                &(num - 1).to_be_bytes(),
+                members,
+            );
+        }
+
+        assert_eq!(MEMBER_HISTORY_SIZE, history.len());
+        assert!(history.get(&10u64.to_be_bytes()).is_none());
+        assert_eq!(
+            Some(&[vec![(total / 100) as u8]][..]),
+            history.get(&total.to_be_bytes())
+        );
+
+        let persisted = serde_json::to_string(&history).expect("Failed to serialize history");
+        let loaded: MemberHistory =
+            serde_json::from_str(&persisted).expect("Failed to deserialize history");
+        assert_eq!(PERSISTED_MEMBER_HISTORY_SIZE, loaded.len());
+        for num in total - PERSISTED_MEMBER_HISTORY_SIZE as u64 + 1..=total {
+            assert_eq!(
+                history.get(&num.to_be_bytes()),
+                loaded.get(&num.to_be_bytes())
+            );
+        }
+        assert!(loaded
+            .get(&(total - PERSISTED_MEMBER_HISTORY_SIZE as u64).to_be_bytes())
+            .is_none());
+    }
+}
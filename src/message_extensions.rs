@@ -25,9 +25,8 @@ use std::fmt;
 use std::hash::{Hash, Hasher};
 
 use hex;
-use sawtooth_sdk::consensus::engine::PeerId;
-use sawtooth_sdk::messages::consensus::ConsensusPeerMessageHeader;
 
+use crate::host::PeerId;
 use crate::message_type::PbftMessageType;
 use crate::protos::pbft_message::{
     PbftMessage, PbftMessageInfo, PbftNewView, PbftSeal, PbftSignedVote,
@@ -111,10 +110,8 @@ impl fmt::Display for PbftSignedVote {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "PbftSignedVote(header: {:?}, message: {:?}, header_bytes: {}, header_signature: {}, \
+            "PbftSignedVote(message: {:?}, header_bytes: {}, header_signature: {}, \
              message_bytes: {})",
-            protobuf::parse_from_bytes::<ConsensusPeerMessageHeader>(self.get_header_bytes())
-                .map_err(|_| fmt::Error)?,
             protobuf::parse_from_bytes::<PbftMessage>(self.get_message_bytes())
                 .map_err(|_| fmt::Error)?,
             hex::encode(self.get_header_bytes()),
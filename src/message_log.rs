@@ -16,18 +16,46 @@
  */
 
 //! The message log used by PBFT nodes to save messages
+//!
+//! Messages are indexed by type, sequence number, view, and block ID, and blocks by ID and block
+//! number, so that each lookup only touches the messages or blocks it returns instead of scanning
+//! the whole log.
+//!
+//! So that no member can fill up the log, normal-case messages from other nodes are only accepted
+//! for sequence numbers and views that aren't too far ahead of the node's own, only the highest
+//! ViewChange from each other node is kept, and each signer may only have a limited number of
+//! messages in the log at once. Rejected messages are counted, so flooding can be noticed.
 
 #![allow(unknown_lints)]
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 
 use hex;
-use sawtooth_sdk::consensus::engine::{Block, BlockId};
 
 use crate::config::PbftConfig;
-use crate::message_type::{ParsedMessage, PbftMessageType};
-use crate::protos::pbft_message::PbftMessageInfo;
+use crate::host::{Block, BlockId, PeerId};
+use crate::message_type::{ParsedMessage, PbftMessageType, PbftMessageWrapper};
+use crate::state::PbftState;
+
+/// Identifies a message in the log; messages are numbered in the order they were added
+type MessageId = u64;
+
+/// The IDs of the messages of a single type and sequence number, by view and then by block ID
+type ViewIndex = HashMap<u64, HashMap<BlockId, Vec<MessageId>>>;
+
+/// Counts of the messages from other nodes that the log didn't accept
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct RejectedMessages {
+    /// Messages for a sequence number too far ahead of the node's
+    pub outside_seq_window: u64,
+    /// Normal-case messages and ViewChanges for a view too far ahead of the node's
+    pub outside_view_window: u64,
+    /// Messages from signers that already had as many messages in the log as they may
+    pub over_quota: u64,
+    /// All rejected messages, by hex-encoded signer ID
+    pub by_signer: BTreeMap<String, u64>,
+}
 
 /// Struct for storing messages that a PbftNode receives
 pub struct PbftLog {
@@ -36,25 +64,52 @@ pub struct PbftLog {
 
     /// All blocks received from the validator that have been validated and not yet garbage
     /// collected
-    blocks: HashSet<Block>,
+    blocks: HashMap<BlockId, Block>,
+
+    /// The IDs of the validated blocks, by block number
+    block_ids_by_num: HashMap<u64, Vec<BlockId>>,
 
     /// All messages accepted by the node that have not been garbage collected
-    messages: HashSet<ParsedMessage>,
+    messages: HashMap<MessageId, ParsedMessage>,
+
+    /// The ID to give the next message that is added
+    next_message_id: MessageId,
+
+    /// The messages, by type and sequence number, then by view and block ID (see `block_key`)
+    by_type_seq: HashMap<(PbftMessageType, u64), ViewIndex>,
+
+    /// The messages, by type and view
+    by_type_view: HashMap<(PbftMessageType, u64), Vec<MessageId>>,
+
+    /// The messages, by signer
+    by_signer: HashMap<PeerId, HashSet<MessageId>>,
+
+    /// The highest ViewChange from each other node
+    view_changes: HashMap<PeerId, MessageId>,
 
     /// Maximum log size
     max_log_size: u64,
+
+    /// How far ahead of the node's sequence number a message may be
+    seq_window: u64,
+
+    /// How far ahead of the node's view a normal-case message or ViewChange may be
+    view_window: u64,
+
+    /// How many messages a single signer may have in the log
+    max_messages_per_signer: u64,
+
+    rejected: RejectedMessages,
 }
 
 impl fmt::Display for PbftLog {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let msg_infos: Vec<PbftMessageInfo> = self
-            .messages
-            .iter()
-            .map(|ref msg| msg.info().clone())
-            .collect();
-        let string_infos: Vec<String> = msg_infos
-            .iter()
-            .map(|info: &PbftMessageInfo| -> String {
+        let mut ids: Vec<&MessageId> = self.messages.keys().collect();
+        ids.sort();
+        let string_infos: Vec<String> = ids
+            .into_iter()
+            .map(|id| {
+                let info = self.messages[id].info();
                 format!(
                     "    {{ {}, view: {}, seq: {}, signer: {} }}",
                     info.get_msg_type(),
@@ -69,21 +124,40 @@ impl fmt::Display for PbftLog {
     }
 }
 
+/// The block ID a message is filed under: the block it is about, or an empty ID for NewView and
+/// Seal messages, which aren't about a single block
+fn block_key(msg: &ParsedMessage) -> BlockId {
+    match &msg.message {
+        PbftMessageWrapper::Message(m) => m.get_block_id().to_vec(),
+        PbftMessageWrapper::NewView(_) | PbftMessageWrapper::Seal(_) => vec![],
+    }
+}
+
 impl PbftLog {
     /// Create a new, empty `PbftLog` with the `max_log_size` specified in the `config`
     pub fn new(config: &PbftConfig) -> Self {
         PbftLog {
             unvalidated_blocks: HashMap::new(),
-            blocks: HashSet::new(),
-            messages: HashSet::new(),
+            blocks: HashMap::new(),
+            block_ids_by_num: HashMap::new(),
+            messages: HashMap::new(),
+            next_message_id: 0,
+            by_type_seq: HashMap::new(),
+            by_type_view: HashMap::new(),
+            by_signer: HashMap::new(),
+            view_changes: HashMap::new(),
             max_log_size: config.max_log_size,
+            seq_window: config.message_seq_window,
+            view_window: config.message_view_window,
+            max_messages_per_signer: config.max_messages_per_signer,
+            rejected: RejectedMessages::default(),
         }
     }
 
     /// Add an already validated `Block` to the log
     pub fn add_validated_block(&mut self, block: Block) {
         trace!("Adding validated block to log: {:?}", block);
-        self.blocks.insert(block);
+        self.insert_block(block);
     }
 
     /// Add an unvalidated `Block` to the log
@@ -98,7 +172,7 @@ impl PbftLog {
     pub fn block_validated(&mut self, block_id: BlockId) -> Option<Block> {
         trace!("Marking block as validated: {:?}", block_id);
         self.unvalidated_blocks.remove(&block_id).map(|block| {
-            self.blocks.insert(block.clone());
+            self.insert_block(block.clone());
             block
         })
     }
@@ -109,19 +183,27 @@ impl PbftLog {
         self.unvalidated_blocks.remove(&block_id).is_some()
     }
 
+    fn insert_block(&mut self, block: Block) {
+        if !self.blocks.contains_key(&block.block_id) {
+            self.block_ids_by_num
+                .entry(block.block_num)
+                .or_default()
+                .push(block.block_id.clone());
+        }
+        self.blocks.insert(block.block_id.clone(), block);
+    }
+
     /// Get all `Block`s in the message log with the specified block number
     pub fn get_blocks_with_num(&self, block_num: u64) -> Vec<&Block> {
-        self.blocks
-            .iter()
-            .filter(|block| block.block_num == block_num)
-            .collect()
+        self.block_ids_by_num
+            .get(&block_num)
+            .map(|ids| ids.iter().map(|id| &self.blocks[id]).collect())
+            .unwrap_or_default()
     }
 
     /// Get the `Block` with the specified block ID
     pub fn get_block_with_id(&self, block_id: &[u8]) -> Option<&Block> {
-        self.blocks
-            .iter()
-            .find(|block| block.block_id.as_slice() == block_id)
+        self.blocks.get(block_id)
     }
 
     /// Get the `Block` with the specified block ID from `unvalidated_blocks`.
@@ -130,17 +212,276 @@ impl PbftLog {
     }
 
     /// Add a parsed PBFT message to the log
-    pub fn add_message(&mut self, msg: ParsedMessage) {
+    ///
+    /// Messages from other nodes are only added if they are within the acceptance windows of the
+    /// node's current sequence number and view, and if their signer has room in its quota;
+    /// otherwise they are counted as rejected. A ViewChange from another node replaces the
+    /// signer's earlier ViewChange, and is ignored if it isn't for a higher view. Messages this
+    /// node made itself are always added.
+    pub fn add_message(&mut self, msg: ParsedMessage, state: &PbftState) {
         trace!("Adding message to log: {:?}", msg);
-        self.messages.insert(msg);
+
+        let msg_type = PbftMessageType::from(msg.info().get_msg_type());
+        if self.contains(&msg, msg_type) {
+            return;
+        }
+        if !msg.from_self && !self.accept(&msg, msg_type, state) {
+            return;
+        }
+
+        let signer_id = msg.info().get_signer_id().to_vec();
+        let from_self = msg.from_self;
+        let id = self.insert_message(msg, msg_type);
+        if msg_type == PbftMessageType::ViewChange && !from_self {
+            self.view_changes.insert(signer_id, id);
+        }
+    }
+
+    /// Add a vote from a consensus seal that has been verified; the votes are needed to build the
+    /// seal for the block that the node catches up to, so they are added regardless of the
+    /// acceptance windows and their signers' quotas
+    pub fn add_seal_vote(&mut self, msg: ParsedMessage) {
+        trace!("Adding seal vote to log: {:?}", msg);
+
+        let msg_type = PbftMessageType::from(msg.info().get_msg_type());
+        if !self.contains(&msg, msg_type) {
+            self.insert_message(msg, msg_type);
+        }
+    }
+
+    /// Check if the log already has the given message
+    fn contains(&self, msg: &ParsedMessage, msg_type: PbftMessageType) -> bool {
+        // Any duplicate would be filed under the same type, sequence number, view, and block
+        self.by_type_seq
+            .get(&(msg_type, msg.info().get_seq_num()))
+            .and_then(|views| views.get(&msg.info().get_view()))
+            .and_then(|blocks| blocks.get(&block_key(msg)))
+            .map_or(false, |ids| ids.iter().any(|id| &self.messages[id] == msg))
+    }
+
+    /// Add a message to the log and its indexes, returning its ID
+    fn insert_message(&mut self, msg: ParsedMessage, msg_type: PbftMessageType) -> MessageId {
+        let id = self.next_message_id;
+        self.next_message_id += 1;
+        self.by_type_seq
+            .entry((msg_type, msg.info().get_seq_num()))
+            .or_default()
+            .entry(msg.info().get_view())
+            .or_default()
+            .entry(block_key(&msg))
+            .or_default()
+            .push(id);
+        self.by_type_view
+            .entry((msg_type, msg.info().get_view()))
+            .or_default()
+            .push(id);
+        self.by_signer
+            .entry(msg.info().get_signer_id().to_vec())
+            .or_default()
+            .insert(id);
+        self.messages.insert(id, msg);
+        id
+    }
+
+    /// Remove a message from the log and its indexes
+    fn remove_message(&mut self, id: MessageId) {
+        let msg = match self.messages.remove(&id) {
+            Some(msg) => msg,
+            None => return,
+        };
+        let msg_type = PbftMessageType::from(msg.info().get_msg_type());
+        let view = msg.info().get_view();
+        let block_id = block_key(&msg);
+
+        let seq_key = (msg_type, msg.info().get_seq_num());
+        if let Some(views) = self.by_type_seq.get_mut(&seq_key) {
+            if let Some(blocks) = views.get_mut(&view) {
+                if let Some(ids) = blocks.get_mut(&block_id) {
+                    ids.retain(|other| *other != id);
+                    if ids.is_empty() {
+                        blocks.remove(&block_id);
+                    }
+                }
+                if blocks.is_empty() {
+                    views.remove(&view);
+                }
+            }
+            if views.is_empty() {
+                self.by_type_seq.remove(&seq_key);
+            }
+        }
+        if let Some(ids) = self.by_type_view.get_mut(&(msg_type, view)) {
+            ids.retain(|other| *other != id);
+            if ids.is_empty() {
+                self.by_type_view.remove(&(msg_type, view));
+            }
+        }
+        let signer_id = msg.info().get_signer_id();
+        if let Some(ids) = self.by_signer.get_mut(signer_id) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.by_signer.remove(signer_id);
+            }
+        }
+        if self.view_changes.get(signer_id) == Some(&id) {
+            self.view_changes.remove(signer_id);
+        }
+    }
+
+    /// Make way for a ViewChange from another node by removing the signer's earlier ViewChange;
+    /// returns `false` (keeping the earlier one) if the new ViewChange isn't for a higher view
+    ///
+    /// A node that asks for a later view no longer supports its request for an earlier one, so
+    /// only the highest ViewChange from each node matters.
+    fn replace_view_change(&mut self, msg: &ParsedMessage) -> bool {
+        let signer_id = msg.info().get_signer_id();
+        let current = self
+            .view_changes
+            .get(signer_id)
+            .and_then(|id| self.messages.get(id).map(|vc| (*id, vc.info().get_view())));
+        match current {
+            Some((_, view)) if view >= msg.info().get_view() => {
+                debug!(
+                    "Ignoring ViewChange for view {} from {}; already have one for view {}",
+                    msg.info().get_view(),
+                    hex::encode(signer_id),
+                    view
+                );
+                false
+            }
+            Some((id, _)) => {
+                self.remove_message(id);
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Check that a message from another node is within the acceptance windows and that its
+    /// signer has room in its quota, counting the message as rejected if not; a ViewChange within
+    /// the windows replaces the signer's earlier one (see `replace_view_change`)
+    fn accept(
+        &mut self,
+        msg: &ParsedMessage,
+        msg_type: PbftMessageType,
+        state: &PbftState,
+    ) -> bool {
+        let info = msg.info();
+
+        // ViewChanges and NewViews carry the sequence number their signer was on, which may be
+        // well behind or ahead of this node without the message being any less relevant
+        let seq_limited = match msg_type {
+            PbftMessageType::PrePrepare
+            | PbftMessageType::Prepare
+            | PbftMessageType::Commit
+            | PbftMessageType::SealRequest => true,
+            _ => false,
+        };
+        // Normal-case messages and ViewChanges are limited to views near this node's, so a
+        // ViewChange for a far-off view can't displace its signer's real one; NewViews are what
+        // move the node to later views
+        let view_limited = match msg_type {
+            PbftMessageType::PrePrepare
+            | PbftMessageType::Prepare
+            | PbftMessageType::Commit
+            | PbftMessageType::ViewChange => true,
+            _ => false,
+        };
+
+        if seq_limited && info.get_seq_num() > state.seq_num.saturating_add(self.seq_window) {
+            debug!(
+                "Rejecting {} for seq_num {}; node is on seq_num {}",
+                msg_type,
+                info.get_seq_num(),
+                state.seq_num
+            );
+            self.rejected.outside_seq_window += 1;
+        } else if view_limited && info.get_view() > state.view.saturating_add(self.view_window) {
+            debug!(
+                "Rejecting {} for view {}; node is on view {}",
+                msg_type,
+                info.get_view(),
+                state.view
+            );
+            self.rejected.outside_view_window += 1;
+        } else if msg_type == PbftMessageType::ViewChange && !self.replace_view_change(msg) {
+            return false;
+        } else if !self.has_quota(info.get_signer_id(), state.seq_num) {
+            debug!(
+                "Rejecting {} from {}; signer already has {} messages in the log",
+                msg_type,
+                hex::encode(info.get_signer_id()),
+                self.max_messages_per_signer
+            );
+            self.rejected.over_quota += 1;
+        } else {
+            return true;
+        }
+
+        *self
+            .rejected
+            .by_signer
+            .entry(hex::encode(info.get_signer_id()))
+            .or_default() += 1;
+        false
+    }
+
+    /// Check if the signer has fewer messages in the log than the quota allows. If it doesn't,
+    /// the signer's own messages that the node no longer needs are evicted first; no other
+    /// signer's messages are touched.
+    fn has_quota(&mut self, signer_id: &[u8], current_seq_num: u64) -> bool {
+        let count = |log: &Self| log.by_signer.get(signer_id).map_or(0, HashSet::len) as u64;
+        if count(self) < self.max_messages_per_signer {
+            return true;
+        }
+
+        let min_seq_num = current_seq_num.saturating_sub(1);
+        let stale: Vec<MessageId> = self
+            .by_signer
+            .get(signer_id)
+            .into_iter()
+            .flatten()
+            .filter(|id| self.messages[id].info().get_seq_num() < min_seq_num)
+            .cloned()
+            .collect();
+        for id in stale {
+            self.remove_message(id);
+        }
+        count(self) < self.max_messages_per_signer
+    }
+
+    /// Get the counts of messages that weren't accepted into the log
+    pub fn rejected_messages(&self) -> &RejectedMessages {
+        &self.rejected
+    }
+
+    /// Number of messages in the log
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
     }
 
     /// Check if the log has a PrePrepare at the given view and sequence number that matches the
     /// given block ID
     pub fn has_pre_prepare(&self, seq_num: u64, view: u64, block_id: &[u8]) -> bool {
-        self.get_messages_of_type_seq_view(PbftMessageType::PrePrepare, seq_num, view)
-            .iter()
-            .any(|msg| msg.get_block_id() == block_id)
+        !self
+            .get_messages_of_type_seq_view_block(
+                PbftMessageType::PrePrepare,
+                seq_num,
+                view,
+                block_id,
+            )
+            .is_empty()
+    }
+
+    /// Get the messages with the given IDs, in the order they were added to the log
+    fn get_messages<'a>(&self, ids: impl Iterator<Item = &'a MessageId>) -> Vec<&ParsedMessage> {
+        let mut ids: Vec<&MessageId> = ids.collect();
+        ids.sort();
+        ids.into_iter().map(|id| &self.messages[id]).collect()
     }
 
     /// Obtain all messages from the log that match the given type and sequence_number
@@ -149,14 +490,12 @@ impl PbftLog {
         msg_type: PbftMessageType,
         sequence_number: u64,
     ) -> Vec<&ParsedMessage> {
-        self.messages
-            .iter()
-            .filter(|&msg| {
-                let info = (*msg).info();
-                info.get_msg_type() == String::from(msg_type)
-                    && info.get_seq_num() == sequence_number
-            })
-            .collect()
+        match self.by_type_seq.get(&(msg_type, sequence_number)) {
+            Some(views) => {
+                self.get_messages(views.values().flat_map(|blocks| blocks.values().flatten()))
+            }
+            None => vec![],
+        }
     }
 
     /// Obtain all messages from the log that match the given type and view
@@ -165,13 +504,10 @@ impl PbftLog {
         msg_type: PbftMessageType,
         view: u64,
     ) -> Vec<&ParsedMessage> {
-        self.messages
-            .iter()
-            .filter(|&msg| {
-                let info = (*msg).info();
-                info.get_msg_type() == String::from(msg_type) && info.get_view() == view
-            })
-            .collect()
+        match self.by_type_view.get(&(msg_type, view)) {
+            Some(ids) => self.get_messages(ids.iter()),
+            None => vec![],
+        }
     }
 
     /// Obtain all messages from the log that match the given type, sequence number, and view
@@ -181,15 +517,14 @@ impl PbftLog {
         sequence_number: u64,
         view: u64,
     ) -> Vec<&ParsedMessage> {
-        self.messages
-            .iter()
-            .filter(|&msg| {
-                let info = (*msg).info();
-                info.get_msg_type() == String::from(msg_type)
-                    && info.get_seq_num() == sequence_number
-                    && info.get_view() == view
-            })
-            .collect()
+        match self
+            .by_type_seq
+            .get(&(msg_type, sequence_number))
+            .and_then(|views| views.get(&view))
+        {
+            Some(blocks) => self.get_messages(blocks.values().flatten()),
+            None => vec![],
+        }
     }
 
     /// Obtain all messages from the log that match the given type, sequence number, view, and
@@ -201,17 +536,16 @@ impl PbftLog {
         view: u64,
         block_id: &[u8],
     ) -> Vec<&ParsedMessage> {
-        self.messages
-            .iter()
-            .filter(|&msg| {
-                let info = (*msg).info();
-                let msg_block_id = (*msg).get_block_id();
-                info.get_msg_type() == String::from(msg_type)
-                    && info.get_seq_num() == sequence_number
-                    && info.get_view() == view
-                    && msg_block_id == block_id
-            })
-            .collect()
+        match self
+            .by_type_seq
+            .get(&(msg_type, sequence_number))
+            .and_then(|views| views.get(&view))
+            .and_then(|blocks| blocks.get(block_id))
+        {
+            // Messages are filed under a block in the order they were added
+            Some(ids) => ids.iter().map(|id| &self.messages[id]).collect(),
+            None => vec![],
+        }
     }
 
     /// Garbage collect the log if it has reached the `max_log_size`
@@ -219,16 +553,37 @@ impl PbftLog {
     pub fn garbage_collect(&mut self, current_seq_num: u64) {
         // If the max log size has been reached, filter out all old messages
         if self.messages.len() as u64 >= self.max_log_size {
-            // The node needs to keep messages from the previous sequence number in case it
-            // needs to build the next consensus seal
-            self.messages
-                .retain(|msg| msg.info().get_seq_num() >= current_seq_num - 1);
+            self.prune(current_seq_num);
 
             self.blocks
-                .retain(|block| block.block_num >= current_seq_num - 1);
+                .retain(|_, block| block.block_num >= current_seq_num - 1);
+            self.block_ids_by_num
+                .retain(|block_num, _| *block_num >= current_seq_num - 1);
         }
     }
 
+    /// Remove all messages that are older than the node needs
+    fn prune(&mut self, current_seq_num: u64) {
+        // The node needs to keep messages from the previous sequence number in case it needs to
+        // build the next consensus seal
+        let min_seq_num = current_seq_num.saturating_sub(1);
+
+        self.messages
+            .retain(|_, msg| msg.info().get_seq_num() >= min_seq_num);
+        self.by_type_seq
+            .retain(|(_, seq_num), _| *seq_num >= min_seq_num);
+        let messages = &self.messages;
+        self.by_type_view.retain(|_, ids| {
+            ids.retain(|id| messages.contains_key(id));
+            !ids.is_empty()
+        });
+        self.view_changes.retain(|_, id| messages.contains_key(id));
+        self.by_signer.retain(|_, ids| {
+            ids.retain(|id| messages.contains_key(id));
+            !ids.is_empty()
+        });
+    }
+
     #[cfg(test)]
     pub fn set_max_log_size(&mut self, size: u64) {
         self.max_log_size = size;
@@ -238,7 +593,7 @@ impl PbftLog {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_helpers::*;
+    use crate::testkit::*;
 
     /// The `PbftLog` must reliably store and retrieve blocks for the node to keep track of the
     /// blocks it receives from the validator, perform consensus on them, and commit or fail them.
@@ -283,7 +638,7 @@ mod tests {
             .expect("Block was not in log");
         assert_eq!(block, block1);
         assert!(log.unvalidated_blocks.is_empty());
-        assert!(log.blocks.get(&block1).is_some());
+        assert!(log.blocks.get(&block1.block_id).is_some());
 
         // Verify log correctly retrieves blocks by ID
         assert_eq!(
@@ -300,7 +655,7 @@ mod tests {
 
         // Verify adding an already validated block works
         assert!(log.unvalidated_blocks.is_empty());
-        assert!(log.blocks.get(&block2).is_some());
+        assert!(log.blocks.get(&block2.block_id).is_some());
 
         // Add a 3rd block to the log
         let block3 = mock_block(3);
@@ -343,24 +698,25 @@ mod tests {
         // Initialize an empty log
         let cfg = mock_config(4);
         let mut log = PbftLog::new(&cfg);
+        let state = PbftState::new(vec![0], 0, &cfg);
 
         // Verify adding single message works
         let msg1 = mock_msg(PbftMessageType::PrePrepare, 0, 1, vec![0], vec![1], false);
-        log.add_message(msg1.clone());
+        log.add_message(msg1.clone(), &state);
         assert_eq!(1, log.messages.len());
-        assert!(log.messages.contains(&msg1));
+        assert!(log.messages.values().any(|msg| msg == &msg1));
 
         // Verify messages aren't duplicated
-        log.add_message(msg1.clone());
+        log.add_message(msg1.clone(), &state);
         assert_eq!(1, log.messages.len());
 
         // Verify get_messages_of_type_seq() works
         let msg2 = mock_msg(PbftMessageType::Commit, 0, 1, vec![0], vec![1], false);
-        log.add_message(msg2.clone());
+        log.add_message(msg2.clone(), &state);
         let msg3 = mock_msg(PbftMessageType::Commit, 0, 2, vec![0], vec![2], false);
-        log.add_message(msg3.clone());
+        log.add_message(msg3.clone(), &state);
         let msg4 = mock_msg(PbftMessageType::Commit, 1, 2, vec![0], vec![2], false);
-        log.add_message(msg4.clone());
+        log.add_message(msg4.clone(), &state);
 
         let res1 = log.get_messages_of_type_seq(PbftMessageType::Commit, 1);
         assert_eq!(1, res1.len());
@@ -373,11 +729,11 @@ mod tests {
 
         // Verify get_messages_of_type_view() works
         let msg5 = mock_msg(PbftMessageType::ViewChange, 0, 1, vec![0], vec![1], false);
-        log.add_message(msg5.clone());
+        log.add_message(msg5.clone(), &state);
         let msg6 = mock_msg(PbftMessageType::ViewChange, 1, 1, vec![0], vec![1], false);
-        log.add_message(msg6.clone());
+        log.add_message(msg6.clone(), &state);
         let msg7 = mock_msg(PbftMessageType::ViewChange, 1, 2, vec![0], vec![2], false);
-        log.add_message(msg7.clone());
+        log.add_message(msg7.clone(), &state);
 
         let res3 = log.get_messages_of_type_view(PbftMessageType::ViewChange, 0);
         assert_eq!(1, res3.len());
@@ -390,7 +746,7 @@ mod tests {
 
         // Verify get_messages_of_type_seq_view() works
         let msg8 = mock_msg(PbftMessageType::Commit, 0, 1, vec![1], vec![1], false);
-        log.add_message(msg8.clone());
+        log.add_message(msg8.clone(), &state);
 
         let res5 = log.get_messages_of_type_seq_view(PbftMessageType::Commit, 1, 0);
         assert_eq!(2, res5.len());
@@ -411,7 +767,7 @@ mod tests {
 
         // Verify get_messages_of_type_seq_view_block() works
         let msg9 = mock_msg(PbftMessageType::Commit, 0, 1, vec![0], vec![2], false);
-        log.add_message(msg9.clone());
+        log.add_message(msg9.clone(), &state);
 
         let res9 = log.get_messages_of_type_seq_view_block(PbftMessageType::Commit, 1, 0, &vec![1]);
         assert_eq!(2, res9.len());
@@ -423,4 +779,213 @@ mod tests {
         assert_eq!(1, res10.len());
         assert!(res10.contains(&&msg9));
     }
+
+    /// Messages must be returned in the order they were added to the log, and once the log is
+    /// garbage collected, neither the messages nor the blocks that were removed may be found by
+    /// any lookup.
+    #[test]
+    fn test_indexes_after_garbage_collection() {
+        let cfg = mock_config(4);
+        let mut log = PbftLog::new(&cfg);
+        let state = PbftState::new(vec![0], 0, &cfg);
+        log.set_max_log_size(4);
+
+        let commits: Vec<ParsedMessage> = (1..=3)
+            .flat_map(|seq_num| {
+                (0..2).map(move |signer| {
+                    mock_msg(
+                        PbftMessageType::Commit,
+                        0,
+                        seq_num,
+                        vec![2 - signer],
+                        vec![seq_num as u8],
+                        false,
+                    )
+                })
+            })
+            .collect();
+        for msg in &commits {
+            log.add_message(msg.clone(), &state);
+        }
+        for num in 1..=3 {
+            log.add_validated_block(mock_block(num));
+        }
+
+        assert_eq!(
+            vec![&commits[2], &commits[3]],
+            log.get_messages_of_type_seq(PbftMessageType::Commit, 2)
+        );
+        assert_eq!(
+            commits.iter().collect::<Vec<_>>(),
+            log.get_messages_of_type_view(PbftMessageType::Commit, 0)
+        );
+
+        // Only messages and blocks for sequence number 2 and later are kept
+        log.garbage_collect(3);
+        assert_eq!(4, log.messages.len());
+        assert!(log
+            .get_messages_of_type_seq(PbftMessageType::Commit, 1)
+            .is_empty());
+        assert!(log
+            .get_messages_of_type_seq_view_block(PbftMessageType::Commit, 1, 0, &[1])
+            .is_empty());
+        assert_eq!(
+            commits[2..].iter().collect::<Vec<_>>(),
+            log.get_messages_of_type_view(PbftMessageType::Commit, 0)
+        );
+        assert!(log.get_block_with_id(&[1]).is_none());
+        assert!(log.get_blocks_with_num(1).is_empty());
+        assert_eq!(vec![&mock_block(2)], log.get_blocks_with_num(2));
+    }
+
+    /// Messages from other nodes must only be accepted for sequence numbers and (for normal-case
+    /// messages and ViewChanges) views within the acceptance windows, and only while their signer
+    /// has room in its quota (once the signer's messages that the node no longer needs have been
+    /// evicted); all others must be counted as rejected. Only the highest ViewChange from each
+    /// other node must be kept. The node's own messages and the votes from verified seals must
+    /// always be accepted.
+    #[test]
+    fn test_message_acceptance() {
+        let mut cfg = mock_config(4);
+        cfg.message_seq_window = 10;
+        cfg.message_view_window = 2;
+        cfg.max_messages_per_signer = 3;
+        let mut log = PbftLog::new(&cfg);
+        let mut state = PbftState::new(vec![0], 0, &cfg);
+        assert_eq!(1, state.seq_num);
+
+        // Only Prepares up to 10 sequence numbers ahead are accepted, but a ViewChange's sequence
+        // number doesn't matter
+        log.add_message(
+            mock_msg(PbftMessageType::Prepare, 0, 11, vec![1], vec![11], false),
+            &state,
+        );
+        log.add_message(
+            mock_msg(PbftMessageType::Prepare, 0, 12, vec![1], vec![12], false),
+            &state,
+        );
+        log.add_message(
+            mock_msg(PbftMessageType::ViewChange, 2, 12, vec![1], vec![], false),
+            &state,
+        );
+        assert_eq!(2, log.len());
+
+        // Only normal-case messages and ViewChanges up to 2 views ahead are accepted
+        log.add_message(
+            mock_msg(PbftMessageType::Prepare, 3, 1, vec![2], vec![1], false),
+            &state,
+        );
+        log.add_message(
+            mock_msg(PbftMessageType::ViewChange, 1, 1, vec![2], vec![], false),
+            &state,
+        );
+        assert_eq!(3, log.len());
+
+        // Only the highest ViewChange from each node is kept, and one outside the window doesn't
+        // replace it
+        log.add_message(
+            mock_msg(
+                PbftMessageType::ViewChange,
+                std::u64::MAX,
+                1,
+                vec![2],
+                vec![],
+                false,
+            ),
+            &state,
+        );
+        assert_eq!(
+            1,
+            log.get_messages_of_type_view(PbftMessageType::ViewChange, 1)
+                .len()
+        );
+        log.add_message(
+            mock_msg(PbftMessageType::ViewChange, 2, 1, vec![2], vec![], false),
+            &state,
+        );
+        log.add_message(
+            mock_msg(PbftMessageType::ViewChange, 1, 1, vec![2], vec![], false),
+            &state,
+        );
+        assert_eq!(3, log.len());
+        assert!(log
+            .get_messages_of_type_view(PbftMessageType::ViewChange, 1)
+            .is_empty());
+        assert!(log
+            .get_messages_of_type_view(PbftMessageType::ViewChange, std::u64::MAX)
+            .is_empty());
+        assert_eq!(
+            2,
+            log.get_messages_of_type_view(PbftMessageType::ViewChange, 2)
+                .len()
+        );
+
+        // The node's own messages are always accepted
+        log.add_message(
+            mock_msg(PbftMessageType::Commit, 5, 50, vec![0], vec![50], true),
+            &state,
+        );
+        assert_eq!(4, log.len());
+
+        // Node 1 has room for one more message; a duplicate isn't rejected
+        let commit = mock_msg(PbftMessageType::Commit, 0, 1, vec![1], vec![1], false);
+        log.add_message(commit.clone(), &state);
+        log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 2, vec![1], vec![2], false),
+            &state,
+        );
+        log.add_message(commit, &state);
+        assert_eq!(5, log.len());
+
+        let mut by_signer = BTreeMap::new();
+        by_signer.insert("01".to_string(), 2);
+        by_signer.insert("02".to_string(), 2);
+        assert_eq!(
+            &RejectedMessages {
+                outside_seq_window: 1,
+                outside_view_window: 2,
+                over_quota: 1,
+                by_signer,
+            },
+            log.rejected_messages()
+        );
+
+        // Once the node has moved on, node 1's old Commit is evicted to make room for a new one;
+        // other signers' old messages are left alone
+        state.seq_num = 12;
+        log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 12, vec![1], vec![12], false),
+            &state,
+        );
+        assert_eq!(
+            1,
+            log.get_messages_of_type_seq(PbftMessageType::Commit, 12)
+                .len()
+        );
+        assert!(log
+            .get_messages_of_type_seq(PbftMessageType::Commit, 1)
+            .is_empty());
+        assert_eq!(
+            1,
+            log.get_messages_of_type_seq(PbftMessageType::ViewChange, 1)
+                .len()
+        );
+        assert_eq!(1, log.rejected_messages().over_quota);
+
+        // Votes from a verified seal are added even if their signer has no room left
+        log.add_seal_vote(mock_msg(
+            PbftMessageType::Commit,
+            0,
+            12,
+            vec![1],
+            vec![13],
+            false,
+        ));
+        assert_eq!(
+            2,
+            log.get_messages_of_type_seq(PbftMessageType::Commit, 12)
+                .len()
+        );
+        assert_eq!(1, log.rejected_messages().over_quota);
+    }
 }
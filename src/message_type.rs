@@ -23,9 +23,10 @@ use std::fmt;
 use std::hash::{Hash, Hasher};
 
 use protobuf::Message;
-use sawtooth_sdk::consensus::engine::{BlockId, PeerMessage};
+use sawtooth_sdk::consensus::engine::PeerMessage;
 
 use crate::error::PbftError;
+use crate::host::BlockId;
 use crate::protos::pbft_message::{
     PbftMessage, PbftMessageInfo, PbftNewView, PbftSeal, PbftSignedVote,
 };
@@ -230,7 +231,7 @@ impl ParsedMessage {
 }
 
 // Messages related to PBFT consensus
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd)]
 pub enum PbftMessageType {
     /// Basic message types for the multicast protocol
     PrePrepare,
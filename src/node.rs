@@ -17,51 +17,177 @@
 
 //! The core PBFT algorithm
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::convert::From;
+use std::fmt;
+use std::time::Instant;
 
 use hex;
 use itertools::Itertools;
 use protobuf::{Message, RepeatedField};
-use sawtooth_sdk::consensus::engine::{Block, BlockId, PeerId, PeerInfo};
+use sawtooth_sdk::consensus::engine as sdk;
 use sawtooth_sdk::consensus::service::Service;
-use sawtooth_sdk::messages::consensus::ConsensusPeerMessageHeader;
-use sawtooth_sdk::signing::{create_context, secp256k1::Secp256k1PublicKey};
 
 use crate::config::{get_members_from_settings, PbftConfig};
 use crate::error::PbftError;
-use crate::hash::verify_sha512;
+use crate::events::{ConsensusEvent, EventSink};
+use crate::host::{Block, BlockId, ConsensusHost, PeerId, SawtoothHost};
 use crate::message_log::PbftLog;
 use crate::message_type::{ParsedMessage, PbftMessageType};
+use crate::participation::{ParticipationReport, ParticipationTracker};
 use crate::protos::pbft_message::{
-    PbftMessage, PbftMessageInfo, PbftNewView, PbftSeal, PbftSignedVote,
+    PbftBlockHeader, PbftFinalityProof, PbftMessage, PbftMessageInfo, PbftNewView, PbftSeal,
+    PbftSignedVote,
 };
 use crate::state::{PbftMode, PbftPhase, PbftState};
-use crate::timing::{retry_until_ok, Timeout};
+use crate::timing::{Backoff, Timeout};
+use crate::verification::{check_vote_count, verify_seal_with, verify_votes_with};
+
+/// How many member lists read from the host (for blocks that aren't in the member history) are
+/// kept
+const READ_MEMBERS_SIZE: usize = 16;
+
+/// How many pieces of work that are waiting for member lists to be read are kept; only blocks and
+/// seals for the next couple of sequence numbers need members, so work the node has moved past is
+/// dropped first, and the oldest work after that
+const DEFERRED_SIZE: usize = 16;
+
+/// How many peer messages that arrive while a block commit is pending are held to be handled once
+/// it is finished; if more arrive, the oldest are dropped
+const HELD_MESSAGES_SIZE: usize = 1024;
+
+/// A block commit that can't be finished until the members of the committed block are read; the
+/// node doesn't start the next sequence number until then, since quorums for it must be formed
+/// from the committed block's members
+struct PendingCommit {
+    block_id: BlockId,
+    previous_id: BlockId,
+    is_catching_up: bool,
+}
+
+/// Work that couldn't be done because the host couldn't provide the members of a block; it is
+/// done again once a read of some block's members succeeds
+#[derive(PartialEq)]
+enum Deferred {
+    /// Verify the seal of a block and handle the block
+    Block(Block),
+    /// Verify the seal of a seal response and catch up with it
+    SealResponse(ParsedMessage),
+}
+
+impl Deferred {
+    /// Whether the work is for a sequence number the node has already moved past
+    fn is_stale(&self, seq_num: u64) -> bool {
+        match self {
+            Deferred::Block(block) => block.block_num < seq_num,
+            Deferred::SealResponse(msg) => msg.info().get_seq_num() < seq_num,
+        }
+    }
+}
+
+impl fmt::Display for Deferred {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Deferred::Block(block) => write!(f, "block {}", hex::encode(&block.block_id)),
+            Deferred::SealResponse(msg) => write!(
+                f,
+                "seal for block {}",
+                hex::encode(&msg.get_seal().block_id)
+            ),
+        }
+    }
+}
 
 /// Contains the core logic of the PBFT node
 pub struct PbftNode {
-    /// Used for interactions with the validator
-    pub service: Box<dyn Service>,
+    /// Used for interactions with the validator (or whatever else the node runs on)
+    pub(crate) host: Box<dyn ConsensusHost>,
 
     /// Log of messages this node has received and accepted
-    pub msg_log: PbftLog,
+    pub(crate) msg_log: PbftLog,
+
+    /// Where consensus events are published, if anywhere
+    event_sink: Option<Box<dyn EventSink>>,
+
+    /// Whether the node is in the process of catching up using seals from the network
+    catching_up: bool,
+
+    /// Record of which members have been taking part in consensus recently
+    participation: ParticipationTracker,
+
+    /// Reads of member lists that failed and will be retried, by block ID
+    member_reads: BTreeMap<BlockId, Backoff>,
+
+    /// Member lists that were read from the host for blocks that aren't in the member history
+    read_members: VecDeque<(BlockId, Vec<PeerId>)>,
+
+    /// The last block commit, if it is waiting for the committed block's members to be read
+    pending_commit: Option<PendingCommit>,
+
+    /// Work that is waiting for member lists to be read, oldest first
+    deferred: VecDeque<Deferred>,
+
+    /// Peer messages that arrived while a block commit was pending, in the order they arrived
+    held_messages: VecDeque<ParsedMessage>,
+
+    /// How many blocks behind the chain head a block may be for this node to build a finality
+    /// proof for it
+    max_finality_proof_depth: u64,
 }
 
 impl PbftNode {
-    /// Construct a new PBFT node
+    /// Construct a new PBFT node that runs on a Sawtooth validator
     ///
     /// If the node is the primary on start-up, it initializes a new block on the chain
     pub fn new(
         config: &PbftConfig,
-        chain_head: Block,
-        connected_peers: Vec<PeerInfo>,
+        chain_head: sdk::Block,
+        connected_peers: Vec<sdk::PeerInfo>,
         service: Box<dyn Service>,
         state: &mut PbftState,
+    ) -> Self {
+        Self::with_host(
+            config,
+            chain_head.into(),
+            connected_peers
+                .into_iter()
+                .map(|peer| peer.peer_id)
+                .collect(),
+            Box::new(SawtoothHost::with_verifier(
+                service,
+                config.signature_scheme.verifier(),
+                config.verification_cache_size,
+            )),
+            state,
+        )
+    }
+
+    /// Construct a new PBFT node that runs on the given host, given the members it is already
+    /// connected to
+    ///
+    /// If the node is the primary on start-up, it initializes a new block on the chain
+    pub fn with_host(
+        config: &PbftConfig,
+        chain_head: Block,
+        connected_peers: Vec<PeerId>,
+        host: Box<dyn ConsensusHost>,
+        state: &mut PbftState,
     ) -> Self {
         let mut n = PbftNode {
-            service,
+            host,
             msg_log: PbftLog::new(config),
+            event_sink: None,
+            catching_up: false,
+            participation: ParticipationTracker::new(
+                config.participation_window,
+                config.participation_threshold,
+            ),
+            member_reads: BTreeMap::new(),
+            read_members: VecDeque::new(),
+            pending_commit: None,
+            deferred: VecDeque::new(),
+            held_messages: VecDeque::new(),
+            max_finality_proof_depth: config.max_finality_proof_depth,
         };
 
         // Add chain head to log and update state
@@ -77,8 +203,8 @@ impl PbftNode {
                 info!("Updated view to {} on startup", state.view);
             }
             // If connected to any peers already, send bootstrap commit messages to them
-            for peer in connected_peers {
-                n.broadcast_bootstrap_commit(peer.peer_id, state)
+            for peer_id in connected_peers {
+                n.broadcast_bootstrap_commit(peer_id, state)
                     .unwrap_or_else(|err| {
                         error!("Failed to broadcast bootstrap commit due to error: {}", err)
                     });
@@ -87,7 +213,7 @@ impl PbftNode {
 
         // Primary initializes a block
         if state.is_primary() {
-            n.service.initialize_block(None).unwrap_or_else(|err| {
+            n.host.initialize_block(None).unwrap_or_else(|err| {
                 error!("Couldn't initialize block on startup due to error: {}", err)
             });
         }
@@ -108,6 +234,26 @@ impl PbftNode {
     ) -> Result<(), PbftError> {
         trace!("{}: Got peer message: {}", state, msg.info());
 
+        // The node can't take part in consensus until it knows the members of the last committed
+        // block; hold the message until then
+        if self.pending_commit.is_some() {
+            debug!(
+                "{}: Waiting for the members of the last committed block; holding message",
+                state
+            );
+            self.held_messages.push_back(msg);
+            if self.held_messages.len() > HELD_MESSAGES_SIZE {
+                if let Some(dropped) = self.held_messages.pop_front() {
+                    warn!(
+                        "{}: Too many messages arrived while waiting for members; dropping {}",
+                        state,
+                        dropped.info()
+                    );
+                }
+            }
+            return Ok(());
+        }
+
         // Make sure this message is from a known member of the PBFT network
         if !state.member_ids.contains(&msg.info().signer_id) {
             return Err(PbftError::InvalidMessage(format!(
@@ -118,6 +264,15 @@ impl PbftNode {
 
         let msg_type = PbftMessageType::from(msg.info().msg_type.as_str());
 
+        self.participation.record_message(
+            msg_type,
+            msg.info().get_view(),
+            msg.info().get_seq_num(),
+            msg.info().get_signer_id(),
+            state.seq_num,
+            state.view,
+        );
+
         // If this node is in the process of a view change, ignore all messages except ViewChanges
         // and NewViews
         if match state.mode {
@@ -202,17 +357,19 @@ impl PbftNode {
             .collect::<Vec<_>>();
 
         if !mismatched_blocks.is_empty() {
-            self.start_view_change(state, state.view + 1)?;
-            return Err(PbftError::FaultyPrimary(format!(
+            let reason = format!(
                 "When checking PrePrepare with block {:?}, found PrePrepare(s) with same view and \
                  seq num but mismatched block(s): {:?}",
                 hex::encode(&msg.get_block_id()),
                 mismatched_blocks,
-            )));
+            );
+            self.publish_faulty_primary(state, &reason);
+            self.start_view_change(state, state.view + 1)?;
+            return Err(PbftError::FaultyPrimary(reason));
         }
 
         // Add message to the log
-        self.msg_log.add_message(msg.clone());
+        self.msg_log.add_message(msg.clone(), state);
 
         // If the node is in the PrePreparing phase, this message is for the current sequence
         // number, and the node already has this block: switch to Preparing
@@ -243,14 +400,16 @@ impl PbftNode {
 
         // The primary is not allowed to send a Prepare; its PrePrepare counts as its "vote"
         if PeerId::from(info.get_signer_id()) == state.get_primary_id() {
-            self.start_view_change(state, state.view + 1)?;
-            return Err(PbftError::FaultyPrimary(format!(
+            let reason = format!(
                 "Received Prepare from primary at view {}, seq_num {}",
                 state.view, state.seq_num
-            )));
+            );
+            self.publish_faulty_primary(state, &reason);
+            self.start_view_change(state, state.view + 1)?;
+            return Err(PbftError::FaultyPrimary(reason));
         }
 
-        self.msg_log.add_message(msg);
+        self.msg_log.add_message(msg, state);
 
         // If this message is for the current sequence number and the node is in the Preparing
         // phase, check if the node is ready to move on to the Committing phase
@@ -309,7 +468,7 @@ impl PbftNode {
             )));
         }
 
-        self.msg_log.add_message(msg);
+        self.msg_log.add_message(msg, state);
 
         // If this message is for the current sequence number and the node is in the Committing
         // phase, check if the node is ready to commit the block
@@ -333,7 +492,7 @@ impl PbftNode {
                 .len() as u64
                 > 2 * state.f;
             if has_matching_pre_prepare && has_required_commits {
-                self.service.commit_block(block_id.clone()).map_err(|err| {
+                self.host.commit_block(block_id.clone()).map_err(|err| {
                     PbftError::ServiceError(
                         format!("Failed to commit block {:?}", hex::encode(&block_id)),
                         err,
@@ -372,7 +531,7 @@ impl PbftNode {
             return Ok(());
         }
 
-        self.msg_log.add_message(msg.clone());
+        self.msg_log.add_message(msg.clone(), state);
 
         // Even if the node hasn't detected a faulty primary yet, start view changing if there are
         // f + 1 ViewChange messages in the log for this proposed view (but if already view
@@ -410,6 +569,7 @@ impl PbftNode {
                     .view_change_duration
                     .checked_mul((msg_view - state.view) as u32)
                     .expect("View change timeout has overflowed"),
+                state.clock.clone(),
             );
             state.view_change_timeout.start();
         }
@@ -469,7 +629,7 @@ impl PbftNode {
 
         // If this node was the primary before, cancel any block that may have been initialized
         if state.is_primary() {
-            self.service.cancel_block().unwrap_or_else(|err| {
+            self.host.cancel_block().unwrap_or_else(|err| {
                 info!("Failed to cancel block when becoming secondary: {:?}", err);
             });
         }
@@ -480,6 +640,11 @@ impl PbftNode {
 
         info!("{}: Updated to view {}", state, state.view);
 
+        self.publish_event(ConsensusEvent::ViewChangeCompleted {
+            view: state.view,
+            primary: hex::encode(state.get_primary_id()),
+        });
+
         // Reset state to Normal mode, reset the phase (unless waiting for a BlockCommit) and
         // restart the idle timeout
         state.mode = PbftMode::Normal;
@@ -493,7 +658,7 @@ impl PbftNode {
 
         // Initialize a new block if this node is the new primary
         if state.is_primary() {
-            self.service.initialize_block(None).map_err(|err| {
+            self.host.initialize_block(None).map_err(|err| {
                 PbftError::ServiceError("Couldn't initialize block after view change".into(), err)
             })?;
         }
@@ -517,7 +682,7 @@ impl PbftNode {
         if state.seq_num == msg.info().get_seq_num() + 1 {
             return self.send_seal_response(state, &msg.info().get_signer_id().to_vec());
         } else if state.seq_num == msg.info().get_seq_num() {
-            self.msg_log.add_message(msg);
+            self.msg_log.add_message(msg, state);
         }
         Ok(())
     }
@@ -569,6 +734,16 @@ impl PbftNode {
             Ok(_) => {
                 trace!("Consensus seal passed verification");
             }
+            Err(err @ PbftError::MembersUnavailable(_)) => {
+                warn!(
+                    "Couldn't verify seal for block {} yet; will try again once the members can \
+                     be read: {}",
+                    hex::encode(&seal.block_id),
+                    err
+                );
+                self.defer(Deferred::SealResponse(msg.clone()), state);
+                return Ok(());
+            }
             Err(err) => {
                 return Err(PbftError::InvalidMessage(format!(
                     "Consensus seal failed verification - Error was: {}",
@@ -596,7 +771,7 @@ impl PbftNode {
 
         // Only future blocks should be considered since committed blocks are final
         if block.block_num < state.seq_num {
-            self.service
+            self.host
                 .fail_block(block.block_id.clone())
                 .unwrap_or_else(|err| error!("Couldn't fail block due to error: {:?}", err));
             return Err(PbftError::InternalError(format!(
@@ -617,7 +792,7 @@ impl PbftNode {
                     .get_unvalidated_block_with_id(block.previous_id.as_slice())
             });
         if previous_block.is_none() {
-            self.service
+            self.host
                 .fail_block(block.block_id.clone())
                 .unwrap_or_else(|err| error!("Couldn't fail block due to error: {:?}", err));
             return Err(PbftError::InternalError(format!(
@@ -632,7 +807,7 @@ impl PbftNode {
         // are strictly monotically increasing by 1)
         let previous_block = previous_block.expect("Previous block's existence already checked");
         if previous_block.block_num != block.block_num - 1 {
-            self.service
+            self.host
                 .fail_block(block.block_id.clone())
                 .unwrap_or_else(|err| error!("Couldn't fail block due to error: {:?}", err));
             return Err(PbftError::InternalError(format!(
@@ -649,7 +824,7 @@ impl PbftNode {
         self.msg_log.add_unvalidated_block(block.clone());
 
         // Have the validator check the block
-        self.service
+        self.host
             .check_blocks(vec![block.block_id.clone()])
             .map_err(|err| {
                 PbftError::ServiceError(
@@ -705,17 +880,36 @@ impl PbftNode {
             return Ok(());
         }
 
-        let seal = self
-            .verify_consensus_seal_from_block(&block, state)
-            .map_err(|err| {
-                self.service
+        // Blocks can't be handled until the members of the last committed block are known
+        if self.pending_commit.is_some() {
+            self.defer(Deferred::Block(block), state);
+            return Ok(());
+        }
+
+        let seal = match self.verify_consensus_seal_from_block(&block, state) {
+            Ok(seal) => seal,
+            // The members needed to verify the seal couldn't be read; handle the block once they
+            // can be, rather than failing a block that may be valid
+            Err(err @ PbftError::MembersUnavailable(_)) => {
+                warn!(
+                    "Couldn't verify seal of block {} yet; will try again once the members can \
+                     be read: {}",
+                    hex::encode(&block.block_id),
+                    err
+                );
+                self.defer(Deferred::Block(block), state);
+                return Ok(());
+            }
+            Err(err) => {
+                self.host
                     .fail_block(block.block_id.clone())
                     .unwrap_or_else(|err| error!("Couldn't fail block due to error: {:?}", err));
-                PbftError::InvalidMessage(format!(
+                return Err(PbftError::InvalidMessage(format!(
                     "Consensus seal failed verification - Error was: {}",
                     err
-                ))
-            })?;
+                )));
+            }
+        };
 
         // This block's seal can be used to commit the block previous to it (i.e. catch-up) if it's
         // a future block and the node isn't waiting for a commit message for a previous block (if
@@ -764,7 +958,7 @@ impl PbftNode {
         }
 
         // Fail the block
-        self.service
+        self.host
             .fail_block(block_id)
             .unwrap_or_else(|err| error!("Couldn't fail block due to error: {:?}", err));
 
@@ -783,6 +977,14 @@ impl PbftNode {
             state, state.seq_num
         );
 
+        if !self.catching_up {
+            self.catching_up = true;
+            self.publish_event(ConsensusEvent::CatchupStarted {
+                seq_num: state.seq_num,
+                block_id: hex::encode(&seal.block_id),
+            });
+        }
+
         let messages = seal
             .get_commit_votes()
             .iter()
@@ -791,20 +993,23 @@ impl PbftNode {
                 Ok(msgs)
             })?;
 
-        // Update view if necessary
-        let view = messages[0].info().get_view();
-        if view != state.view {
+        // Update view if necessary; the seal's votes have been verified to be from the seal's
+        // view, but the seal may not have any. A node that has already moved on to a later view
+        // must never go back to an earlier one.
+        let view = seal.get_info().get_view();
+        if view > state.view {
             info!("Updating view from {} to {}", state.view, view);
             state.view = view;
         }
 
-        // Add messages to the log
+        // Add messages to the log; they have been verified as part of the seal, so they don't
+        // count against their signers' quotas
         for message in &messages {
-            self.msg_log.add_message(message.clone());
+            self.msg_log.add_seal_vote(message.clone());
         }
 
         // Commit the block, stop the idle timeout, and skip straight to Finishing
-        self.service
+        self.host
             .commit_block(seal.block_id.clone())
             .map_err(|err| {
                 PbftError::ServiceError(
@@ -855,7 +1060,7 @@ impl PbftNode {
             .collect::<Vec<_>>();
 
         for id in invalid_block_ids {
-            self.service.fail_block(id.clone()).unwrap_or_else(|err| {
+            self.host.fail_block(id.clone()).unwrap_or_else(|err| {
                 error!(
                     "Couldn't fail block {:?} due to error: {:?}",
                     &hex::encode(id),
@@ -868,7 +1073,19 @@ impl PbftNode {
         state.seq_num += 1;
         state.mode = PbftMode::Normal;
         state.phase = PbftPhase::PrePreparing;
-        state.chain_head = block_id.clone();
+        let previous_head = std::mem::replace(&mut state.chain_head, block_id.clone());
+
+        self.publish_block_finalized(&block_id, state);
+
+        // The node has finished catching up once it commits a block that it wasn't told to catch
+        // up past
+        if self.catching_up && !is_catching_up {
+            self.catching_up = false;
+            self.publish_event(ConsensusEvent::CatchupFinished {
+                seq_num: state.seq_num - 1,
+                block_id: hex::encode(&block_id),
+            });
+        }
 
         // If node(s) are waiting for a seal to commit the last block, send it now
         let requesters = self
@@ -884,8 +1101,47 @@ impl PbftNode {
             });
         }
 
-        // Update membership if necessary
-        self.update_membership(block_id.clone(), state);
+        // Update membership if necessary; the next sequence number can't be started until the
+        // members of the committed block are known, or quorums could be formed from the wrong
+        // members
+        if let Err(err) = self.update_membership(
+            block_id.clone(),
+            previous_head.clone(),
+            state.seq_num - 1,
+            state,
+        ) {
+            warn!(
+                "{}: Waiting to read the members of block {} before continuing: {}",
+                state,
+                hex::encode(&block_id),
+                err
+            );
+            self.pending_commit = Some(PendingCommit {
+                block_id,
+                previous_id: previous_head,
+                is_catching_up,
+            });
+            return Ok(());
+        }
+
+        self.finish_commit(block_id, is_catching_up, state)
+    }
+
+    /// Finish handling a block commit once the committed block's members are known: report on
+    /// participation, make any forced view change, garbage collect the logs, and move on to the
+    /// next block
+    fn finish_commit(
+        &mut self,
+        block_id: BlockId,
+        is_catching_up: bool,
+        state: &mut PbftState,
+    ) -> Result<(), PbftError> {
+        // Periodically report on members that haven't been taking part in consensus
+        self.participation.prune(state.seq_num, state.view);
+        let window = self.participation.window();
+        if window > 0 && (state.seq_num - 1) % window == 0 {
+            self.log_participation(state);
+        }
 
         // Increment the view if a view change must be forced for fairness
         if state.at_forced_view_change() {
@@ -951,35 +1207,41 @@ impl PbftNode {
                 state,
                 hex::encode(&block_id)
             );
-            self.service
-                .initialize_block(Some(block_id))
-                .map_err(|err| {
-                    PbftError::ServiceError("Couldn't initialize block after commit".into(), err)
-                })?;
+            self.host.initialize_block(Some(block_id)).map_err(|err| {
+                PbftError::ServiceError("Couldn't initialize block after commit".into(), err)
+            })?;
         }
 
         Ok(())
     }
 
-    /// Check the on-chain list of members; if it has changed, update members list and return true.
+    /// Check the on-chain list of members of the block that was just committed; if it has
+    /// changed, update the members list. If the members can't be read yet, a
+    /// `MembersUnavailable` error is returned.
     ///
     /// # Panics
     /// + If the `sawtooth.consensus.pbft.members` setting is unset or invalid
     /// + If the network this node is on does not have enough nodes to be Byzantine fault tolernant
-    fn update_membership(&mut self, block_id: BlockId, state: &mut PbftState) {
-        // Get list of members from settings (retry until a valid result is received)
+    fn update_membership(
+        &mut self,
+        block_id: BlockId,
+        previous_id: BlockId,
+        block_num: u64,
+        state: &mut PbftState,
+    ) -> Result<(), PbftError> {
+        // Only the members of the last committed block are current
+        if block_num + 1 != state.seq_num {
+            return Ok(());
+        }
+
         trace!("Getting on-chain list of members to check for membership updates");
-        let settings = retry_until_ok(
-            state.exponential_retry_base,
-            state.exponential_retry_max,
-            || {
-                self.service.get_settings(
-                    block_id.clone(),
-                    vec![String::from("sawtooth.consensus.pbft.members")],
-                )
-            },
-        );
-        let on_chain_members = get_members_from_settings(&settings);
+        let on_chain_members = self.get_on_chain_members(block_id.clone(), state)?;
+
+        // Remember the members of the committed block for verifying the seal that proves its
+        // successor
+        state
+            .member_history
+            .record(block_num, block_id, &previous_id, on_chain_members.clone());
 
         if on_chain_members != state.member_ids {
             info!("Updating membership: {:?}", on_chain_members);
@@ -990,6 +1252,8 @@ impl PbftNode {
             }
             state.f = f as u64;
         }
+
+        Ok(())
     }
 
     /// When the node has a block and a corresponding PrePrepare for its current sequence number,
@@ -1096,7 +1360,7 @@ impl PbftNode {
             PbftError::SerializationError("Error writing commit to bytes".into(), err)
         })?;
 
-        self.service
+        self.host
             .send_to(
                 &peer_id,
                 String::from(PbftMessageType::Commit).as_str(),
@@ -1178,91 +1442,6 @@ impl PbftNode {
         Ok(seal)
     }
 
-    /// Verify that a vote matches the expected type, is properly signed, and passes the specified
-    /// criteria; if it passes verification, return the signer ID to be used for further
-    /// verification
-    fn verify_vote<F>(
-        vote: &PbftSignedVote,
-        expected_type: PbftMessageType,
-        validation_criteria: F,
-    ) -> Result<PeerId, PbftError>
-    where
-        F: Fn(&PbftMessage) -> Result<(), PbftError>,
-    {
-        // Parse the message
-        let pbft_message: PbftMessage = protobuf::parse_from_bytes(&vote.get_message_bytes())
-            .map_err(|err| {
-                PbftError::SerializationError("Error parsing PbftMessage from vote".into(), err)
-            })?;
-        let header: ConsensusPeerMessageHeader =
-            protobuf::parse_from_bytes(&vote.get_header_bytes()).map_err(|err| {
-                PbftError::SerializationError("Error parsing header from vote".into(), err)
-            })?;
-
-        trace!(
-            "Verifying vote with PbftMessage: {:?} and header: {:?}",
-            pbft_message,
-            header
-        );
-
-        // Verify the header's signer matches the PbftMessage's signer
-        if header.signer_id != pbft_message.get_info().get_signer_id() {
-            return Err(PbftError::InvalidMessage(format!(
-                "Received a vote where PbftMessage's signer ID ({:?}) and PeerMessage's signer ID \
-                 ({:?}) don't match",
-                pbft_message.get_info().get_signer_id(),
-                header.signer_id
-            )));
-        }
-
-        // Verify the message type
-        let msg_type = PbftMessageType::from(pbft_message.get_info().get_msg_type());
-        if msg_type != expected_type {
-            return Err(PbftError::InvalidMessage(format!(
-                "Received a {:?} vote, but expected a {:?}",
-                msg_type, expected_type
-            )));
-        }
-
-        // Verify the signature
-        let key = Secp256k1PublicKey::from_hex(&hex::encode(&header.signer_id)).map_err(|err| {
-            PbftError::SigningError(format!(
-                "Couldn't parse public key from signer ID ({:?}) due to error: {:?}",
-                header.signer_id, err
-            ))
-        })?;
-        let context = create_context("secp256k1").map_err(|err| {
-            PbftError::SigningError(format!("Couldn't create context due to error: {}", err))
-        })?;
-
-        match context.verify(
-            &hex::encode(vote.get_header_signature()),
-            vote.get_header_bytes(),
-            &key,
-        ) {
-            Ok(true) => {}
-            Ok(false) => {
-                return Err(PbftError::SigningError(format!(
-                    "Vote ({}) failed signature verification",
-                    vote
-                )));
-            }
-            Err(err) => {
-                return Err(PbftError::SigningError(format!(
-                    "Error while verifying vote signature: {:?}",
-                    err
-                )));
-            }
-        }
-
-        verify_sha512(vote.get_message_bytes(), header.get_content_sha512())?;
-
-        // Validate against the specified criteria
-        validation_criteria(&pbft_message)?;
-
-        Ok(PeerId::from(pbft_message.get_info().get_signer_id()))
-    }
-
     /// Verify that a NewView messsage is valid
     fn verify_new_view(
         &mut self,
@@ -1288,27 +1467,26 @@ impl PbftNode {
             )));
         }
 
+        check_vote_count(new_view.get_view_changes(), &state.member_ids)?;
+
         // Verify each individual vote and extract the signer ID from each ViewChange so the IDs
         // can be verified
-        let voter_ids =
-            new_view
-                .get_view_changes()
-                .iter()
-                .try_fold(HashSet::new(), |mut ids, vote| {
-                    Self::verify_vote(vote, PbftMessageType::ViewChange, |msg| {
-                        if msg.get_info().get_view() != new_view.get_info().get_view() {
-                            return Err(PbftError::InvalidMessage(format!(
-                                "ViewChange's view number ({}) doesn't match NewView's view \
-                                 number ({})",
-                                msg.get_info().get_view(),
-                                new_view.get_info().get_view(),
-                            )));
-                        }
-                        Ok(())
-                    })
-                    .and_then(|id| Ok(ids.insert(id)))?;
-                    Ok(ids)
-                })?;
+        let host = &self.host;
+        let voter_ids = verify_votes_with(
+            new_view.get_view_changes(),
+            PbftMessageType::ViewChange,
+            &|votes| host.authenticate_votes(votes),
+            |msg| {
+                if msg.get_info().get_view() != new_view.get_info().get_view() {
+                    return Err(PbftError::InvalidMessage(format!(
+                        "ViewChange's view number ({}) doesn't match NewView's view number ({})",
+                        msg.get_info().get_view(),
+                        new_view.get_info().get_view(),
+                    )));
+                }
+                Ok(())
+            },
+        )?;
 
         // All of the votes must come from PBFT members, and the primary can't explicitly vote
         // itself, since broacasting the NewView is an implicit vote. Check that the votes received
@@ -1409,153 +1587,399 @@ impl PbftNode {
         previous_id: BlockId,
         state: &mut PbftState,
     ) -> Result<(), PbftError> {
-        // Verify each individual vote and extract the signer ID from each PbftMessage so the IDs
-        // can be verified
-        let voter_ids =
-            seal.get_commit_votes()
-                .iter()
-                .try_fold(HashSet::new(), |mut ids, vote| {
-                    Self::verify_vote(vote, PbftMessageType::Commit, |msg| {
-                        // Make sure all votes are for the right block
-                        if msg.block_id != seal.block_id {
-                            return Err(PbftError::InvalidMessage(format!(
-                                "Commit vote's block ID ({:?}) doesn't match seal's ID ({:?})",
-                                msg.block_id, seal.block_id
-                            )));
-                        }
-                        // Make sure all votes are for the right view
-                        if msg.get_info().get_view() != seal.get_info().get_view() {
-                            return Err(PbftError::InvalidMessage(format!(
-                                "Commit vote's view ({:?}) doesn't match seal's view ({:?})",
-                                msg.get_info().get_view(),
-                                seal.get_info().get_view()
-                            )));
-                        }
-                        // Make sure all votes are for the right sequence number
-                        if msg.get_info().get_seq_num() != seal.get_info().get_seq_num() {
-                            return Err(PbftError::InvalidMessage(format!(
-                                "Commit vote's seq_num ({:?}) doesn't match seal's seq_num ({:?})",
-                                msg.get_info().get_seq_num(),
-                                seal.get_info().get_seq_num()
-                            )));
-                        }
-                        Ok(())
-                    })
-                    .and_then(|id| Ok(ids.insert(id)))?;
-                    Ok(ids)
-                })?;
-
-        // All of the votes in a seal must come from PBFT members, and the primary can't explicitly
-        // vote itself, since building a consensus seal is an implicit vote. Check that the votes
-        // received are from a subset of "members - seal creator". Use the list of members from the
-        // block previous to the one this seal verifies, since that represents the state of the
-        // network at the time this block was voted on.
+        // Use the list of members from the block previous to the one this seal verifies, since
+        // that represents the state of the network at the time this block was voted on.
         trace!("Getting on-chain list of members to verify seal");
-        let settings = retry_until_ok(
-            state.exponential_retry_base,
-            state.exponential_retry_max,
-            || {
-                self.service.get_settings(
-                    previous_id.clone(),
-                    vec![String::from("sawtooth.consensus.pbft.members")],
-                )
-            },
-        );
-        let members = get_members_from_settings(&settings);
+        let members = self.get_on_chain_members(previous_id, state)?;
 
-        // Verify that the seal's signer is a PBFT member
-        if !members.contains(&seal.get_info().get_signer_id().to_vec()) {
-            return Err(PbftError::InvalidMessage(format!(
-                "Consensus seal is signed by an unknown peer: {:?}",
-                seal.get_info().get_signer_id()
-            )));
-        }
-
-        let peer_ids: HashSet<_> = members
-            .iter()
-            .cloned()
-            .filter(|pid| pid.as_slice() != seal.get_info().get_signer_id())
-            .collect();
+        let host = &self.host;
+        let voter_ids = verify_seal_with(seal, &members, state.f, &|votes| {
+            host.authenticate_votes(votes)
+        })?;
+        self.participation
+            .record_seal_votes(seal.get_info().get_seq_num(), voter_ids);
 
-        trace!(
-            "Comparing voter IDs ({:?}) with on-chain member IDs - primary ({:?})",
-            voter_ids,
-            peer_ids
-        );
+        Ok(())
+    }
 
-        if !voter_ids.is_subset(&peer_ids) {
-            return Err(PbftError::InvalidMessage(format!(
-                "Consensus seal contains vote(s) from invalid ID(s): {:?}",
-                voter_ids.difference(&peer_ids).collect::<Vec<_>>()
-            )));
+    /// Get the list of members from the on-chain settings at the given block; the member history
+    /// and earlier reads are checked first, and the host is only asked if the block's members
+    /// aren't known
+    ///
+    /// The host is asked once; if it can't provide the members, a `MembersUnavailable` error is
+    /// returned and the read is retried with exponential backoff by `retry_member_reads`, so a
+    /// slow or unavailable validator never blocks the node.
+    ///
+    /// # Panics
+    /// + If the `sawtooth.consensus.pbft.members` setting is unset or invalid
+    fn get_on_chain_members(
+        &mut self,
+        block_id: BlockId,
+        state: &PbftState,
+    ) -> Result<Vec<PeerId>, PbftError> {
+        if let Some(members) = state.member_history.get(&block_id) {
+            return Ok(members.to_vec());
+        }
+        if let Some((_, members)) = self.read_members.iter().find(|(id, _)| id == &block_id) {
+            return Ok(members.clone());
         }
 
-        // Check that the seal contains 2f votes (primary vote is implicit, so total of 2f + 1)
-        if (voter_ids.len() as u64) < 2 * state.f {
-            return Err(PbftError::InvalidMessage(format!(
-                "Consensus seal needs {} votes, but only {} found",
-                2 * state.f,
-                voter_ids.len()
+        // Wait for the scheduled retry instead of asking again right away
+        if self.member_reads.contains_key(&block_id) {
+            return Err(PbftError::MembersUnavailable(format!(
+                "Still waiting to read the members of block {}",
+                hex::encode(&block_id)
             )));
         }
 
-        Ok(())
+        self.read_members_from_host(&block_id).map_err(|err| {
+            let mut backoff = Backoff::new(
+                state.exponential_retry_base,
+                state.exponential_retry_max,
+                state.clock.now(),
+            );
+            backoff.failed(state.clock.now());
+            self.member_reads.insert(block_id, backoff);
+            PbftError::MembersUnavailable(err.to_string())
+        })
     }
 
-    // ---------- Methods called in the main engine loop to periodically check and update state ----------
-
-    /// At a regular interval, try to finalize a block when the primary is ready
-    pub fn try_publish(&mut self, state: &mut PbftState) -> Result<(), PbftError> {
-        // Only the primary takes care of this, and we try publishing a block
-        // on every engine loop, even if it's not yet ready. This isn't an error,
-        // so just return Ok(()).
-        if !state.is_primary() || state.phase != PbftPhase::PrePreparing {
-            return Ok(());
+    /// Get the list of members from the on-chain settings at the given block without scheduling
+    /// a retry or remembering the members if they have to be read from the host; used for
+    /// queries that aren't part of consensus
+    ///
+    /// # Panics
+    /// + If the `sawtooth.consensus.pbft.members` setting is unset or invalid
+    fn lookup_on_chain_members(
+        &mut self,
+        block_id: &[u8],
+        state: &PbftState,
+    ) -> Result<Vec<PeerId>, PbftError> {
+        if let Some(members) = state.member_history.get(block_id) {
+            return Ok(members.to_vec());
         }
-
-        trace!("{}: Attempting to summarize block", state);
-
-        match self.service.summarize_block() {
-            Ok(_) => {}
-            Err(err) => {
-                trace!("Couldn't summarize, so not finalizing: {}", err);
-                return Ok(());
-            }
+        if let Some((_, members)) = self.read_members.iter().find(|(id, _)| id == block_id) {
+            return Ok(members.clone());
         }
+        self.fetch_members_from_host(block_id)
+    }
 
-        // We don't publish a consensus seal at block 1, since we never receive any
-        // votes on the genesis block. Leave payload blank for the first block.
-        let data = if state.seq_num <= 1 {
-            vec![]
-        } else {
-            self.build_seal(state)?.write_to_bytes().map_err(|err| {
-                PbftError::SerializationError("Error writing seal to bytes".into(), err)
-            })?
-        };
+    /// Ask the host for the members in the on-chain settings at the given block, remembering them
+    /// if they are read
+    fn read_members_from_host(&mut self, block_id: &[u8]) -> Result<Vec<PeerId>, PbftError> {
+        let members = self.fetch_members_from_host(block_id)?;
 
-        match self.service.finalize_block(data) {
-            Ok(block_id) => {
-                info!("{}: Publishing block {}", state, hex::encode(&block_id));
-                Ok(())
-            }
-            Err(err) => Err(PbftError::ServiceError(
-                "Couldn't finalize block".into(),
-                err,
-            )),
+        self.read_members
+            .push_back((block_id.to_vec(), members.clone()));
+        if self.read_members.len() > READ_MEMBERS_SIZE {
+            self.read_members.pop_front();
         }
+
+        Ok(members)
     }
 
-    /// Check to see if the idle timeout has expired
-    pub fn check_idle_timeout_expired(&mut self, state: &mut PbftState) -> bool {
-        state.idle_timeout.check_expired()
+    /// Ask the host for the members in the on-chain settings at the given block
+    fn fetch_members_from_host(&mut self, block_id: &[u8]) -> Result<Vec<PeerId>, PbftError> {
+        let settings = self
+            .host
+            .get_settings(
+                block_id.to_vec(),
+                vec![String::from("sawtooth.consensus.pbft.members")],
+            )
+            .map_err(|err| {
+                PbftError::ServiceError(
+                    format!(
+                        "Couldn't read the members of block {}",
+                        hex::encode(block_id)
+                    ),
+                    err,
+                )
+            })?;
+        Ok(get_members_from_settings(&settings))
     }
 
-    /// Start the idle timeout
-    pub fn start_idle_timeout(&self, state: &mut PbftState) {
-        state.idle_timeout.start();
+    /// The time at which the next retry of a member read is due, if any reads are waiting to be
+    /// retried
+    pub fn next_member_read(&self) -> Option<Instant> {
+        self.member_reads.values().map(Backoff::next_attempt).min()
     }
 
-    /// Check to see if the commit timeout has expired
+    /// Retry any reads of member lists that are due; once one succeeds, redo the work that was
+    /// waiting for members
+    pub fn retry_member_reads(&mut self, state: &mut PbftState) {
+        let now = state.clock.now();
+        let due = self
+            .member_reads
+            .iter()
+            .filter(|(_, backoff)| backoff.is_due(now))
+            .map(|(block_id, _)| block_id.clone())
+            .collect::<Vec<_>>();
+
+        let mut any_read = false;
+        for block_id in due {
+            match self.read_members_from_host(&block_id) {
+                Ok(_) => {
+                    self.member_reads.remove(&block_id);
+                    any_read = true;
+                }
+                Err(err) => {
+                    debug!("Retrying member read failed: {}", err);
+                    if let Some(backoff) = self.member_reads.get_mut(&block_id) {
+                        backoff.failed(now);
+                    }
+                }
+            }
+        }
+
+        if any_read {
+            self.redo_deferred(state);
+        }
+    }
+
+    /// Defer work until member lists can be read; if too much work is waiting, work for sequence
+    /// numbers the node has moved past is dropped, then the oldest work (with a warning)
+    fn defer(&mut self, work: Deferred, state: &PbftState) {
+        if self.deferred.contains(&work) {
+            return;
+        }
+        self.deferred.push_back(work);
+        if self.deferred.len() > DEFERRED_SIZE {
+            self.deferred.retain(|work| !work.is_stale(state.seq_num));
+        }
+        while self.deferred.len() > DEFERRED_SIZE {
+            if let Some(dropped) = self.deferred.pop_front() {
+                warn!(
+                    "{}: Too much work is waiting for member lists; dropping {}",
+                    state, dropped
+                );
+            }
+        }
+    }
+
+    /// Redo all work that was waiting for member lists to be read, starting with the pending
+    /// commit (if any) since nothing else can be done until it is finished, and then the peer
+    /// messages that were held while it was pending; work that still can't be done is deferred
+    /// again
+    fn redo_deferred(&mut self, state: &mut PbftState) {
+        if let Some(commit) = self.pending_commit.take() {
+            match self.update_membership(
+                commit.block_id.clone(),
+                commit.previous_id.clone(),
+                state.seq_num - 1,
+                state,
+            ) {
+                Ok(()) => {
+                    info!(
+                        "{}: Read the members of block {}; continuing",
+                        state,
+                        hex::encode(&commit.block_id)
+                    );
+                    if let Err(err) =
+                        self.finish_commit(commit.block_id, commit.is_catching_up, state)
+                    {
+                        warn!("Finishing block commit failed: {}", err);
+                    }
+
+                    for msg in std::mem::replace(&mut self.held_messages, VecDeque::new()) {
+                        if let Err(err) = self.on_peer_message(msg, state) {
+                            warn!("Handling held message failed: {}", err);
+                        }
+                    }
+                }
+                Err(err) => {
+                    debug!("Still can't finish block commit: {}", err);
+                    self.pending_commit = Some(commit);
+                    return;
+                }
+            }
+        }
+
+        for work in std::mem::replace(&mut self.deferred, VecDeque::new()) {
+            let result = match work {
+                Deferred::Block(block) => {
+                    if block.block_num < state.seq_num {
+                        continue;
+                    }
+                    self.try_handling_block(block, state)
+                }
+                Deferred::SealResponse(msg) => self.handle_seal_response(&msg, state),
+            };
+            if let Err(err) = result {
+                warn!("Deferred work failed: {}", err);
+            }
+        }
+
+        // Reads that no work is waiting for anymore don't need to be retried
+        if self.deferred.is_empty() && self.pending_commit.is_none() {
+            self.member_reads.clear();
+        }
+    }
+
+    /// Get the block with the given ID from the log, or from the validator if it's not in the log
+    fn get_block(&mut self, block_id: &[u8]) -> Result<Block, PbftError> {
+        if let Some(block) = self.msg_log.get_block_with_id(block_id) {
+            return Ok(block.clone());
+        }
+
+        self.host
+            .get_blocks(vec![block_id.to_vec()])
+            .map_err(|err| PbftError::ServiceError("Couldn't get block".into(), err))?
+            .remove(block_id)
+            .ok_or_else(|| {
+                PbftError::InternalError(format!("Block not found: {}", hex::encode(block_id)))
+            })
+    }
+
+    /// Build a self-contained proof that the given block was committed by the network
+    ///
+    /// The proof contains the block's header, the seal for the block, and the on-chain members at
+    /// the block's predecessor (the members the seal must be verified against). The seal is taken
+    /// from the payload of the block's successor; if the block is this node's chain head, it has
+    /// no successor yet, so the seal is built from this node's log instead.
+    ///
+    /// The proof only binds the block ID: the seal's votes sign the ID, but the header is built
+    /// from the fields the validator reports rather than from the signed Sawtooth block header, so
+    /// its other fields are only as trustworthy as this node.
+    ///
+    /// Finding the successor requires walking back from the chain head, which also guarantees
+    /// that the block is on the chain this node has committed. To keep the walk short, proofs are
+    /// only built for blocks at most `PbftConfig::max_finality_proof_depth` blocks behind the
+    /// chain head.
+    ///
+    /// Building a proof doesn't change the node's state: if the host can't provide the members
+    /// of the block's predecessor, the error is returned without scheduling a retry.
+    ///
+    /// # Panics
+    /// + If the `sawtooth.consensus.pbft.members` setting is unset or invalid
+    pub fn get_finality_proof(
+        &mut self,
+        block_id: &[u8],
+        state: &PbftState,
+    ) -> Result<PbftFinalityProof, PbftError> {
+        let block = self.get_block(block_id)?;
+
+        // The genesis block isn't voted on, so there is nothing to prove
+        if block.block_num == 0 {
+            return Err(PbftError::InvalidMessage(
+                "The genesis block doesn't have a finality proof".into(),
+            ));
+        }
+
+        if block.block_num >= state.seq_num {
+            return Err(PbftError::InvalidMessage(format!(
+                "Block {} has not been committed yet",
+                block.block_num
+            )));
+        }
+
+        let seal = if block.block_id == state.chain_head {
+            self.build_seal(state)?
+        } else {
+            let mut successor = self.get_block(&state.chain_head)?;
+            if successor.block_num > block.block_num + self.max_finality_proof_depth {
+                return Err(PbftError::InvalidMessage(format!(
+                    "Block {} is more than {} blocks behind the chain head; raise \
+                     max_finality_proof_depth to build proofs for older blocks",
+                    block.block_num, self.max_finality_proof_depth
+                )));
+            }
+            while successor.previous_id != block.block_id {
+                if successor.block_num <= block.block_num + 1 {
+                    return Err(PbftError::InvalidMessage(format!(
+                        "Block {} is not on this node's committed chain",
+                        hex::encode(&block.block_id)
+                    )));
+                }
+                successor = self.get_block(&successor.previous_id)?;
+            }
+
+            protobuf::parse_from_bytes::<PbftSeal>(&successor.payload).map_err(|err| {
+                PbftError::SerializationError("Error parsing seal from block payload".into(), err)
+            })?
+        };
+
+        if seal.block_id != block.block_id {
+            return Err(PbftError::InternalError(format!(
+                "Seal is for block {}, not block {}",
+                hex::encode(&seal.block_id),
+                hex::encode(&block.block_id)
+            )));
+        }
+
+        let members = self.lookup_on_chain_members(&block.previous_id, state)?;
+
+        let mut header = PbftBlockHeader::new();
+        header.set_block_id(block.block_id);
+        header.set_previous_id(block.previous_id);
+        header.set_signer_id(block.signer_id);
+        header.set_block_num(block.block_num);
+        header.set_summary(block.summary);
+
+        let mut proof = PbftFinalityProof::new();
+        proof.set_header(header);
+        proof.set_seal(seal);
+        proof.set_members(RepeatedField::from(members));
+
+        Ok(proof)
+    }
+
+    // ---------- Methods called in the main engine loop to periodically check and update state ----------
+
+    /// At a regular interval, try to finalize a block when the primary is ready
+    pub fn try_publish(&mut self, state: &mut PbftState) -> Result<(), PbftError> {
+        // Only the primary takes care of this, and we try publishing a block
+        // on every engine loop, even if it's not yet ready. This isn't an error,
+        // so just return Ok(()).
+        if !state.is_primary()
+            || state.phase != PbftPhase::PrePreparing
+            || self.pending_commit.is_some()
+        {
+            return Ok(());
+        }
+
+        trace!("{}: Attempting to summarize block", state);
+
+        match self.host.summarize_block() {
+            Ok(_) => {}
+            Err(err) => {
+                trace!("Couldn't summarize, so not finalizing: {}", err);
+                return Ok(());
+            }
+        }
+
+        // We don't publish a consensus seal at block 1, since we never receive any
+        // votes on the genesis block. Leave payload blank for the first block.
+        let data = if state.seq_num <= 1 {
+            vec![]
+        } else {
+            self.build_seal(state)?.write_to_bytes().map_err(|err| {
+                PbftError::SerializationError("Error writing seal to bytes".into(), err)
+            })?
+        };
+
+        match self.host.finalize_block(data) {
+            Ok(block_id) => {
+                info!("{}: Publishing block {}", state, hex::encode(&block_id));
+                Ok(())
+            }
+            Err(err) => Err(PbftError::ServiceError(
+                "Couldn't finalize block".into(),
+                err,
+            )),
+        }
+    }
+
+    /// Check to see if the idle timeout has expired
+    pub fn check_idle_timeout_expired(&mut self, state: &mut PbftState) -> bool {
+        state.idle_timeout.check_expired()
+    }
+
+    /// Start the idle timeout
+    pub fn start_idle_timeout(&self, state: &mut PbftState) {
+        state.idle_timeout.start();
+    }
+
+    /// Check to see if the commit timeout has expired
     pub fn check_commit_timeout_expired(&mut self, state: &mut PbftState) -> bool {
         state.commit_timeout.check_expired()
     }
@@ -1602,7 +2026,7 @@ impl PbftNode {
         state: &mut PbftState,
     ) -> Result<(), PbftError> {
         // Broadcast to peers
-        self.service
+        self.host
             .broadcast(
                 String::from(msg.info().get_msg_type()).as_str(),
                 msg.message_bytes.clone(),
@@ -1635,7 +2059,7 @@ impl PbftNode {
         })?;
 
         // Send the seal to the requester
-        self.service
+        self.host
             .send_to(
                 recipient,
                 String::from(PbftMessageType::Seal).as_str(),
@@ -1652,6 +2076,94 @@ impl PbftNode {
             })
     }
 
+    // ---------- Methods for publishing consensus events ----------
+
+    /// Publish consensus events to the given sink from now on
+    pub fn set_event_sink(&mut self, sink: Box<dyn EventSink>) {
+        self.event_sink = Some(sink);
+    }
+
+    /// Publish the event if the node has an event sink
+    fn publish_event(&mut self, event: ConsensusEvent) {
+        if let Some(sink) = self.event_sink.as_mut() {
+            trace!("Publishing consensus event: {:?}", event);
+            sink.publish(&event)
+                .unwrap_or_else(|err| error!("Couldn't publish consensus event: {}", err));
+        }
+    }
+
+    /// Publish a `BlockFinalized` event for the block that was just committed, along with the
+    /// seal that proves it (if one can be built); the seal is only built when there is a sink to
+    /// publish it to.
+    fn publish_block_finalized(&mut self, block_id: &[u8], state: &PbftState) {
+        if self.event_sink.is_none() {
+            return;
+        }
+
+        let seal = self.build_seal(state).ok();
+        let event = ConsensusEvent::BlockFinalized {
+            block_id: hex::encode(block_id),
+            block_num: state.seq_num - 1,
+            view: seal
+                .as_ref()
+                .map(|seal| seal.get_info().get_view())
+                .unwrap_or(state.view),
+            seal: seal
+                .and_then(|seal| seal.write_to_bytes().ok())
+                .map(hex::encode),
+        };
+        self.publish_event(event);
+    }
+
+    /// Publish a `FaultyPrimaryDetected` event for the current primary
+    fn publish_faulty_primary(&mut self, state: &PbftState, reason: &str) {
+        self.publish_event(ConsensusEvent::FaultyPrimaryDetected {
+            view: state.view,
+            primary: hex::encode(state.get_primary_id()),
+            reason: reason.into(),
+        });
+    }
+
+    // ---------- Methods for reporting on member participation ----------
+
+    /// Report on how consistently each member has taken part in consensus over the participation
+    /// window
+    pub fn participation_report(&self, state: &PbftState) -> ParticipationReport {
+        self.participation
+            .report(&state.member_ids, state.seq_num, state.view)
+    }
+
+    /// Log the participation report, with a warning for each member below the threshold
+    fn log_participation(&self, state: &PbftState) {
+        let report = self.participation_report(state);
+        info!(
+            "{}: Participation over the last {} blocks: {}",
+            state,
+            report.rounds,
+            report
+                .members
+                .iter()
+                .map(|activity| format!(
+                    "{}: {:.0}%",
+                    activity.member,
+                    activity.participation * 100.0
+                ))
+                .join(", ")
+        );
+
+        for activity in report.flagged() {
+            warn!(
+                "{}: Member {} only participated in {:.0}% of the last {} blocks (threshold: \
+                 {:.0}%)",
+                state,
+                activity.member,
+                activity.participation * 100.0,
+                report.rounds,
+                report.threshold * 100.0
+            );
+        }
+    }
+
     // ---------- Miscellaneous methods ----------
 
     /// Start a view change when this node suspects that the primary is faulty
@@ -1672,6 +2184,11 @@ impl PbftNode {
 
         info!("{}: Starting change to view {}", state, view);
 
+        self.publish_event(ConsensusEvent::ViewChangeStarted {
+            from_view: state.view,
+            to_view: view,
+        });
+
         state.mode = PbftMode::ViewChanging(view);
 
         // Stop the idle and commit timeouts because they are not needed until after the view
@@ -1697,348 +2214,20 @@ impl PbftNode {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::engine::test_handle_update;
-    use crate::hash::hash_sha512;
+    use crate::host::HostError;
     use crate::message_type::PbftMessageWrapper;
     use crate::protos::pbft_message::PbftMessageInfo;
-    use crate::test_helpers::*;
-    use sawtooth_sdk::consensus::engine::{Error, PeerId, PeerMessage, Update};
-    use sawtooth_sdk::signing::secp256k1::Secp256k1PrivateKey;
+    use crate::stringify_func_call;
+    use crate::testkit::*;
+    use crate::timing::ManualClock;
+    use sawtooth_sdk::consensus::engine::{PeerMessage, Update};
     use serde_json;
     use std::cell::RefCell;
     use std::collections::HashMap;
     use std::default::Default;
     use std::rc::Rc;
-
-    /// Turns a series of items into a `Vec<String>` for easily tracking and checking for function
-    /// calls to the MockService
-    macro_rules! stringify_func_call {
-        ( $( $x:expr ),* ) => {
-            {
-                let mut output = Vec::new();
-                $(
-                    output.push(format!("{:?}", $x));
-                )*
-                output
-            }
-        }
-    }
-
-    /// Implementation of the consensus' `Service` trait that's used to mock out interactions with
-    /// the Sawtooth validator. The `MockService` will track calls to its methods and supports
-    /// configurable return values for some of its methods.
-    #[derive(Clone)]
-    struct MockService {
-        /// A list of function calls, where each function call is a list of the form (func_name,
-        /// arg1, arg2, ...)
-        calls: Rc<RefCell<Vec<Vec<String>>>>,
-        /// For each block ID, the settings value to return when `get_settings` is called
-        settings: Rc<RefCell<HashMap<BlockId, HashMap<String, String>>>>,
-        /// Determines the return value of the `summarize_block` method
-        summarize_block_return_val: Rc<RefCell<Result<Vec<u8>, Error>>>,
-    }
-
-    impl MockService {
-        /// Create a new `MockService` and set the members setting based on the `PbftConfig`
-        fn new(cfg: &PbftConfig) -> Self {
-            let members: Vec<_> = cfg.members.iter().map(hex::encode).collect();
-            let service = MockService {
-                calls: Default::default(),
-                settings: Default::default(),
-                summarize_block_return_val: Rc::new(RefCell::new(Ok(Default::default()))),
-            };
-            // Set the default settings
-            let mut default_settings = HashMap::new();
-            default_settings.insert(
-                "sawtooth.consensus.pbft.members".to_string(),
-                serde_json::to_string(&members).unwrap(),
-            );
-            service
-                .settings
-                .borrow_mut()
-                .insert(vec![0], default_settings);
-
-            service
-        }
-
-        /// Indicates if the specified method was called
-        fn was_called(&self, method_name: &str) -> bool {
-            self.calls
-                .borrow()
-                .iter()
-                .any(|call| call[0] == format!("{:?}", method_name))
-        }
-
-        /// Indicates if the specified method was called with the given arguments (allows partial
-        /// args)
-        fn was_called_with_args(&self, call: Vec<String>) -> bool {
-            self.calls
-                .borrow()
-                .iter()
-                .any(|logged_call| logged_call.starts_with(&call))
-        }
-
-        /// Indicates if the specified method was called with the given arguments only once (allows
-        /// partial args)
-        fn was_called_with_args_once(&self, call: Vec<String>) -> bool {
-            self.calls
-                .borrow()
-                .iter()
-                .filter(|logged_call| logged_call.starts_with(&call))
-                .count()
-                == 1
-        }
-    }
-
-    impl Service for MockService {
-        fn send_to(
-            &mut self,
-            peer: &PeerId,
-            message_type: &str,
-            payload: Vec<u8>,
-        ) -> Result<(), Error> {
-            self.calls.borrow_mut().push(stringify_func_call!(
-                "send_to",
-                peer,
-                message_type,
-                payload
-            ));
-            Ok(())
-        }
-        fn broadcast(&mut self, message_type: &str, payload: Vec<u8>) -> Result<(), Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("broadcast", message_type, payload));
-            Ok(())
-        }
-        fn initialize_block(&mut self, previous_id: Option<BlockId>) -> Result<(), Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("initialize_block", previous_id));
-            Ok(())
-        }
-        fn summarize_block(&mut self) -> Result<Vec<u8>, Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("summarize_block"));
-            self.summarize_block_return_val
-                .replace(Ok(Default::default()))
-        }
-        fn finalize_block(&mut self, data: Vec<u8>) -> Result<BlockId, Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("finalize_block", data));
-            Ok(Default::default())
-        }
-        fn cancel_block(&mut self) -> Result<(), Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("cancel_block"));
-            Ok(())
-        }
-        fn check_blocks(&mut self, priority: Vec<BlockId>) -> Result<(), Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("check_blocks", priority));
-            Ok(())
-        }
-        fn commit_block(&mut self, block_id: BlockId) -> Result<(), Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("commit_block", block_id));
-            Ok(())
-        }
-        fn ignore_block(&mut self, block_id: BlockId) -> Result<(), Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("ignore_block", block_id));
-            Ok(())
-        }
-        fn fail_block(&mut self, block_id: BlockId) -> Result<(), Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("fail_block", block_id));
-            Ok(())
-        }
-        fn get_blocks(
-            &mut self,
-            block_ids: Vec<BlockId>,
-        ) -> Result<HashMap<BlockId, Block>, Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("get_blocks", block_ids));
-            Ok(Default::default())
-        }
-        fn get_chain_head(&mut self) -> Result<Block, Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("get_chain_head"));
-            Ok(Default::default())
-        }
-        fn get_settings(
-            &mut self,
-            block_id: BlockId,
-            settings: Vec<String>,
-        ) -> Result<HashMap<String, String>, Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("get_settings", block_id, settings));
-            let settings = self.settings.borrow();
-            Ok(settings
-                .get(&block_id)
-                .unwrap_or_else(|| {
-                    // Fall back to defualt settings (in block 0)
-                    settings.get(&vec![0]).expect("Default settings not set")
-                })
-                .clone())
-        }
-        fn get_state(
-            &mut self,
-            block_id: BlockId,
-            addresses: Vec<String>,
-        ) -> Result<HashMap<String, Vec<u8>>, Error> {
-            self.calls
-                .borrow_mut()
-                .push(stringify_func_call!("get_state", block_id, addresses));
-            Ok(Default::default())
-        }
-    }
-
-    struct KeyPair {
-        pub_key: Vec<u8>,
-        priv_key: Vec<u8>,
-    }
-
-    /// Create a list of public/private key pairs for the specified number of nodes
-    fn mock_signer_network(size: u8) -> Vec<KeyPair> {
-        let context = create_context("secp256k1").expect("Failed to create context");
-        (0..size)
-            .map(|_| {
-                let priv_key = context
-                    .new_random_private_key()
-                    .expect("Failed to generate new private key");
-                let pub_key = context
-                    .get_public_key(&*priv_key)
-                    .expect("Failed to get public key");
-                KeyPair {
-                    pub_key: pub_key.as_slice().to_vec(),
-                    priv_key: priv_key.as_slice().to_vec(),
-                }
-            })
-            .collect()
-    }
-
-    /// Create a mock configuration for the list of signers generated by `mock_signer_network`
-    fn mock_config_from_signer_network(keys: &[KeyPair]) -> PbftConfig {
-        let mut config = PbftConfig::default();
-        config.members = keys
-            .iter()
-            .map(|key_pair| key_pair.pub_key.clone())
-            .collect();
-        config
-    }
-
-    /// Create a new PbftNode, PbftState, and MockService based on the given config, node ID, and
-    /// chain head
-    fn mock_node(
-        cfg: &PbftConfig,
-        node_id: PeerId,
-        chain_head: Block,
-    ) -> (PbftNode, PbftState, MockService) {
-        let mut state = PbftState::new(node_id.clone(), chain_head.block_num, cfg);
-        let service = MockService::new(cfg);
-        (
-            PbftNode::new(
-                cfg,
-                chain_head.clone(),
-                vec![],
-                Box::new(service.clone()),
-                &mut state,
-            ),
-            state,
-            service,
-        )
-    }
-
-    /// Create a validly-signed PbftSignedVote
-    fn mock_vote(
-        msg_type: PbftMessageType,
-        view: u64,
-        seq_num: u64,
-        block_id: BlockId,
-        signer: &KeyPair,
-    ) -> PbftSignedVote {
-        let info = PbftMessageInfo::new_from(msg_type, view, seq_num, signer.pub_key.clone());
-        let mut msg = PbftMessage::new();
-        msg.set_info(info);
-        msg.set_block_id(block_id);
-        let msg_bytes = msg
-            .write_to_bytes()
-            .expect("Failed to write msg to bytes for mock vote");
-
-        let mut header = ConsensusPeerMessageHeader::new();
-        header.set_signer_id(signer.pub_key.clone());
-        header.set_content_sha512(hash_sha512(&msg_bytes));
-
-        let header_bytes = header
-            .write_to_bytes()
-            .expect("Failed to write header to bytes");
-        let header_signature = hex::decode(
-            create_context("secp256k1")
-                .expect("Failed to create context")
-                .sign(
-                    &header_bytes,
-                    &Secp256k1PrivateKey::from_hex(&hex::encode(signer.priv_key.clone()))
-                        .expect("Failed to create private key from hex"),
-                )
-                .expect("Failed to sign header"),
-        )
-        .expect("Failed to decode signed header");
-
-        let mut vote = PbftSignedVote::new();
-        vote.set_header_bytes(header_bytes);
-        vote.set_header_signature(header_signature);
-        vote.set_message_bytes(msg_bytes.to_vec());
-        vote
-    }
-
-    /// Create a PbftNewView
-    fn mock_new_view(
-        view: u64,
-        seq_num: u64,
-        signer: &KeyPair,
-        votes: Vec<PbftSignedVote>,
-    ) -> PbftNewView {
-        let mut new_view = PbftNewView::new();
-        new_view.set_info(PbftMessageInfo::new_from(
-            PbftMessageType::NewView,
-            view,
-            seq_num,
-            signer.pub_key.clone(),
-        ));
-        new_view.set_view_changes(RepeatedField::from(votes));
-        new_view
-    }
-
-    /// Create a PbftSeal
-    fn mock_seal(
-        view: u64,
-        seq_num: u64,
-        block_id: BlockId,
-        signer: &KeyPair,
-        votes: Vec<PbftSignedVote>,
-    ) -> PbftSeal {
-        let mut seal = PbftSeal::new();
-        seal.set_info(PbftMessageInfo::new_from(
-            PbftMessageType::Seal,
-            view,
-            seq_num,
-            signer.pub_key.clone(),
-        ));
-        seal.set_block_id(block_id);
-        seal.set_commit_votes(RepeatedField::from(votes));
-        seal
-    }
+    use std::sync::Arc;
+    use std::time::Duration;
 
     /// This test will verify that when the `PbftNode::new` method is called, it will return a
     /// `PbftNode` after performing the following actions:
@@ -2111,102 +2300,6 @@ mod tests {
         });
     }
 
-    /// In order to verify that a consensus seal or a `NewView` is correct, nodes must be able to
-    /// verify each of the signed votes that are contained in the seal/`NewView`. `PbftSignedVote`s
-    /// are verified by the `PbftNode::verify_vote` method, which takes as arguments the vote
-    /// itself, the expected vote type, and a closure that evaluates some arbitrary criteria. The
-    /// `verify_vote` method should make sure the vote’s type matches the expected type, the header
-    /// is properly signed, the header’s signer matches the message’s signer, the message hash is
-    /// correct, and that it meets the criteria specified in the closure.
-    ///
-    /// This test verifies that the `verify_vote` method works correctly by testing all cases where
-    /// it should fail and a case where it should succeed.
-    #[test]
-    fn test_vote_verification() {
-        // Generate a public/private key pair
-        let key_pair = mock_signer_network(1).remove(0);
-
-        // Create a validly-signed Commit vote
-        let valid_vote = mock_vote(PbftMessageType::Commit, 0, 1, vec![1], &key_pair);
-
-        // Test verification of a valid vote
-        assert_eq!(
-            key_pair.pub_key,
-            PbftNode::verify_vote(&valid_vote, PbftMessageType::Commit, |_| Ok(()))
-                .expect("Valid vote was determined to be invalid")
-        );
-
-        // Test verification of a vote with invalid type
-        assert!(
-            PbftNode::verify_vote(&valid_vote, PbftMessageType::ViewChange, |_| Ok(())).is_err()
-        );
-
-        // Test verification of a vote that doesn't meet the validation_criteria
-        assert!(
-            PbftNode::verify_vote(&valid_vote, PbftMessageType::Commit, |_| Err(
-                PbftError::InvalidMessage("".into())
-            ))
-            .is_err()
-        );
-
-        // Test verification of a vote with an invalid header signature
-        let mut invalid_header_sig = valid_vote.clone();
-        invalid_header_sig.set_header_signature(vec![0]);
-        assert!(PbftNode::verify_vote(
-            &invalid_header_sig,
-            PbftMessageType::ViewChange,
-            |_| Ok(())
-        )
-        .is_err());
-
-        // Test verification of a vote with an invalid message hash
-        let mut invalid_msg_hash = valid_vote.clone();
-        invalid_msg_hash.set_message_bytes(vec![0]);
-        assert!(
-            PbftNode::verify_vote(&invalid_msg_hash, PbftMessageType::Commit, |_| Ok(())).is_err()
-        );
-
-        // Test verification of a vote where the header's signer doesn't match the message's
-        // signers (the vote signer didn't create the message)
-        let bad_key_pair = mock_signer_network(1).remove(0);
-
-        let other_nodes_message = mock_msg(
-            PbftMessageType::Commit,
-            0,
-            1,
-            key_pair.pub_key.clone(),
-            vec![1],
-            false,
-        );
-
-        let mut header = ConsensusPeerMessageHeader::new();
-        header.set_signer_id(bad_key_pair.pub_key.clone());
-        header.set_content_sha512(hash_sha512(&other_nodes_message.message_bytes));
-        let header_bytes = header
-            .write_to_bytes()
-            .expect("Failed to write header to bytes");
-        let header_signature = hex::decode(
-            create_context("secp256k1")
-                .expect("Failed to create context")
-                .sign(
-                    &header_bytes,
-                    &Secp256k1PrivateKey::from_hex(&hex::encode(bad_key_pair.priv_key.clone()))
-                        .expect("Failed to create private key from hex"),
-                )
-                .expect("Failed to sign header"),
-        )
-        .expect("Failed to decode signed header");
-
-        let mut mismatched_signer = PbftSignedVote::new();
-        mismatched_signer.set_header_bytes(header_bytes);
-        mismatched_signer.set_header_signature(header_signature);
-        mismatched_signer.set_message_bytes(other_nodes_message.message_bytes.clone());
-
-        assert!(
-            PbftNode::verify_vote(&mismatched_signer, PbftMessageType::Commit, |_| Ok(())).is_err()
-        );
-    }
-
     /// Nodes must be able to verify `NewView` messages to ensure that view changes are valid. To
     /// do this, nodes use the `PbftNode::verify_new_view` method. A `NewView` message is valid if:
     ///
@@ -2291,59 +2384,189 @@ mod tests {
                 mock_vote(PbftMessageType::ViewChange, 0, 1, vec![], &key_pairs[3]),
             ],
         );
-        assert!(node
-            .verify_new_view(&vote_for_different_view, &mut state)
-            .is_err());
+        assert!(node
+            .verify_new_view(&vote_for_different_view, &mut state)
+            .is_err());
+
+        // Test verification of a NewView that contains a vote from a non-member
+        let vote_from_unknown_peer = mock_new_view(
+            1,
+            1,
+            &key_pairs[1],
+            vec![
+                mock_vote(PbftMessageType::ViewChange, 1, 1, vec![], &key_pairs[2]),
+                mock_vote(
+                    PbftMessageType::ViewChange,
+                    1,
+                    1,
+                    vec![],
+                    &mock_signer_network(1).remove(0),
+                ),
+            ],
+        );
+        assert!(node
+            .verify_new_view(&vote_from_unknown_peer, &mut state)
+            .is_err());
+
+        // Test verification of a NewView that contains a vote from the new primary
+        let vote_from_primary = mock_new_view(
+            1,
+            1,
+            &key_pairs[1],
+            (1..3)
+                .map(|i| mock_vote(PbftMessageType::ViewChange, 1, 1, vec![], &key_pairs[i]))
+                .collect::<Vec<_>>(),
+        );
+        assert!(node
+            .verify_new_view(&vote_from_primary, &mut state)
+            .is_err());
+
+        // Test verification of a NewView that does not contain enough votes
+        let insufficient_votes = mock_new_view(
+            1,
+            1,
+            &key_pairs[1],
+            vec![mock_vote(
+                PbftMessageType::ViewChange,
+                1,
+                1,
+                vec![],
+                &key_pairs[2],
+            )],
+        );
+        assert!(node
+            .verify_new_view(&insufficient_votes, &mut state)
+            .is_err());
+    }
+
+    /// A host that doesn't sign votes: each vote's header bytes are simply the ID of its signer.
+    /// The host records the block lifecycle calls it receives.
+    struct UnsignedHost {
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl ConsensusHost for UnsignedHost {
+        fn send_to(&mut self, _: &PeerId, _: &str, _: Vec<u8>) -> Result<(), HostError> {
+            Ok(())
+        }
+        fn broadcast(&mut self, _: &str, _: Vec<u8>) -> Result<(), HostError> {
+            Ok(())
+        }
+        fn initialize_block(&mut self, _: Option<BlockId>) -> Result<(), HostError> {
+            self.calls.borrow_mut().push("initialize_block".into());
+            Ok(())
+        }
+        fn summarize_block(&mut self) -> Result<Vec<u8>, HostError> {
+            Err(HostError::BlockNotReady)
+        }
+        fn finalize_block(&mut self, _: Vec<u8>) -> Result<BlockId, HostError> {
+            Err(HostError::BlockNotReady)
+        }
+        fn cancel_block(&mut self) -> Result<(), HostError> {
+            self.calls.borrow_mut().push("cancel_block".into());
+            Ok(())
+        }
+        fn check_blocks(&mut self, _: Vec<BlockId>) -> Result<(), HostError> {
+            Ok(())
+        }
+        fn commit_block(&mut self, _: BlockId) -> Result<(), HostError> {
+            Ok(())
+        }
+        fn ignore_block(&mut self, _: BlockId) -> Result<(), HostError> {
+            Ok(())
+        }
+        fn fail_block(&mut self, _: BlockId) -> Result<(), HostError> {
+            Ok(())
+        }
+        fn get_blocks(&mut self, _: Vec<BlockId>) -> Result<HashMap<BlockId, Block>, HostError> {
+            Ok(HashMap::new())
+        }
+        fn get_settings(
+            &mut self,
+            _: BlockId,
+            _: Vec<String>,
+        ) -> Result<HashMap<String, String>, HostError> {
+            Ok(HashMap::new())
+        }
+        fn authenticate_vote(&self, vote: &PbftSignedVote) -> Result<PeerId, PbftError> {
+            Ok(vote.get_header_bytes().to_vec())
+        }
+    }
+
+    /// The consensus logic must not depend on the Sawtooth validator: a node that runs on some
+    /// other `ConsensusHost` must make all of its block lifecycle calls to that host and must
+    /// check the signatures of votes the way the host does.
+    ///
+    /// This test runs node 1 (the primary of view 1) on a host that doesn't sign votes at all,
+    /// verifies that a NewView with votes that the host accepts is valid, that the same votes
+    /// signed for a Sawtooth validator are not, and that the node initializes a block with the
+    /// host once it becomes the primary.
+    #[test]
+    fn test_node_with_custom_host() {
+        let cfg = mock_config(4);
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut state = PbftState::new(vec![1], 0, &cfg);
+        let mut node = PbftNode::with_host(
+            &cfg,
+            mock_block(0),
+            vec![],
+            Box::new(UnsignedHost {
+                calls: calls.clone(),
+            }),
+            &mut state,
+        );
+        assert!(calls.borrow().is_empty());
 
-        // Test verification of a NewView that contains a vote from a non-member
-        let vote_from_unknown_peer = mock_new_view(
-            1,
-            1,
-            &key_pairs[1],
-            vec![
-                mock_vote(PbftMessageType::ViewChange, 1, 1, vec![], &key_pairs[2]),
-                mock_vote(
+        let unsigned_vote = |signer: u8| {
+            let mut vote = PbftSignedVote::new();
+            vote.set_header_bytes(vec![signer]);
+            vote.set_message_bytes(
+                mock_msg(
                     PbftMessageType::ViewChange,
                     1,
                     1,
+                    vec![signer],
                     vec![],
-                    &mock_signer_network(1).remove(0),
-                ),
-            ],
-        );
-        assert!(node
-            .verify_new_view(&vote_from_unknown_peer, &mut state)
-            .is_err());
-
-        // Test verification of a NewView that contains a vote from the new primary
-        let vote_from_primary = mock_new_view(
-            1,
-            1,
-            &key_pairs[1],
-            (1..3)
-                .map(|i| mock_vote(PbftMessageType::ViewChange, 1, 1, vec![], &key_pairs[i]))
-                .collect::<Vec<_>>(),
-        );
-        assert!(node
-            .verify_new_view(&vote_from_primary, &mut state)
-            .is_err());
-
-        // Test verification of a NewView that does not contain enough votes
-        let insufficient_votes = mock_new_view(
+                    false,
+                )
+                .message_bytes,
+            );
+            vote
+        };
+        let primary = KeyPair {
+            pub_key: vec![1],
+            priv_key: vec![],
+        };
+        let new_view = mock_new_view(1, 1, &primary, vec![unsigned_vote(2), unsigned_vote(3)]);
+        assert!(node.verify_new_view(&new_view, &mut state).is_ok());
+
+        // Votes from the wrong signer are still rejected
+        let mut wrong_signer = new_view.clone();
+        wrong_signer.mut_view_changes()[0].set_header_bytes(vec![3]);
+        assert!(node.verify_new_view(&wrong_signer, &mut state).is_err());
+
+        // The host decides how votes are signed; votes signed for a Sawtooth validator by keys
+        // that aren't members are not accepted
+        let key_pairs = mock_signer_network(2);
+        let sawtooth_signed = mock_new_view(
             1,
             1,
-            &key_pairs[1],
+            &key_pairs[0],
             vec![mock_vote(
                 PbftMessageType::ViewChange,
                 1,
                 1,
                 vec![],
-                &key_pairs[2],
+                &key_pairs[1],
             )],
         );
-        assert!(node
-            .verify_new_view(&insufficient_votes, &mut state)
-            .is_err());
+        assert!(node.verify_new_view(&sawtooth_signed, &mut state).is_err());
+
+        let parsed =
+            ParsedMessage::from_new_view_message(new_view).expect("Failed to parse NewView");
+        assert!(node.handle_new_view(&parsed, &mut state).is_ok());
+        assert_eq!(1, state.view);
+        assert_eq!(vec!["initialize_block".to_string()], *calls.borrow());
     }
 
     /// Nodes must be able to verify consensus seals to ensure that committed blocks contain valid
@@ -2394,10 +2617,7 @@ mod tests {
             )
             .unwrap(),
         );
-        service
-            .settings
-            .borrow_mut()
-            .insert(vec![1], block_1_settings);
+        service.set_settings(vec![1], block_1_settings);
 
         // Test verification of a valid seal/previous_id
         let valid_seal = mock_seal(
@@ -2658,14 +2878,17 @@ mod tests {
         );
 
         // Add a group of messages with signed components to the node's log
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            1,
-            key_pairs[0].pub_key.clone(),
-            vec![1],
-            true,
-        ));
+        node.msg_log.add_message(
+            mock_msg(
+                PbftMessageType::Commit,
+                0,
+                1,
+                key_pairs[0].pub_key.clone(),
+                vec![1],
+                true,
+            ),
+            &state,
+        );
         node.msg_log.add_message(
             ParsedMessage::from_signed_vote(&mock_vote(
                 PbftMessageType::Commit,
@@ -2675,6 +2898,7 @@ mod tests {
                 &key_pairs[1],
             ))
             .expect("Failed to parse vote"),
+            &state,
         );
         node.msg_log.add_message(
             ParsedMessage::from_signed_vote(&mock_vote(
@@ -2685,6 +2909,7 @@ mod tests {
                 &key_pairs[2],
             ))
             .expect("Failed to parse vote"),
+            &state,
         );
         node.msg_log.add_message(
             ParsedMessage::from_signed_vote(&mock_vote(
@@ -2695,6 +2920,7 @@ mod tests {
                 &key_pairs[2],
             ))
             .expect("Failed to parse vote"),
+            &state,
         );
         node.msg_log.add_message(
             ParsedMessage::from_signed_vote(&mock_vote(
@@ -2705,6 +2931,7 @@ mod tests {
                 &key_pairs[2],
             ))
             .expect("Failed to parse vote"),
+            &state,
         );
         node.msg_log.add_message(
             ParsedMessage::from_signed_vote(&mock_vote(
@@ -2715,6 +2942,7 @@ mod tests {
                 &key_pairs[2],
             ))
             .expect("Failed to parse vote"),
+            &state,
         );
 
         // Verify that seal cannot be built yet (have 2f matching messages for a block at the last
@@ -2731,6 +2959,7 @@ mod tests {
                 &key_pairs[2],
             ))
             .expect("Failed to parse vote"),
+            &state,
         );
 
         // Verify that a valid seal can be built now
@@ -2776,30 +3005,18 @@ mod tests {
         let (mut node, mut state, service) = mock_node(&mock_config(4), vec![0], mock_block(1));
 
         // Add messages necessary to build a valid seal for block 1
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            1,
-            vec![0],
-            vec![1],
-            true,
-        ));
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            1,
-            vec![1],
-            vec![1],
-            false,
-        ));
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            1,
-            vec![2],
-            vec![1],
-            false,
-        ));
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 1, vec![0], vec![1], true),
+            &state,
+        );
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 1, vec![1], vec![1], false),
+            &state,
+        );
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 1, vec![2], vec![1], false),
+            &state,
+        );
 
         // Update the state to view 1 (so node isn’t primary) and call try_publish(); verify that
         // finalize_block() was not called
@@ -2818,18 +3035,14 @@ mod tests {
         // summarize_block() method returns an Err result; call try_publish() and verify that
         // finalize_block() was not called
         state.phase = PbftPhase::PrePreparing;
-        service
-            .summarize_block_return_val
-            .replace(Err(Error::BlockNotReady));
+        service.set_summarize_block_result(Err(sdk::Error::BlockNotReady));
         assert!(node.try_publish(&mut state).is_ok());
         assert!(service.was_called("summarize_block"));
         assert!(!service.was_called("finalize_block"));
 
         // Update the mock Service so its summarize_block() method returns Ok again, then call
         // try_publish() and verify that finalize_block() is called with a seal for block 1
-        service
-            .summarize_block_return_val
-            .replace(Ok(Default::default()));
+        service.set_summarize_block_result(Ok(Default::default()));
         assert!(node.try_publish(&mut state).is_ok());
         assert!(service.was_called_with_args(stringify_func_call!(
             "finalize_block",
@@ -3100,7 +3313,7 @@ mod tests {
         invalid_peer_message.header.message_type = "PrePrepare".into();
         invalid_peer_message.content =
             mock_msg(PbftMessageType::PrePrepare, 0, 1, vec![1], vec![1], false).message_bytes;
-        assert!(test_handle_update(
+        assert!(handle_update(
             &mut node,
             Ok(Update::PeerMessage(invalid_peer_message, vec![2])),
             &mut state
@@ -3114,7 +3327,7 @@ mod tests {
         valid_peer_message.header.message_type = "PrePrepare".into();
         valid_peer_message.content =
             mock_msg(PbftMessageType::PrePrepare, 0, 1, vec![1], vec![1], false).message_bytes;
-        assert!(test_handle_update(
+        assert!(handle_update(
             &mut node,
             Ok(Update::PeerMessage(valid_peer_message, vec![1])),
             &mut state
@@ -3983,10 +4196,7 @@ mod tests {
             serde_json::to_string(&block_1_members.iter().map(hex::encode).collect::<Vec<_>>())
                 .unwrap(),
         );
-        service
-            .settings
-            .borrow_mut()
-            .insert(vec![1], block_1_settings);
+        service.set_settings(vec![1], block_1_settings);
         let mut block_2_settings = HashMap::new();
         let block_2_members = vec![
             vec![1],
@@ -4002,10 +4212,7 @@ mod tests {
             serde_json::to_string(&block_2_members.iter().map(hex::encode).collect::<Vec<_>>())
                 .unwrap(),
         );
-        service
-            .settings
-            .borrow_mut()
-            .insert(vec![2], block_2_settings);
+        service.set_settings(vec![2], block_2_settings);
         let mut block_3_settings = HashMap::new();
         block_3_settings.insert(
             "sawtooth.consensus.pbft.members".to_string(),
@@ -4017,10 +4224,7 @@ mod tests {
             )
             .unwrap(),
         );
-        service
-            .settings
-            .borrow_mut()
-            .insert(vec![3], block_3_settings);
+        service.set_settings(vec![3], block_3_settings);
 
         // Simulate block commit for block 1; verify node's members list is updated properly and f is
         // now 2
@@ -4038,6 +4242,210 @@ mod tests {
         node.on_block_commit(vec![3], &mut state);
     }
 
+    /// Seals are verified against the members of the block before the one they prove, which the
+    /// node has usually committed already. When it commits a block, the node must remember the
+    /// block's members in its state's member history, and seal verification must use the history
+    /// instead of asking the validator for the members again; members of blocks that aren't in
+    /// the history must still be read from the validator.
+    #[test]
+    fn test_member_history_used_for_seal_verification() {
+        let key_pairs = mock_signer_network(4);
+        let (mut node, mut state, service) = mock_node(
+            &mock_config_from_signer_network(&key_pairs),
+            key_pairs[0].pub_key.clone(),
+            mock_block(0),
+        );
+        let settings_calls = || {
+            service
+                .calls()
+                .iter()
+                .filter(|call| call[0] == format!("{:?}", "get_settings"))
+                .count()
+        };
+
+        // Committing block 1 reads its members once and remembers them
+        assert!(node.on_block_commit(vec![1], &mut state).is_ok());
+        assert_eq!(1, settings_calls());
+        assert_eq!(Some(&state.member_ids[..]), state.member_history.get(&[1]));
+
+        // The seal for block 2 is verified against the remembered members of block 1
+        let seal = |block_id: u8| {
+            mock_seal(
+                0,
+                2,
+                vec![block_id],
+                &key_pairs[0],
+                (1..3)
+                    .map(|i| {
+                        mock_vote(PbftMessageType::Commit, 0, 2, vec![block_id], &key_pairs[i])
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert!(node
+            .verify_consensus_seal(&seal(2), vec![1], &mut state)
+            .is_ok());
+        assert_eq!(1, settings_calls());
+
+        // Members of a block that isn't in the history are read from the validator
+        assert!(node
+            .verify_consensus_seal(&seal(2), vec![5], &mut state)
+            .is_ok());
+        assert_eq!(2, settings_calls());
+    }
+
+    /// A validator that can't provide the on-chain members must not block the node, since the
+    /// node couldn't handle any messages or timeouts while it waited. Instead, the node must ask
+    /// again with exponential backoff between updates, and redo the work that needed the members
+    /// once they're read; a block whose seal can't be verified yet must not be failed. Until the
+    /// members of the last committed block are known, the node must not start the next sequence
+    /// number, since quorums for it must be formed from those members.
+    ///
+    /// This test commits block 1 and receives block 2 (whose seal proves block 1) while the
+    /// validator can't provide any settings, verifies that the node doesn't wait for the members,
+    /// but doesn't start sequence number 2, handle messages for it (they are held instead), or
+    /// fail block 2 either; that reads aren't retried before they're due; and that once the
+    /// members can be read, block 1's members are remembered, the commit is finished, and the held
+    /// messages and block 2 are handled.
+    #[test]
+    fn test_unavailable_members_are_read_later() {
+        let key_pairs = mock_signer_network(4);
+        let (mut node, mut state, service) = mock_node(
+            &mock_config_from_signer_network(&key_pairs),
+            key_pairs[0].pub_key.clone(),
+            mock_block(0),
+        );
+        let clock = ManualClock::new();
+        state.set_clock(Arc::new(clock.clone()));
+        state.idle_timeout.stop();
+        let settings_calls = || {
+            service
+                .calls()
+                .iter()
+                .filter(|call| call[0] == format!("{:?}", "get_settings"))
+                .count()
+        };
+
+        service.set_settings_unavailable(true);
+
+        // Committing block 1 doesn't wait for its members, but the node doesn't start sequence
+        // number 2 yet
+        assert!(node.on_block_commit(vec![1], &mut state).is_ok());
+        assert_eq!(2, state.seq_num);
+        assert!(state.member_history.get(&[1]).is_none());
+        assert_eq!(1, settings_calls());
+        assert!(!state.idle_timeout.is_active());
+        assert!(
+            !service.was_called_with_args(stringify_func_call!("initialize_block", Some(vec![1])))
+        );
+
+        // Messages for sequence number 2 are held rather than handled
+        assert!(node
+            .on_peer_message(
+                mock_msg(
+                    PbftMessageType::Prepare,
+                    0,
+                    2,
+                    key_pairs[1].pub_key.clone(),
+                    vec![2],
+                    false
+                ),
+                &mut state
+            )
+            .is_ok());
+        assert!(node
+            .msg_log
+            .get_messages_of_type_seq(PbftMessageType::Prepare, 2)
+            .is_empty());
+        assert_eq!(1, node.held_messages.len());
+
+        // Block 2 isn't handled until the commit is finished, but it isn't failed
+        node.msg_log.add_validated_block(mock_block(1));
+        let mut block2 = mock_block(2);
+        block2.payload = mock_seal(
+            0,
+            1,
+            vec![1],
+            &key_pairs[0],
+            (1..3)
+                .map(|i| mock_vote(PbftMessageType::Commit, 0, 1, vec![1], &key_pairs[i]))
+                .collect::<Vec<_>>(),
+        )
+        .write_to_bytes()
+        .expect("Failed to write seal to bytes");
+        node.msg_log.add_unvalidated_block(block2);
+        assert!(node.on_block_valid(vec![2], &mut state).is_ok());
+        assert!(!service.was_called("fail_block"));
+        assert_eq!(1, settings_calls());
+        assert_eq!(1, node.deferred.len());
+
+        // Reads aren't retried until the retry base has passed
+        node.retry_member_reads(&mut state);
+        assert_eq!(1, settings_calls());
+        clock.advance(state.exponential_retry_base);
+        node.retry_member_reads(&mut state);
+        assert_eq!(2, settings_calls());
+
+        // Failing again doubles the delay
+        clock.advance(state.exponential_retry_base);
+        node.retry_member_reads(&mut state);
+        assert_eq!(2, settings_calls());
+        clock.advance(state.exponential_retry_base);
+        service.set_settings_unavailable(false);
+        node.retry_member_reads(&mut state);
+
+        // Block 1's members are remembered, the commit was finished, and the held Prepare and
+        // block 2 were handled (which needed the members of block 0)
+        assert_eq!(4, settings_calls());
+        assert_eq!(Some(&state.member_ids[..]), state.member_history.get(&[1]));
+        assert!(state.idle_timeout.is_active());
+        assert!(
+            service.was_called_with_args(stringify_func_call!("initialize_block", Some(vec![1])))
+        );
+        assert!(node.pending_commit.is_none());
+        assert!(node.held_messages.is_empty());
+        assert_eq!(
+            1,
+            node.msg_log
+                .get_messages_of_type_seq(PbftMessageType::Prepare, 2)
+                .len()
+        );
+        assert!(node.deferred.is_empty());
+        assert!(node.member_reads.is_empty());
+        assert!(!service.was_called("fail_block"));
+        clock.advance(Duration::from_secs(60));
+        node.retry_member_reads(&mut state);
+        assert_eq!(4, settings_calls());
+    }
+
+    /// The work waiting for member lists must be bounded without dropping work that may still be
+    /// needed while there is work the node has moved past: stale work goes first, and only then
+    /// the oldest work.
+    #[test]
+    fn test_deferred_work_is_bounded() {
+        let (mut node, mut state, _) = mock_node(&mock_config(4), vec![0], mock_block(0));
+        state.seq_num = 5;
+
+        node.defer(Deferred::Block(mock_block(1)), &state);
+        for num in 5..(5 + DEFERRED_SIZE as u8 - 1) {
+            node.defer(Deferred::Block(mock_block(num)), &state);
+        }
+        node.defer(Deferred::Block(mock_block(5)), &state);
+        assert_eq!(DEFERRED_SIZE, node.deferred.len());
+
+        // The block the node has moved past is dropped to make room
+        node.defer(Deferred::Block(mock_block(100)), &state);
+        assert_eq!(DEFERRED_SIZE, node.deferred.len());
+        assert!(!node.deferred.contains(&Deferred::Block(mock_block(1))));
+        assert!(node.deferred.contains(&Deferred::Block(mock_block(5))));
+
+        // Without stale work, the oldest is dropped
+        node.defer(Deferred::Block(mock_block(101)), &state);
+        assert_eq!(DEFERRED_SIZE, node.deferred.len());
+        assert!(!node.deferred.contains(&Deferred::Block(mock_block(5))));
+        assert!(node.deferred.contains(&Deferred::Block(mock_block(101))));
+    }
+
     /// To keep memory usage under control, the PBFT log must be garbage-collected periodically.
     /// Every time a block gets committed (the node moves on to the next sequence number), the node
     /// will check if the number of messages in its logs exceeds a certain size; if it does, it
@@ -4057,22 +4465,14 @@ mod tests {
         // Add Block and PrePrepare for sequence numbers 1 and 2
         node.msg_log.add_validated_block(mock_block(1));
         node.msg_log.add_validated_block(mock_block(2));
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::PrePrepare,
-            0,
-            1,
-            vec![0],
-            vec![1],
-            false,
-        ));
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::PrePrepare,
-            0,
-            2,
-            vec![0],
-            vec![2],
-            false,
-        ));
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::PrePrepare, 0, 1, vec![0], vec![1], false),
+            &state,
+        );
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::PrePrepare, 0, 2, vec![0], vec![2], false),
+            &state,
+        );
 
         // Simulate commit of block 1; verify node is now at seq_num 2 and all messages are still
         // in the log since they all have seq_num >= state.seq_num - 1
@@ -4628,25 +5028,82 @@ mod tests {
     /// committed. To handle this, the node must check that it is not waiting for the commit
     /// confirmation (it will be in the `Finishing` phase if it is waiting).
     #[test]
-    fn test_catch_up_on_new_block() {
-        // Create signing keys for a new network and instantiate node 1
+    fn test_catch_up_on_new_block() {
+        // Create signing keys for a new network and instantiate node 1
+        let key_pairs = mock_signer_network(4);
+        let (mut node, mut state, service) = mock_node(
+            &mock_config_from_signer_network(&key_pairs),
+            key_pairs[1].pub_key.clone(),
+            mock_block(0),
+        );
+
+        // Receive block 1 (BlockNew and BlockValid) and verify that node is still PrePreparing
+        // (should not perform catch-up for current block)
+        assert!(node.on_block_new(mock_block(1), &mut state).is_ok());
+        assert!(node.on_block_valid(vec![1], &mut state).is_ok());
+        assert_eq!(PbftPhase::PrePreparing, state.phase);
+
+        // Receive block 2 (BlockNew and BlockValid) and verify that catch up was performed for
+        // block 1 (phase is Finishing(true) and Service.commit_block(block1.block_id) was called)
+        let mut block2 = mock_block(2);
+        block2.payload = mock_seal(
+            0,
+            1,
+            vec![1],
+            &key_pairs[0],
+            (2..4)
+                .map(|i| mock_vote(PbftMessageType::Commit, 0, 1, vec![1], &key_pairs[i]))
+                .collect::<Vec<_>>(),
+        )
+        .write_to_bytes()
+        .expect("Failed to write seal to bytes");
+        assert!(node.on_block_new(block2.clone(), &mut state).is_ok());
+        assert!(node.on_block_valid(vec![2], &mut state).is_ok());
+        assert_eq!(PbftPhase::Finishing(true), state.phase);
+        assert!(service.was_called_with_args(stringify_func_call!("commit_block", vec![1])));
+
+        // Receive block 2 again and verify that Service.commit_block was not called again (block
+        // was already committed)
+        assert!(node.on_block_new(block2, &mut state).is_ok());
+        assert!(node.on_block_valid(vec![2], &mut state).is_ok());
+        assert!(service.was_called_with_args_once(stringify_func_call!("commit_block")));
+    }
+
+    /// The `catchup` method assumes the seal has been verified, but it must not assume that the
+    /// seal has any votes: a seal only carries votes from the members besides its signer, so the
+    /// view the block was committed in must be taken from the seal itself. Catching up with a seal
+    /// that has no votes must not panic.
+    #[test]
+    fn test_catch_up_with_empty_seal() {
+        let key_pairs = mock_signer_network(4);
+        let (mut node, mut state, service) = mock_node(
+            &mock_config_from_signer_network(&key_pairs),
+            key_pairs[1].pub_key.clone(),
+            mock_block(0),
+        );
+
+        let seal = mock_seal(1, 1, vec![1], &key_pairs[0], vec![]);
+        assert!(node.catchup(&mut state, &seal, false).is_ok());
+
+        assert_eq!(PbftPhase::Finishing(false), state.phase);
+        assert_eq!(1, state.view);
+        assert!(service.was_called_with_args(stringify_func_call!("commit_block", vec![1])));
+    }
+
+    /// A node that has already moved on to a later view (because the rest of the network changed
+    /// views after committing the block) must not go back to the earlier view of the seal it uses
+    /// to catch up; views must never decrease.
+    #[test]
+    fn test_catch_up_keeps_later_view() {
         let key_pairs = mock_signer_network(4);
         let (mut node, mut state, service) = mock_node(
             &mock_config_from_signer_network(&key_pairs),
             key_pairs[1].pub_key.clone(),
             mock_block(0),
         );
+        state.view = 2;
 
-        // Receive block 1 (BlockNew and BlockValid) and verify that node is still PrePreparing
-        // (should not perform catch-up for current block)
-        assert!(node.on_block_new(mock_block(1), &mut state).is_ok());
-        assert!(node.on_block_valid(vec![1], &mut state).is_ok());
-        assert_eq!(PbftPhase::PrePreparing, state.phase);
-
-        // Receive block 2 (BlockNew and BlockValid) and verify that catch up was performed for
-        // block 1 (phase is Finishing(true) and Service.commit_block(block1.block_id) was called)
-        let mut block2 = mock_block(2);
-        block2.payload = mock_seal(
+        let seal = mock_seal(
             0,
             1,
             vec![1],
@@ -4654,19 +5111,11 @@ mod tests {
             (2..4)
                 .map(|i| mock_vote(PbftMessageType::Commit, 0, 1, vec![1], &key_pairs[i]))
                 .collect::<Vec<_>>(),
-        )
-        .write_to_bytes()
-        .expect("Failed to write seal to bytes");
-        assert!(node.on_block_new(block2.clone(), &mut state).is_ok());
-        assert!(node.on_block_valid(vec![2], &mut state).is_ok());
-        assert_eq!(PbftPhase::Finishing(true), state.phase);
-        assert!(service.was_called_with_args(stringify_func_call!("commit_block", vec![1])));
+        );
+        assert!(node.catchup(&mut state, &seal, false).is_ok());
 
-        // Receive block 2 again and verify that Service.commit_block was not called again (block
-        // was already committed)
-        assert!(node.on_block_new(block2, &mut state).is_ok());
-        assert!(node.on_block_valid(vec![2], &mut state).is_ok());
-        assert!(service.was_called_with_args_once(stringify_func_call!("commit_block")));
+        assert_eq!(2, state.view);
+        assert!(service.was_called_with_args(stringify_func_call!("commit_block", vec![1])));
     }
 
     /// When a node that is on block/seq_num `n` receives a block `m` (where `m > n + 1`), it will
@@ -4773,30 +5222,18 @@ mod tests {
         state.seq_num = 2;
 
         // Add messages needed to build seal for block 1
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            1,
-            vec![0],
-            vec![1],
-            true,
-        ));
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            1,
-            vec![1],
-            vec![1],
-            false,
-        ));
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            1,
-            vec![2],
-            vec![1],
-            false,
-        ));
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 1, vec![0], vec![1], true),
+            &state,
+        );
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 1, vec![1], vec![1], false),
+            &state,
+        );
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 1, vec![2], vec![1], false),
+            &state,
+        );
 
         // Receive a SealRequest for sequence number 2 and verify that a seal is sent to the node
         // that requested it
@@ -4817,30 +5254,18 @@ mod tests {
         )));
 
         // Add messages needed to build seal for block 2
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            2,
-            vec![0],
-            vec![2],
-            true,
-        ));
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            2,
-            vec![1],
-            vec![2],
-            false,
-        ));
-        node.msg_log.add_message(mock_msg(
-            PbftMessageType::Commit,
-            0,
-            2,
-            vec![2],
-            vec![2],
-            false,
-        ));
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 2, vec![0], vec![2], true),
+            &state,
+        );
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 2, vec![1], vec![2], false),
+            &state,
+        );
+        node.msg_log.add_message(
+            mock_msg(PbftMessageType::Commit, 0, 2, vec![2], vec![2], false),
+            &state,
+        );
 
         // Verify SealRequests for old or future sequence numbers are ignored
         node.on_peer_message(
@@ -5087,12 +5512,15 @@ mod tests {
         )));
 
         // Verify Commit messages are sent to all peers that are already connected on node startup
-        let peers = vec![PeerInfo { peer_id: vec![2] }, PeerInfo { peer_id: vec![3] }];
+        let peers = vec![
+            sdk::PeerInfo { peer_id: vec![2] },
+            sdk::PeerInfo { peer_id: vec![3] },
+        ];
         let mut state2 = PbftState::new(vec![1], 2, &mock_config(4));
         let service2 = MockService::new(&mock_config(4));
         let _node2 = PbftNode::new(
             &mock_config(4),
-            mock_block(2),
+            mock_block(2).into(),
             peers,
             Box::new(service2.clone()),
             &mut state2,
@@ -5110,4 +5538,378 @@ mod tests {
             mock_msg(PbftMessageType::Commit, 0, 2, vec![1], vec![2], false).message_bytes
         )));
     }
+
+    /// Implementation of `EventSink` that collects published events so they can be checked
+    #[derive(Clone, Default)]
+    struct MockEventSink {
+        events: Rc<RefCell<Vec<ConsensusEvent>>>,
+    }
+
+    impl EventSink for MockEventSink {
+        fn publish(&mut self, event: &ConsensusEvent) -> Result<(), String> {
+            self.events.borrow_mut().push(event.clone());
+            Ok(())
+        }
+    }
+
+    /// External services can subscribe to a stream of consensus events to react to finality and
+    /// to changes in the network's health. The node publishes:
+    ///
+    /// 1. `BlockFinalized` when a block is committed, including the seal that proves the commit
+    /// 2. `FaultyPrimaryDetected` when the primary sends a message that proves it is faulty
+    /// 3. `ViewChangeStarted` when the node starts a view change
+    /// 4. `ViewChangeCompleted` when the node accepts a `NewView`
+    /// 5. `CatchupStarted` when the node starts committing blocks using seals from the network
+    /// 6. `CatchupFinished` when the node commits the last block it was catching up on
+    ///
+    /// This test verifies that each of these events is published in the situations listed above.
+    #[test]
+    #[allow(unused_must_use)]
+    fn test_consensus_event_publishing() {
+        // Create a new node 0 on a 4 node network and give it an event sink
+        let key_pairs = mock_signer_network(4);
+        let (mut node, mut state, _) = mock_node(
+            &mock_config_from_signer_network(&key_pairs),
+            key_pairs[0].pub_key.clone(),
+            mock_block(0),
+        );
+        let sink = MockEventSink::default();
+        node.set_event_sink(Box::new(sink.clone()));
+
+        // Commit block 1 with the Commits required to build a seal for it in the log; verify that
+        // the block is published with its seal
+        for i in 1..3 {
+            node.msg_log.add_message(
+                ParsedMessage::from_signed_vote(&mock_vote(
+                    PbftMessageType::Commit,
+                    0,
+                    1,
+                    vec![1],
+                    &key_pairs[i],
+                ))
+                .expect("Failed to parse vote"),
+                &state,
+            );
+        }
+        state.phase = PbftPhase::Finishing(false);
+        assert!(node.on_block_commit(vec![1], &mut state).is_ok());
+        match &sink.events.borrow()[0] {
+            ConsensusEvent::BlockFinalized {
+                block_id,
+                block_num,
+                view,
+                seal,
+            } => {
+                assert_eq!("01", block_id);
+                assert_eq!(1, *block_num);
+                assert_eq!(0, *view);
+                let seal: PbftSeal = protobuf::parse_from_bytes(
+                    &hex::decode(seal.as_ref().expect("Seal not published")).unwrap(),
+                )
+                .expect("Failed to parse seal");
+                assert_eq!(vec![1], seal.block_id);
+                assert_eq!(2, seal.get_commit_votes().len());
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+
+        // Receive a Prepare from the primary (this node); verify that the faulty primary is
+        // reported and the view change is published
+        node.on_peer_message(
+            mock_msg(
+                PbftMessageType::Prepare,
+                0,
+                2,
+                key_pairs[0].pub_key.clone(),
+                vec![2],
+                true,
+            ),
+            &mut state,
+        );
+        match &sink.events.borrow()[1] {
+            ConsensusEvent::FaultyPrimaryDetected { view, primary, .. } => {
+                assert_eq!(0, *view);
+                assert_eq!(&hex::encode(&key_pairs[0].pub_key), primary);
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+        assert_eq!(
+            ConsensusEvent::ViewChangeStarted {
+                from_view: 0,
+                to_view: 1,
+            },
+            sink.events.borrow()[2]
+        );
+
+        // Accept a valid NewView for view 1; verify that the completed view change is published
+        let new_view = mock_new_view(
+            1,
+            1,
+            &key_pairs[1],
+            (2..4)
+                .map(|i| mock_vote(PbftMessageType::ViewChange, 1, 1, vec![], &key_pairs[i]))
+                .collect::<Vec<_>>(),
+        );
+        assert!(node
+            .on_peer_message(
+                ParsedMessage::from_new_view_message(new_view).expect("Failed to parse NewView"),
+                &mut state
+            )
+            .is_ok());
+        assert_eq!(
+            ConsensusEvent::ViewChangeCompleted {
+                view: 1,
+                primary: hex::encode(&key_pairs[1].pub_key),
+            },
+            sink.events.borrow()[3]
+        );
+
+        // Catch up to block 2 using a seal; verify that the start of catch-up is published, then
+        // that the block and the end of catch-up are published when the block is committed
+        let seal = mock_seal(
+            1,
+            2,
+            vec![2],
+            &key_pairs[1],
+            (2..4)
+                .map(|i| mock_vote(PbftMessageType::Commit, 1, 2, vec![2], &key_pairs[i]))
+                .collect::<Vec<_>>(),
+        );
+        assert!(node.catchup(&mut state, &seal, false).is_ok());
+        assert_eq!(
+            ConsensusEvent::CatchupStarted {
+                seq_num: 2,
+                block_id: "02".into(),
+            },
+            sink.events.borrow()[4]
+        );
+
+        assert!(node.on_block_commit(vec![2], &mut state).is_ok());
+        match &sink.events.borrow()[5] {
+            ConsensusEvent::BlockFinalized {
+                block_num, seal, ..
+            } => {
+                assert_eq!(2, *block_num);
+                assert!(seal.is_some());
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+        assert_eq!(
+            ConsensusEvent::CatchupFinished {
+                seq_num: 2,
+                block_id: "02".into(),
+            },
+            sink.events.borrow()[6]
+        );
+        assert_eq!(7, sink.events.borrow().len());
+    }
+
+    /// To prove to a light client that a block is final, a node must be able to produce a
+    /// `PbftFinalityProof` for any block it has committed. The proof consists of:
+    ///
+    /// 1. The block's header
+    /// 2. The seal for the block, which comes from the next block's payload or, if the block is
+    ///    the node's chain head, is built from the node's log
+    /// 3. The on-chain members at the block's predecessor, which the seal is verified against
+    ///
+    /// This test verifies that proofs are built for committed blocks (both the chain head and
+    /// earlier blocks) and that the seal in each proof is valid, and that no proof is produced for
+    /// the genesis block, uncommitted blocks, unknown blocks, blocks that aren't on the node's
+    /// chain, or blocks deeper than `max_finality_proof_depth`. Building a proof must not
+    /// schedule member reads.
+    #[test]
+    fn test_finality_proof() {
+        // Create a new node 0 on a 4 node network with block 3 as its chain head
+        let key_pairs = mock_signer_network(4);
+        let (mut node, mut state, service) = mock_node(
+            &mock_config_from_signer_network(&key_pairs),
+            key_pairs[0].pub_key.clone(),
+            mock_block(3),
+        );
+
+        // Make blocks 0-4 available from the validator, where each block's payload is the seal for
+        // its predecessor
+        let seal_for = |num: u8| {
+            mock_seal(
+                0,
+                num as u64,
+                vec![num],
+                &key_pairs[0],
+                (1..3)
+                    .map(|i| {
+                        mock_vote(
+                            PbftMessageType::Commit,
+                            0,
+                            num as u64,
+                            vec![num],
+                            &key_pairs[i],
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        };
+        for num in 0..5 {
+            let mut block = mock_block(num);
+            if num > 1 {
+                block.payload = seal_for(num - 1)
+                    .write_to_bytes()
+                    .expect("Failed to write seal to bytes");
+            }
+            service.add_block(block);
+        }
+
+        // Verify the proof for block 1, whose seal is in block 2
+        let proof = node
+            .get_finality_proof(&[1], &state)
+            .expect("Failed to get finality proof for block 1");
+        assert_eq!(vec![1], proof.get_header().get_block_id());
+        assert_eq!(vec![0], proof.get_header().get_previous_id());
+        assert_eq!(1, proof.get_header().get_block_num());
+        assert_eq!(seal_for(1), *proof.get_seal());
+        assert_eq!(state.member_ids, proof.get_members().to_vec());
+        assert!(node
+            .verify_consensus_seal(proof.get_seal(), vec![0], &mut state)
+            .is_ok());
+
+        // Verify that the proof for the chain head can't be produced until the node has the
+        // Commits it needs to build the seal, then verify the proof
+        assert!(node.get_finality_proof(&[3], &state).is_err());
+        for i in 1..3 {
+            node.msg_log.add_message(
+                ParsedMessage::from_signed_vote(&mock_vote(
+                    PbftMessageType::Commit,
+                    0,
+                    3,
+                    vec![3],
+                    &key_pairs[i],
+                ))
+                .expect("Failed to parse vote"),
+                &state,
+            );
+        }
+        let proof = node
+            .get_finality_proof(&[3], &state)
+            .expect("Failed to get finality proof for block 3");
+        assert_eq!(3, proof.get_header().get_block_num());
+        assert_eq!(vec![3], proof.get_seal().get_block_id());
+        assert!(node
+            .verify_consensus_seal(proof.get_seal(), vec![2], &mut state)
+            .is_ok());
+
+        // Verify that there are no proofs for the genesis block, an uncommitted block, an unknown
+        // block, or a block on a different fork
+        assert!(node.get_finality_proof(&[0], &state).is_err());
+        assert!(node.get_finality_proof(&[4], &state).is_err());
+        assert!(node.get_finality_proof(&[5], &state).is_err());
+        let mut fork = mock_block(2);
+        fork.block_id = vec![0xff];
+        service.add_block(fork);
+        assert!(node.get_finality_proof(&[0xff], &state).is_err());
+
+        // Verify that a proof isn't built for a block further behind the chain head than the
+        // configured depth, and that the error names the setting
+        node.max_finality_proof_depth = 1;
+        let err = node
+            .get_finality_proof(&[1], &state)
+            .expect_err("Built a proof deeper than the configured depth");
+        assert!(err.to_string().contains("max_finality_proof_depth"));
+        node.max_finality_proof_depth = 100;
+
+        // Verify that a failure to read the members for a proof doesn't schedule a member read
+        service.set_settings_unavailable(true);
+        assert!(node.get_finality_proof(&[1], &state).is_err());
+        assert!(node.next_member_read().is_none());
+    }
+
+    /// Nodes track how often each member's Prepare, Commit, and ViewChange messages arrive and
+    /// how often each member has a vote in verified seals, so operators can find members that
+    /// are consistently absent.
+    ///
+    /// This test verifies that messages received by the node and votes in verified seals are
+    /// reflected in the participation report once the block they're for is committed, and that
+    /// members that weren't heard from are flagged.
+    #[test]
+    fn test_participation_tracking() {
+        // Create a new node 0 on a 4 node network
+        let key_pairs = mock_signer_network(4);
+        let (mut node, mut state, _) = mock_node(
+            &mock_config_from_signer_network(&key_pairs),
+            key_pairs[0].pub_key.clone(),
+            mock_block(0),
+        );
+
+        // Receive a Prepare from node 1 and a Commit from node 2 for block 1, and a ViewChange
+        // from node 1
+        node.on_peer_message(
+            mock_msg(
+                PbftMessageType::Prepare,
+                0,
+                1,
+                key_pairs[1].pub_key.clone(),
+                vec![1],
+                false,
+            ),
+            &mut state,
+        )
+        .expect("Failed to handle Prepare");
+        node.on_peer_message(
+            mock_msg(
+                PbftMessageType::Commit,
+                0,
+                1,
+                key_pairs[2].pub_key.clone(),
+                vec![1],
+                false,
+            ),
+            &mut state,
+        )
+        .expect("Failed to handle Commit");
+        let _ = node.on_peer_message(
+            mock_msg(
+                PbftMessageType::ViewChange,
+                1,
+                1,
+                key_pairs[1].pub_key.clone(),
+                vec![],
+                false,
+            ),
+            &mut state,
+        );
+
+        // Nothing has been committed yet, so no one is flagged
+        assert_eq!(0, node.participation_report(&state).flagged().count());
+
+        // Verify a seal for block 1 with votes from nodes 2 and 3
+        let seal = mock_seal(
+            0,
+            1,
+            vec![1],
+            &key_pairs[0],
+            (2..4)
+                .map(|i| mock_vote(PbftMessageType::Commit, 0, 1, vec![1], &key_pairs[i]))
+                .collect(),
+        );
+        node.verify_consensus_seal(&seal, vec![0], &mut state)
+            .expect("Seal verification failed");
+
+        // Commit block 1 and check the report
+        state.mode = PbftMode::Normal;
+        state.phase = PbftPhase::Finishing(false);
+        node.on_block_commit(vec![1], &mut state)
+            .expect("Failed to commit block");
+        let report = node.participation_report(&state);
+        assert_eq!(1, report.rounds);
+        assert_eq!(1, report.members[1].prepares);
+        assert_eq!(1, report.members[1].view_changes);
+        assert_eq!(1, report.members[2].commits);
+        assert_eq!(1, report.members[2].seal_votes);
+        assert_eq!(1, report.members[3].seal_votes);
+        assert_eq!(
+            vec![hex::encode(&key_pairs[0].pub_key)],
+            report
+                .flagged()
+                .map(|activity| activity.member.clone())
+                .collect::<Vec<_>>()
+        );
+    }
 }
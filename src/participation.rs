@@ -0,0 +1,298 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Tracking of how consistently each member takes part in consensus
+//!
+//! A member that never sends its votes doesn't break the network, but it silently reduces the
+//! number of faults the network can tolerate. The tracker records which members were heard from
+//! for each of the most recently committed sequence numbers so these members can be found.
+
+use std::collections::{BTreeMap, HashSet};
+
+use hex;
+
+use crate::host::PeerId;
+use crate::message_type::PbftMessageType;
+
+/// The members heard from for a single sequence number
+#[derive(Debug, Default)]
+struct Round {
+    prepares: HashSet<PeerId>,
+    commits: HashSet<PeerId>,
+    seal_votes: HashSet<PeerId>,
+}
+
+impl Round {
+    fn includes(&self, member: &[u8]) -> bool {
+        self.prepares.contains(member)
+            || self.commits.contains(member)
+            || self.seal_votes.contains(member)
+    }
+}
+
+/// Records member activity over a sliding window of sequence numbers (and views, for view
+/// changes)
+#[derive(Debug)]
+pub struct ParticipationTracker {
+    /// How many of the most recent sequence numbers and views are tracked
+    window: u64,
+    /// Members that participated in fewer than this fraction of rounds are flagged
+    threshold: f64,
+    rounds: BTreeMap<u64, Round>,
+    /// Senders of ViewChange messages for each view
+    view_changes: BTreeMap<u64, HashSet<PeerId>>,
+}
+
+/// How active a single member has been over the window
+#[derive(Debug, Serialize)]
+pub struct MemberActivity {
+    /// Hex-encoded ID of the member
+    pub member: String,
+    /// Number of sequence numbers in the window that a Prepare arrived for
+    pub prepares: u64,
+    /// Number of sequence numbers in the window that a Commit arrived for
+    pub commits: u64,
+    /// Number of sequence numbers in the window that this member has a vote in the seal for
+    pub seal_votes: u64,
+    /// Number of the view changes in the window that this member voted for
+    pub view_changes: u64,
+    /// Fraction of sequence numbers in the window that this member was heard from at all
+    pub participation: f64,
+    /// Whether participation is below the configured threshold
+    pub below_threshold: bool,
+}
+
+/// Activity of all members over the window
+#[derive(Debug, Serialize)]
+pub struct ParticipationReport {
+    /// Number of committed sequence numbers in the window
+    pub rounds: u64,
+    /// Number of views in the window that any member voted to change to
+    pub view_change_rounds: u64,
+    pub threshold: f64,
+    pub members: Vec<MemberActivity>,
+}
+
+impl ParticipationReport {
+    /// The members whose participation is below the threshold
+    pub fn flagged(&self) -> impl Iterator<Item = &MemberActivity> {
+        self.members
+            .iter()
+            .filter(|activity| activity.below_threshold)
+    }
+}
+
+impl ParticipationTracker {
+    pub fn new(window: u64, threshold: f64) -> Self {
+        ParticipationTracker {
+            window,
+            threshold,
+            rounds: BTreeMap::new(),
+            view_changes: BTreeMap::new(),
+        }
+    }
+
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    /// Record the arrival of a message from a member, given the node's current sequence number
+    /// and view
+    ///
+    /// Messages for sequence numbers or views more than a window away from the node's are
+    /// ignored, so members can't make the tracker grow by claiming arbitrary ones.
+    pub fn record_message(
+        &mut self,
+        msg_type: PbftMessageType,
+        view: u64,
+        seq_num: u64,
+        signer_id: &[u8],
+        current_seq_num: u64,
+        current_view: u64,
+    ) {
+        let within_window =
+            |value: u64, current: u64| value.max(current) - value.min(current) <= self.window;
+        if !within_window(seq_num, current_seq_num) || !within_window(view, current_view) {
+            return;
+        }
+
+        match msg_type {
+            PbftMessageType::Prepare => {
+                self.round(seq_num).prepares.insert(signer_id.to_vec());
+            }
+            PbftMessageType::Commit => {
+                self.round(seq_num).commits.insert(signer_id.to_vec());
+            }
+            PbftMessageType::ViewChange => {
+                self.view_changes
+                    .entry(view)
+                    .or_default()
+                    .insert(signer_id.to_vec());
+            }
+            _ => {}
+        }
+    }
+
+    /// Record the members with a vote in the (verified) seal for the given sequence number
+    pub fn record_seal_votes<I>(&mut self, seq_num: u64, voter_ids: I)
+    where
+        I: IntoIterator<Item = PeerId>,
+    {
+        self.round(seq_num).seal_votes.extend(voter_ids);
+    }
+
+    /// Forget everything that is older than the window, given the node's current sequence number
+    /// and view
+    pub fn prune(&mut self, seq_num: u64, view: u64) {
+        self.rounds = self.rounds.split_off(&seq_num.saturating_sub(self.window));
+        self.view_changes = self
+            .view_changes
+            .split_off(&view.saturating_sub(self.window).saturating_add(1));
+    }
+
+    /// Build a report on the given members over the committed sequence numbers in the window,
+    /// given the node's current sequence number and view
+    pub fn report(&self, members: &[PeerId], seq_num: u64, view: u64) -> ParticipationReport {
+        // The current sequence number hasn't been committed yet, so it is not part of the window
+        let first_seq_num = seq_num.saturating_sub(self.window).max(1).min(seq_num);
+        let rounds = seq_num.saturating_sub(first_seq_num);
+        let in_window = || self.rounds.range(first_seq_num..seq_num).map(|(_, r)| r);
+
+        let first_view = view.saturating_sub(self.window).saturating_add(1);
+        let view_changes = || self.view_changes.range(first_view..).map(|(_, v)| v);
+
+        let members = members
+            .iter()
+            .map(|member| {
+                let mut activity = MemberActivity {
+                    member: hex::encode(member),
+                    prepares: 0,
+                    commits: 0,
+                    seal_votes: 0,
+                    view_changes: view_changes()
+                        .filter(|voters| voters.contains(member))
+                        .count() as u64,
+                    participation: 1.0,
+                    below_threshold: false,
+                };
+
+                let mut rounds_heard = 0;
+                for round in in_window() {
+                    activity.prepares += round.prepares.contains(member) as u64;
+                    activity.commits += round.commits.contains(member) as u64;
+                    activity.seal_votes += round.seal_votes.contains(member) as u64;
+                    rounds_heard += round.includes(member) as u64;
+                }
+
+                if rounds > 0 {
+                    activity.participation = rounds_heard as f64 / rounds as f64;
+                    activity.below_threshold = activity.participation < self.threshold;
+                }
+
+                activity
+            })
+            .collect();
+
+        ParticipationReport {
+            rounds,
+            view_change_rounds: view_changes().count() as u64,
+            threshold: self.threshold,
+            members,
+        }
+    }
+
+    fn round(&mut self, seq_num: u64) -> &mut Round {
+        self.rounds.entry(seq_num).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Members are rated by the fraction of committed sequence numbers in the window that they
+    /// were heard from (by a Prepare, a Commit, or a vote in a seal), and members below the
+    /// threshold are flagged. Activity outside of the window, including for the current
+    /// (uncommitted) sequence number, must not count.
+    #[test]
+    fn test_participation_tracking() {
+        let members = vec![vec![0], vec![1], vec![2], vec![3]];
+        let mut tracker = ParticipationTracker::new(4, 0.5);
+
+        // Node 0 is heard from for every sequence number by a different kind of vote, node 1
+        // only sends Prepares, node 2 only shows up in seals for half of the rounds, and node 3
+        // is only heard from outside of the window
+        for seq_num in 1..10 {
+            match seq_num % 3 {
+                0 => tracker.record_message(PbftMessageType::Prepare, 0, seq_num, &[0], seq_num, 0),
+                1 => tracker.record_message(PbftMessageType::Commit, 0, seq_num, &[0], seq_num, 0),
+                _ => tracker.record_seal_votes(seq_num, vec![vec![0]]),
+            }
+            tracker.record_message(PbftMessageType::Prepare, 0, seq_num, &[1], seq_num, 0);
+            if seq_num % 2 == 0 {
+                tracker.record_seal_votes(seq_num, vec![vec![2]]);
+            }
+        }
+        tracker.record_message(PbftMessageType::Commit, 0, 2, &[3], 2, 0);
+        tracker.record_message(PbftMessageType::Commit, 0, 9, &[3], 9, 0);
+        tracker.record_message(PbftMessageType::ViewChange, 3, 9, &[1], 9, 0);
+        tracker.record_message(PbftMessageType::ViewChange, 3, 9, &[2], 9, 0);
+        tracker.record_message(PbftMessageType::ViewChange, 4, 9, &[2], 9, 0);
+
+        // Messages for sequence numbers and views far from the node's are ignored
+        tracker.record_message(PbftMessageType::Commit, 0, u64::MAX, &[3], 9, 0);
+        tracker.record_message(PbftMessageType::ViewChange, 1000, 9, &[3], 9, 4);
+        assert!(!tracker.rounds.contains_key(&u64::MAX));
+        assert!(!tracker.view_changes.contains_key(&1000));
+
+        // The node is on sequence number 9 and view 4, so the window is 5-8 and views 1-4
+        tracker.prune(9, 4);
+        let report = tracker.report(&members, 9, 4);
+        assert_eq!(4, report.rounds);
+        assert_eq!(2, report.view_change_rounds);
+
+        let assert_participation = |expected: f64, activity: &MemberActivity| {
+            assert!(
+                (expected - activity.participation).abs() < 1e-9,
+                "{:?}",
+                activity
+            );
+        };
+        assert_participation(1.0, &report.members[0]);
+        assert_participation(1.0, &report.members[1]);
+        assert_eq!(4, report.members[1].prepares);
+        assert_eq!(0, report.members[1].commits);
+        assert_eq!(1, report.members[1].view_changes);
+        assert_participation(0.5, &report.members[2]);
+        assert_eq!(2, report.members[2].seal_votes);
+        assert_eq!(2, report.members[2].view_changes);
+        assert_participation(0.0, &report.members[3]);
+
+        assert_eq!(
+            vec!["03"],
+            report
+                .flagged()
+                .map(|activity| activity.member.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        // A node that hasn't committed anything yet has nothing to flag
+        let report = ParticipationTracker::new(4, 0.5).report(&members, 1, 0);
+        assert_eq!(0, report.rounds);
+        assert_eq!(0, report.flagged().count());
+    }
+}
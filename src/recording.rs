@@ -0,0 +1,1058 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Recording of a node's interaction with its validator, and replay of recordings
+//!
+//! A recording is a file of JSON lines. It starts with the node's configuration, startup state,
+//! and initial `PbftState`; after that, it contains every update received from the validator and
+//! every call the node made to its `Service` along with the result the validator returned. Calls
+//! belong to the update that precedes them (or to the node's startup, if no update does).
+//!
+//! Replaying a recording feeds the updates to a fresh node in the same order, at the same times
+//! (as measured by the node's clock), and answers its service calls with the recorded results.
+//! Since the node's behavior only depends on these, it goes through exactly the same state
+//! transitions as the recorded node; if it makes a different call than the one recorded, the
+//! replay stops and reports where the two diverged.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::rc::Rc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hex;
+use sawtooth_sdk::consensus::engine::{
+    Block, BlockId, Error, PeerId, PeerInfo, PeerMessage, PeerMessageHeader, Update,
+};
+use sawtooth_sdk::consensus::service::Service;
+use serde_json::{self, Value};
+
+use crate::config::PbftConfig;
+use crate::engine::process_update;
+use crate::node::PbftNode;
+use crate::state::PbftState;
+use crate::timing::{Clock, ClockRef, ManualClock, Ticker};
+
+/// A clock that only moves forward when the engine handles the next update
+///
+/// Every reading of the clock while an update is being handled gives the same time (plus the
+/// time the node spent sleeping), so recording the time once per update is enough to reproduce
+/// every reading during replay.
+#[derive(Debug)]
+pub struct RecordingClock {
+    inner: ClockRef,
+    now: Mutex<Instant>,
+}
+
+impl RecordingClock {
+    pub fn new(inner: ClockRef) -> Self {
+        let now = inner.now();
+        RecordingClock {
+            inner,
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Catch up with the real time
+    pub fn step(&self) {
+        let mut now = self.now.lock().expect("Recording clock lock poisoned");
+        *now = cmp::max(*now, self.inner.now());
+    }
+}
+
+impl Clock for RecordingClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().expect("Recording clock lock poisoned")
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.inner.sleep(duration);
+        *self.now.lock().expect("Recording clock lock poisoned") += duration;
+    }
+
+    /// Waiting happens outside of handling an update, so it goes by the inner clock
+    fn time_until(&self, deadline: Instant) -> Duration {
+        self.inner.time_until(deadline)
+    }
+}
+
+/// A single line of a recording
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum Record {
+    Start {
+        config: PbftConfig,
+        startup: RecordedStartup,
+        /// The serialized `PbftState` the node started with
+        state: Value,
+    },
+    Update {
+        /// Milliseconds since the Unix epoch, for matching the update with the node's logs
+        timestamp: u64,
+        /// Time on the node's clock since the recording started
+        elapsed_nanos: u64,
+        update: RecordedUpdate,
+    },
+    Call {
+        call: ServiceCall,
+        result: CallResult,
+    },
+}
+
+/// The parts of the validator's `StartupState` the node is created with
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedStartup {
+    chain_head: RecordedBlock,
+    peers: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedBlock {
+    pub block_id: String,
+    pub previous_id: String,
+    pub signer_id: String,
+    pub block_num: u64,
+    pub payload: String,
+    pub summary: String,
+}
+
+impl RecordedBlock {
+    fn from_block(block: &Block) -> Self {
+        RecordedBlock {
+            block_id: hex::encode(&block.block_id),
+            previous_id: hex::encode(&block.previous_id),
+            signer_id: hex::encode(&block.signer_id),
+            block_num: block.block_num,
+            payload: hex::encode(&block.payload),
+            summary: hex::encode(&block.summary),
+        }
+    }
+
+    fn into_block(self) -> Result<Block, String> {
+        Ok(Block {
+            block_id: decode("block ID", &self.block_id)?,
+            previous_id: decode("previous ID", &self.previous_id)?,
+            signer_id: decode("signer ID", &self.signer_id)?,
+            block_num: self.block_num,
+            payload: decode("payload", &self.payload)?,
+            summary: decode("summary", &self.summary)?,
+        })
+    }
+}
+
+/// An update from the validator, or the lack of one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedUpdate {
+    PeerConnected {
+        peer_id: String,
+    },
+    PeerDisconnected {
+        peer_id: String,
+    },
+    PeerMessage {
+        sender_id: String,
+        signer_id: String,
+        content_sha512: String,
+        message_type: String,
+        name: String,
+        version: String,
+        header_bytes: String,
+        header_signature: String,
+        content: String,
+    },
+    BlockNew {
+        block: RecordedBlock,
+    },
+    BlockValid {
+        block_id: String,
+    },
+    BlockInvalid {
+        block_id: String,
+    },
+    BlockCommit {
+        block_id: String,
+    },
+    Shutdown,
+    /// No update arrived before the receive timeout
+    Timeout,
+    /// The validator disconnected
+    Disconnected,
+}
+
+impl RecordedUpdate {
+    fn from_update(update: &Result<Update, RecvTimeoutError>) -> Self {
+        match update {
+            Ok(Update::PeerConnected(info)) => RecordedUpdate::PeerConnected {
+                peer_id: hex::encode(&info.peer_id),
+            },
+            Ok(Update::PeerDisconnected(peer_id)) => RecordedUpdate::PeerDisconnected {
+                peer_id: hex::encode(peer_id),
+            },
+            Ok(Update::PeerMessage(message, sender_id)) => RecordedUpdate::PeerMessage {
+                sender_id: hex::encode(sender_id),
+                signer_id: hex::encode(&message.header.signer_id),
+                content_sha512: hex::encode(&message.header.content_sha512),
+                message_type: message.header.message_type.clone(),
+                name: message.header.name.clone(),
+                version: message.header.version.clone(),
+                header_bytes: hex::encode(&message.header_bytes),
+                header_signature: hex::encode(&message.header_signature),
+                content: hex::encode(&message.content),
+            },
+            Ok(Update::BlockNew(block)) => RecordedUpdate::BlockNew {
+                block: RecordedBlock::from_block(block),
+            },
+            Ok(Update::BlockValid(block_id)) => RecordedUpdate::BlockValid {
+                block_id: hex::encode(block_id),
+            },
+            Ok(Update::BlockInvalid(block_id)) => RecordedUpdate::BlockInvalid {
+                block_id: hex::encode(block_id),
+            },
+            Ok(Update::BlockCommit(block_id)) => RecordedUpdate::BlockCommit {
+                block_id: hex::encode(block_id),
+            },
+            Ok(Update::Shutdown) => RecordedUpdate::Shutdown,
+            Err(RecvTimeoutError::Timeout) => RecordedUpdate::Timeout,
+            Err(RecvTimeoutError::Disconnected) => RecordedUpdate::Disconnected,
+        }
+    }
+
+    fn into_update(self) -> Result<Result<Update, RecvTimeoutError>, String> {
+        Ok(Ok(match self {
+            RecordedUpdate::PeerConnected { peer_id } => Update::PeerConnected(PeerInfo {
+                peer_id: decode("peer ID", &peer_id)?,
+            }),
+            RecordedUpdate::PeerDisconnected { peer_id } => {
+                Update::PeerDisconnected(decode("peer ID", &peer_id)?)
+            }
+            RecordedUpdate::PeerMessage {
+                sender_id,
+                signer_id,
+                content_sha512,
+                message_type,
+                name,
+                version,
+                header_bytes,
+                header_signature,
+                content,
+            } => Update::PeerMessage(
+                PeerMessage {
+                    header: PeerMessageHeader {
+                        signer_id: decode("signer ID", &signer_id)?,
+                        content_sha512: decode("content hash", &content_sha512)?,
+                        message_type,
+                        name,
+                        version,
+                    },
+                    header_bytes: decode("header", &header_bytes)?,
+                    header_signature: decode("header signature", &header_signature)?,
+                    content: decode("content", &content)?,
+                },
+                decode("sender ID", &sender_id)?,
+            ),
+            RecordedUpdate::BlockNew { block } => Update::BlockNew(block.into_block()?),
+            RecordedUpdate::BlockValid { block_id } => {
+                Update::BlockValid(decode("block ID", &block_id)?)
+            }
+            RecordedUpdate::BlockInvalid { block_id } => {
+                Update::BlockInvalid(decode("block ID", &block_id)?)
+            }
+            RecordedUpdate::BlockCommit { block_id } => {
+                Update::BlockCommit(decode("block ID", &block_id)?)
+            }
+            RecordedUpdate::Shutdown => Update::Shutdown,
+            RecordedUpdate::Timeout => return Ok(Err(RecvTimeoutError::Timeout)),
+            RecordedUpdate::Disconnected => return Ok(Err(RecvTimeoutError::Disconnected)),
+        }))
+    }
+}
+
+/// A call the node made to its `Service`, with its arguments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum ServiceCall {
+    SendTo {
+        peer_id: String,
+        message_type: String,
+        payload: String,
+    },
+    Broadcast {
+        message_type: String,
+        payload: String,
+    },
+    InitializeBlock {
+        previous_id: Option<String>,
+    },
+    SummarizeBlock,
+    FinalizeBlock {
+        data: String,
+    },
+    CancelBlock,
+    CheckBlocks {
+        priority: Vec<String>,
+    },
+    CommitBlock {
+        block_id: String,
+    },
+    IgnoreBlock {
+        block_id: String,
+    },
+    FailBlock {
+        block_id: String,
+    },
+    GetBlocks {
+        block_ids: Vec<String>,
+    },
+    GetChainHead,
+    GetSettings {
+        block_id: String,
+        keys: Vec<String>,
+    },
+    GetState {
+        block_id: String,
+        addresses: Vec<String>,
+    },
+}
+
+/// What the validator returned for a service call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallResult {
+    Ok,
+    Bytes(String),
+    Block(RecordedBlock),
+    Blocks(Vec<RecordedBlock>),
+    Settings(BTreeMap<String, String>),
+    State(BTreeMap<String, String>),
+    Err(RecordedError),
+}
+
+impl CallResult {
+    fn from_result<T, F: FnOnce(&T) -> CallResult>(result: &Result<T, Error>, map: F) -> Self {
+        match result {
+            Ok(value) => map(value),
+            Err(err) => CallResult::Err(RecordedError::from_error(err)),
+        }
+    }
+
+    /// Turn the recorded result back into what the service returns, using `extract` to get the
+    /// expected type of value
+    fn into_result<T, F>(self, extract: F) -> Result<Result<T, Error>, String>
+    where
+        F: FnOnce(CallResult) -> Result<T, String>,
+    {
+        match self {
+            CallResult::Err(err) => Ok(Err(err.into_error())),
+            result => extract(result).map(Ok),
+        }
+    }
+}
+
+/// An error returned by the validator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedError {
+    EncodingError(String),
+    SendError(String),
+    ReceiveError(String),
+    InvalidState(String),
+    UnknownBlock(String),
+    UnknownPeer(String),
+    NoChainHead,
+    BlockNotReady,
+}
+
+impl RecordedError {
+    fn from_error(err: &Error) -> Self {
+        match err {
+            Error::EncodingError(msg) => RecordedError::EncodingError(msg.clone()),
+            Error::SendError(msg) => RecordedError::SendError(msg.clone()),
+            Error::ReceiveError(msg) => RecordedError::ReceiveError(msg.clone()),
+            Error::InvalidState(msg) => RecordedError::InvalidState(msg.clone()),
+            Error::UnknownBlock(msg) => RecordedError::UnknownBlock(msg.clone()),
+            Error::UnknownPeer(msg) => RecordedError::UnknownPeer(msg.clone()),
+            Error::NoChainHead => RecordedError::NoChainHead,
+            Error::BlockNotReady => RecordedError::BlockNotReady,
+        }
+    }
+
+    fn into_error(self) -> Error {
+        match self {
+            RecordedError::EncodingError(msg) => Error::EncodingError(msg),
+            RecordedError::SendError(msg) => Error::SendError(msg),
+            RecordedError::ReceiveError(msg) => Error::ReceiveError(msg),
+            RecordedError::InvalidState(msg) => Error::InvalidState(msg),
+            RecordedError::UnknownBlock(msg) => Error::UnknownBlock(msg),
+            RecordedError::UnknownPeer(msg) => Error::UnknownPeer(msg),
+            RecordedError::NoChainHead => Error::NoChainHead,
+            RecordedError::BlockNotReady => Error::BlockNotReady,
+        }
+    }
+}
+
+/// Writes a recording of a node's updates and service calls
+pub struct Recorder {
+    /// Unset once writing to the recording has failed
+    writer: Option<LineWriter<File>>,
+    clock: Arc<RecordingClock>,
+    start: Instant,
+    /// A timeout that hasn't been written yet, since it only needs to be recorded if the node
+    /// makes any service calls before the next update
+    pending_timeout: Option<Record>,
+    paused: bool,
+}
+
+impl Recorder {
+    /// Create a recording at the given path, timed with the given clock (which the node must
+    /// use)
+    pub fn create(path: &str, clock: Arc<RecordingClock>) -> Result<Self, String> {
+        let file =
+            File::create(path).map_err(|err| format!("Couldn't create recording: {}", err))?;
+        let start = clock.now();
+
+        Ok(Recorder {
+            writer: Some(LineWriter::new(file)),
+            clock,
+            start,
+            pending_timeout: None,
+            paused: false,
+        })
+    }
+
+    /// Record what the node is started with; must be called before anything else is recorded
+    pub fn record_start(
+        &mut self,
+        config: &PbftConfig,
+        chain_head: &Block,
+        peers: &[PeerInfo],
+        state: &PbftState,
+    ) {
+        let state = match serde_json::to_value(state) {
+            Ok(state) => state,
+            Err(err) => {
+                self.fail(&format!("Couldn't serialize state: {}", err));
+                return;
+            }
+        };
+
+        self.write(&Record::Start {
+            config: config.clone(),
+            startup: RecordedStartup {
+                chain_head: RecordedBlock::from_block(chain_head),
+                peers: peers
+                    .iter()
+                    .map(|peer| hex::encode(&peer.peer_id))
+                    .collect(),
+            },
+            state,
+        });
+    }
+
+    /// Record the next update (or timeout) from the validator, which moves the node's clock
+    /// forward
+    pub fn record_update(&mut self, update: &Result<Update, RecvTimeoutError>) {
+        self.clock.step();
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| {
+                since_epoch.as_secs() * 1000 + u64::from(since_epoch.subsec_millis())
+            })
+            .unwrap_or(0);
+        let elapsed = self.clock.now() - self.start;
+
+        let record = Record::Update {
+            timestamp,
+            elapsed_nanos: elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos()),
+            update: RecordedUpdate::from_update(update),
+        };
+
+        if let Err(RecvTimeoutError::Timeout) = update {
+            self.pending_timeout = Some(record);
+        } else {
+            self.pending_timeout = None;
+            self.write(&record);
+        }
+    }
+
+    /// Record a call the node made to its service
+    pub fn record_call(&mut self, call: ServiceCall, result: CallResult) {
+        if self.paused {
+            return;
+        }
+        if let Some(timeout) = self.pending_timeout.take() {
+            self.write(&timeout);
+        }
+        self.write(&Record::Call { call, result });
+    }
+
+    /// Stop (or resume) recording service calls; calls that don't affect the node's state, such
+    /// as those made to answer admin requests, must not be replayed
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    fn write(&mut self, record: &Record) {
+        let result = match self.writer.as_mut() {
+            Some(writer) => serde_json::to_writer(&mut *writer, record)
+                .map_err(|err| err.to_string())
+                .and_then(|_| writer.write_all(b"\n").map_err(|err| err.to_string())),
+            None => return,
+        };
+        if let Err(err) = result {
+            self.fail(&err);
+        }
+    }
+
+    fn fail(&mut self, err: &str) {
+        error!("Failed to write recording; no longer recording: {}", err);
+        self.writer = None;
+    }
+}
+
+/// Wraps the node's `Service`, recording every call and its result
+pub struct RecordingService {
+    inner: Box<dyn Service>,
+    recorder: Rc<RefCell<Recorder>>,
+}
+
+impl RecordingService {
+    pub fn new(inner: Box<dyn Service>, recorder: Rc<RefCell<Recorder>>) -> Self {
+        RecordingService { inner, recorder }
+    }
+
+    fn record(&self, call: ServiceCall, result: CallResult) {
+        self.recorder.borrow_mut().record_call(call, result);
+    }
+}
+
+impl Service for RecordingService {
+    fn send_to(
+        &mut self,
+        peer: &PeerId,
+        message_type: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), Error> {
+        let call = ServiceCall::SendTo {
+            peer_id: hex::encode(peer),
+            message_type: message_type.into(),
+            payload: hex::encode(&payload),
+        };
+        let result = self.inner.send_to(peer, message_type, payload);
+        self.record(call, CallResult::from_result(&result, |_| CallResult::Ok));
+        result
+    }
+
+    fn broadcast(&mut self, message_type: &str, payload: Vec<u8>) -> Result<(), Error> {
+        let call = ServiceCall::Broadcast {
+            message_type: message_type.into(),
+            payload: hex::encode(&payload),
+        };
+        let result = self.inner.broadcast(message_type, payload);
+        self.record(call, CallResult::from_result(&result, |_| CallResult::Ok));
+        result
+    }
+
+    fn initialize_block(&mut self, previous_id: Option<BlockId>) -> Result<(), Error> {
+        let call = ServiceCall::InitializeBlock {
+            previous_id: previous_id.as_ref().map(hex::encode),
+        };
+        let result = self.inner.initialize_block(previous_id);
+        self.record(call, CallResult::from_result(&result, |_| CallResult::Ok));
+        result
+    }
+
+    fn summarize_block(&mut self) -> Result<Vec<u8>, Error> {
+        let result = self.inner.summarize_block();
+        self.record(
+            ServiceCall::SummarizeBlock,
+            CallResult::from_result(&result, |summary| CallResult::Bytes(hex::encode(summary))),
+        );
+        result
+    }
+
+    fn finalize_block(&mut self, data: Vec<u8>) -> Result<BlockId, Error> {
+        let call = ServiceCall::FinalizeBlock {
+            data: hex::encode(&data),
+        };
+        let result = self.inner.finalize_block(data);
+        self.record(
+            call,
+            CallResult::from_result(&result, |block_id| CallResult::Bytes(hex::encode(block_id))),
+        );
+        result
+    }
+
+    fn cancel_block(&mut self) -> Result<(), Error> {
+        let result = self.inner.cancel_block();
+        self.record(
+            ServiceCall::CancelBlock,
+            CallResult::from_result(&result, |_| CallResult::Ok),
+        );
+        result
+    }
+
+    fn check_blocks(&mut self, priority: Vec<BlockId>) -> Result<(), Error> {
+        let call = ServiceCall::CheckBlocks {
+            priority: priority.iter().map(hex::encode).collect(),
+        };
+        let result = self.inner.check_blocks(priority);
+        self.record(call, CallResult::from_result(&result, |_| CallResult::Ok));
+        result
+    }
+
+    fn commit_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        let call = ServiceCall::CommitBlock {
+            block_id: hex::encode(&block_id),
+        };
+        let result = self.inner.commit_block(block_id);
+        self.record(call, CallResult::from_result(&result, |_| CallResult::Ok));
+        result
+    }
+
+    fn ignore_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        let call = ServiceCall::IgnoreBlock {
+            block_id: hex::encode(&block_id),
+        };
+        let result = self.inner.ignore_block(block_id);
+        self.record(call, CallResult::from_result(&result, |_| CallResult::Ok));
+        result
+    }
+
+    fn fail_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        let call = ServiceCall::FailBlock {
+            block_id: hex::encode(&block_id),
+        };
+        let result = self.inner.fail_block(block_id);
+        self.record(call, CallResult::from_result(&result, |_| CallResult::Ok));
+        result
+    }
+
+    fn get_blocks(&mut self, block_ids: Vec<BlockId>) -> Result<HashMap<BlockId, Block>, Error> {
+        let call = ServiceCall::GetBlocks {
+            block_ids: block_ids.iter().map(hex::encode).collect(),
+        };
+        let result = self.inner.get_blocks(block_ids);
+        self.record(
+            call,
+            CallResult::from_result(&result, |blocks| {
+                let mut blocks: Vec<RecordedBlock> =
+                    blocks.values().map(RecordedBlock::from_block).collect();
+                blocks.sort_by(|a, b| a.block_id.cmp(&b.block_id));
+                CallResult::Blocks(blocks)
+            }),
+        );
+        result
+    }
+
+    fn get_chain_head(&mut self) -> Result<Block, Error> {
+        let result = self.inner.get_chain_head();
+        self.record(
+            ServiceCall::GetChainHead,
+            CallResult::from_result(&result, |block| {
+                CallResult::Block(RecordedBlock::from_block(block))
+            }),
+        );
+        result
+    }
+
+    fn get_settings(
+        &mut self,
+        block_id: BlockId,
+        keys: Vec<String>,
+    ) -> Result<HashMap<String, String>, Error> {
+        let call = ServiceCall::GetSettings {
+            block_id: hex::encode(&block_id),
+            keys: keys.clone(),
+        };
+        let result = self.inner.get_settings(block_id, keys);
+        self.record(
+            call,
+            CallResult::from_result(&result, |settings| {
+                CallResult::Settings(settings.clone().into_iter().collect())
+            }),
+        );
+        result
+    }
+
+    fn get_state(
+        &mut self,
+        block_id: BlockId,
+        addresses: Vec<String>,
+    ) -> Result<HashMap<String, Vec<u8>>, Error> {
+        let call = ServiceCall::GetState {
+            block_id: hex::encode(&block_id),
+            addresses: addresses.clone(),
+        };
+        let result = self.inner.get_state(block_id, addresses);
+        self.record(
+            call,
+            CallResult::from_result(&result, |state| {
+                CallResult::State(
+                    state
+                        .iter()
+                        .map(|(address, value)| (address.clone(), hex::encode(value)))
+                        .collect(),
+                )
+            }),
+        );
+        result
+    }
+}
+
+/// The outcome of a replay that didn't diverge from its recording
+pub struct Replay {
+    /// How many updates (including timeouts that led to service calls) were replayed
+    pub updates: usize,
+    /// How many service calls were answered from the recording
+    pub calls: usize,
+    /// The node's state after the last update
+    pub state: PbftState,
+}
+
+/// Replay the recording at the given path
+pub fn replay(path: &str) -> Result<Replay, String> {
+    let file = File::open(path).map_err(|err| format!("Couldn't open recording: {}", err))?;
+    let mut records = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|err| format!("Couldn't read recording: {}", err))?;
+        let record: Record = serde_json::from_str(&line)
+            .map_err(|err| format!("Invalid record on line {}: {}", index + 1, err))?;
+        records.push(record);
+    }
+    let mut records = records.into_iter();
+
+    let (config, startup, state) = match records.next() {
+        Some(Record::Start {
+            config,
+            startup,
+            state,
+        }) => (config, startup, state),
+        _ => return Err("Recording doesn't start with the node's startup".into()),
+    };
+
+    // Split the rest of the recording into the calls made during startup, and each update with
+    // the calls made while handling it
+    let mut setup_calls = VecDeque::new();
+    let mut updates: Vec<(u64, RecordedUpdate, VecDeque<(ServiceCall, CallResult)>)> = vec![];
+    for record in records {
+        match record {
+            Record::Update {
+                elapsed_nanos,
+                update,
+                ..
+            } => updates.push((elapsed_nanos, update, VecDeque::new())),
+            Record::Call { call, result } => match updates.last_mut() {
+                Some((_, _, calls)) => calls.push_back((call, result)),
+                None => setup_calls.push_back((call, result)),
+            },
+            Record::Start { .. } => return Err("Recording has more than one startup".into()),
+        }
+    }
+
+    let clock = ManualClock::new();
+    let mut state: PbftState = serde_json::from_value(state)
+        .map_err(|err| format!("Couldn't deserialize recorded state: {}", err))?;
+    state.set_clock(Arc::new(clock.clone()));
+
+    let chain_head = startup.chain_head.into_block()?;
+    let peers = startup
+        .peers
+        .iter()
+        .map(|peer_id| {
+            Ok(PeerInfo {
+                peer_id: decode("peer ID", peer_id)?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    // Set up the node the same way the engine does
+    let calls = Rc::new(RefCell::new(ReplayedCalls::default()));
+    calls.borrow_mut().load(setup_calls);
+    let mut ticker = Ticker::new(config.block_publishing_delay, Arc::new(clock.clone()));
+    let mut node = PbftNode::new(
+        &config,
+        chain_head,
+        peers,
+        Box::new(ReplayService {
+            calls: calls.clone(),
+        }),
+        &mut state,
+    );
+    node.start_idle_timeout(&mut state);
+    calls
+        .borrow_mut()
+        .finish()
+        .map_err(|err| format!("Replay diverged during startup: {}", err))?;
+
+    let mut replay = Replay {
+        updates: 0,
+        calls: 0,
+        state,
+    };
+
+    for (index, (elapsed_nanos, update, update_calls)) in updates.into_iter().enumerate() {
+        clock.advance_to(Duration::from_nanos(elapsed_nanos));
+        replay.calls += update_calls.len();
+        calls.borrow_mut().load(update_calls);
+
+        let description = format!("{:?}", update);
+        let keep_going = process_update(
+            &mut node,
+            update.into_update()?,
+            &mut replay.state,
+            &mut ticker,
+        );
+        replay.updates += 1;
+
+        calls.borrow_mut().finish().map_err(|err| {
+            format!(
+                "Replay diverged at update {} ({}): {}",
+                index + 1,
+                description,
+                err
+            )
+        })?;
+
+        if !keep_going {
+            break;
+        }
+    }
+
+    Ok(replay)
+}
+
+/// The recorded calls that the node is expected to make while handling the current update
+#[derive(Default)]
+struct ReplayedCalls {
+    expected: VecDeque<(ServiceCall, CallResult)>,
+    /// How the node first diverged from the recording, if it did
+    divergence: Option<String>,
+}
+
+impl ReplayedCalls {
+    fn load(&mut self, calls: VecDeque<(ServiceCall, CallResult)>) {
+        self.expected = calls;
+    }
+
+    /// Check that the node made exactly the recorded calls
+    fn finish(&mut self) -> Result<(), String> {
+        if let Some(divergence) = self.divergence.take() {
+            return Err(divergence);
+        }
+        match self.expected.front() {
+            Some((call, _)) => Err(format!("the recorded node went on to call {:?}", call)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Answers the node's service calls with the results in the recording
+struct ReplayService {
+    calls: Rc<RefCell<ReplayedCalls>>,
+}
+
+impl ReplayService {
+    fn replay<T, F>(&mut self, call: ServiceCall, extract: F) -> Result<T, Error>
+    where
+        F: FnOnce(CallResult) -> Result<T, String>,
+    {
+        let mut calls = self.calls.borrow_mut();
+        if let Some(divergence) = &calls.divergence {
+            return Err(Error::InvalidState(format!(
+                "Replay already diverged: {}",
+                divergence
+            )));
+        }
+
+        let outcome = match calls.expected.pop_front() {
+            Some((expected, result)) => {
+                if expected == call {
+                    result.into_result(extract)
+                } else {
+                    Err(format!(
+                        "the node called {:?} instead of {:?}",
+                        call, expected
+                    ))
+                }
+            }
+            None => Err(format!("the node made an unrecorded call to {:?}", call)),
+        };
+
+        outcome.unwrap_or_else(|divergence| {
+            let err = Error::InvalidState(format!("Replay diverged: {}", divergence));
+            calls.divergence = Some(divergence);
+            Err(err)
+        })
+    }
+}
+
+fn expect_ok(result: CallResult) -> Result<(), String> {
+    match result {
+        CallResult::Ok => Ok(()),
+        result => Err(format!("recorded result {:?} isn't empty", result)),
+    }
+}
+
+fn expect_bytes(result: CallResult) -> Result<Vec<u8>, String> {
+    match result {
+        CallResult::Bytes(bytes) => decode("result", &bytes),
+        result => Err(format!("recorded result {:?} isn't bytes", result)),
+    }
+}
+
+impl Service for ReplayService {
+    fn send_to(
+        &mut self,
+        peer: &PeerId,
+        message_type: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), Error> {
+        let call = ServiceCall::SendTo {
+            peer_id: hex::encode(peer),
+            message_type: message_type.into(),
+            payload: hex::encode(&payload),
+        };
+        self.replay(call, expect_ok)
+    }
+
+    fn broadcast(&mut self, message_type: &str, payload: Vec<u8>) -> Result<(), Error> {
+        let call = ServiceCall::Broadcast {
+            message_type: message_type.into(),
+            payload: hex::encode(&payload),
+        };
+        self.replay(call, expect_ok)
+    }
+
+    fn initialize_block(&mut self, previous_id: Option<BlockId>) -> Result<(), Error> {
+        let call = ServiceCall::InitializeBlock {
+            previous_id: previous_id.as_ref().map(hex::encode),
+        };
+        self.replay(call, expect_ok)
+    }
+
+    fn summarize_block(&mut self) -> Result<Vec<u8>, Error> {
+        self.replay(ServiceCall::SummarizeBlock, expect_bytes)
+    }
+
+    fn finalize_block(&mut self, data: Vec<u8>) -> Result<BlockId, Error> {
+        let call = ServiceCall::FinalizeBlock {
+            data: hex::encode(&data),
+        };
+        self.replay(call, expect_bytes)
+    }
+
+    fn cancel_block(&mut self) -> Result<(), Error> {
+        self.replay(ServiceCall::CancelBlock, expect_ok)
+    }
+
+    fn check_blocks(&mut self, priority: Vec<BlockId>) -> Result<(), Error> {
+        let call = ServiceCall::CheckBlocks {
+            priority: priority.iter().map(hex::encode).collect(),
+        };
+        self.replay(call, expect_ok)
+    }
+
+    fn commit_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        let call = ServiceCall::CommitBlock {
+            block_id: hex::encode(&block_id),
+        };
+        self.replay(call, expect_ok)
+    }
+
+    fn ignore_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        let call = ServiceCall::IgnoreBlock {
+            block_id: hex::encode(&block_id),
+        };
+        self.replay(call, expect_ok)
+    }
+
+    fn fail_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        let call = ServiceCall::FailBlock {
+            block_id: hex::encode(&block_id),
+        };
+        self.replay(call, expect_ok)
+    }
+
+    fn get_blocks(&mut self, block_ids: Vec<BlockId>) -> Result<HashMap<BlockId, Block>, Error> {
+        let call = ServiceCall::GetBlocks {
+            block_ids: block_ids.iter().map(hex::encode).collect(),
+        };
+        self.replay(call, |result| match result {
+            CallResult::Blocks(blocks) => blocks
+                .into_iter()
+                .map(|block| {
+                    let block = block.into_block()?;
+                    Ok((block.block_id.clone(), block))
+                })
+                .collect(),
+            result => Err(format!(
+                "recorded result {:?} isn't a list of blocks",
+                result
+            )),
+        })
+    }
+
+    fn get_chain_head(&mut self) -> Result<Block, Error> {
+        self.replay(ServiceCall::GetChainHead, |result| match result {
+            CallResult::Block(block) => block.into_block(),
+            result => Err(format!("recorded result {:?} isn't a block", result)),
+        })
+    }
+
+    fn get_settings(
+        &mut self,
+        block_id: BlockId,
+        keys: Vec<String>,
+    ) -> Result<HashMap<String, String>, Error> {
+        let call = ServiceCall::GetSettings {
+            block_id: hex::encode(&block_id),
+            keys,
+        };
+        self.replay(call, |result| match result {
+            CallResult::Settings(settings) => Ok(settings.into_iter().collect()),
+            result => Err(format!("recorded result {:?} isn't settings", result)),
+        })
+    }
+
+    fn get_state(
+        &mut self,
+        block_id: BlockId,
+        addresses: Vec<String>,
+    ) -> Result<HashMap<String, Vec<u8>>, Error> {
+        let call = ServiceCall::GetState {
+            block_id: hex::encode(&block_id),
+            addresses,
+        };
+        self.replay(call, |result| match result {
+            CallResult::State(state) => state
+                .into_iter()
+                .map(|(address, value)| Ok((address, decode("state value", &value)?)))
+                .collect(),
+            result => Err(format!("recorded result {:?} isn't state", result)),
+        })
+    }
+}
+
+fn decode(name: &str, value: &str) -> Result<Vec<u8>, String> {
+    hex::decode(value).map_err(|err| format!("Invalid {} in recording: {}", name, err))
+}
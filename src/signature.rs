@@ -0,0 +1,427 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Signature schemes that validators may sign votes with
+//!
+//! A member's ID is its public key, so checking the signature of a vote only requires the
+//! signer's ID, the signed bytes, and the signature. Which scheme a network uses is set by the
+//! `signature_scheme` of the `PbftConfig`; it must be the same on every node.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use hex;
+use openssl::pkey::{Id, PKey};
+use openssl::sign::Verifier;
+use sawtooth_sdk::signing::{create_context, secp256k1::Secp256k1PublicKey};
+
+use crate::error::PbftError;
+use crate::hash::hash_sha512;
+
+/// How many valid signatures a `CachingVerifier` remembers unless configured otherwise
+pub const DEFAULT_VERIFICATION_CACHE_SIZE: usize = 1024;
+
+/// Checks signatures made with a particular signature scheme
+pub trait VoteVerifier: Send + Sync {
+    /// Check that the signature of the message was made with the private key of the given public
+    /// key
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), PbftError>;
+}
+
+/// Verifies secp256k1 signatures, which Sawtooth validators sign with by default
+pub struct Secp256k1Verifier;
+
+impl VoteVerifier for Secp256k1Verifier {
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), PbftError> {
+        let key = Secp256k1PublicKey::from_hex(&hex::encode(public_key)).map_err(|err| {
+            PbftError::SigningError(format!(
+                "Couldn't parse public key from signer ID ({:?}) due to error: {:?}",
+                public_key, err
+            ))
+        })?;
+        let context = create_context("secp256k1").map_err(|err| {
+            PbftError::SigningError(format!("Couldn't create context due to error: {}", err))
+        })?;
+
+        match context.verify(&hex::encode(signature), message, &key) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(PbftError::SigningError(format!(
+                "Signature {} of {} is not valid for {}",
+                hex::encode(signature),
+                hex::encode(message),
+                hex::encode(public_key)
+            ))),
+            Err(err) => Err(PbftError::SigningError(format!(
+                "Error while verifying signature: {:?}",
+                err
+            ))),
+        }
+    }
+}
+
+/// Verifies Ed25519 signatures; public keys are the raw 32-byte keys
+pub struct Ed25519Verifier;
+
+impl VoteVerifier for Ed25519Verifier {
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), PbftError> {
+        let key = PKey::public_key_from_raw_bytes(public_key, Id::ED25519).map_err(|err| {
+            PbftError::SigningError(format!(
+                "Couldn't parse Ed25519 public key from signer ID ({:?}) due to error: {}",
+                public_key, err
+            ))
+        })?;
+
+        let valid = Verifier::new_without_digest(&key)
+            .and_then(|mut verifier| verifier.verify_oneshot(signature, message))
+            .map_err(|err| {
+                PbftError::SigningError(format!("Error while verifying signature: {}", err))
+            })?;
+
+        if valid {
+            Ok(())
+        } else {
+            Err(PbftError::SigningError(format!(
+                "Signature {} of {} is not valid for {}",
+                hex::encode(signature),
+                hex::encode(message),
+                hex::encode(public_key)
+            )))
+        }
+    }
+}
+
+/// How a `CachingVerifier`'s cache has performed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct VerificationCacheStats {
+    /// Signatures that were found in the cache, so they didn't have to be verified again
+    pub hits: u64,
+    /// Signatures that weren't in the cache and had to be verified
+    pub misses: u64,
+    /// Number of signatures currently in the cache
+    pub entries: usize,
+    /// Maximum number of signatures the cache holds
+    pub capacity: usize,
+}
+
+/// Wraps another verifier with a cache of the signatures it has found to be valid, so votes that
+/// are checked again (because they show up in a NewView, in a seal, and in a seal response) are
+/// only verified once
+///
+/// Signatures are identified by the SHA-512 hash of the public key, the signed bytes (a vote's
+/// header), and the signature. Only valid signatures are cached; when the cache is full, the
+/// least recently used signature is evicted.
+pub struct CachingVerifier {
+    inner: Box<dyn VoteVerifier>,
+    cache: Mutex<LruSet>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachingVerifier {
+    /// Cache up to `capacity` valid signatures checked by the inner verifier; if `capacity` is 0,
+    /// nothing is cached and every signature is checked by the inner verifier
+    pub fn new(inner: Box<dyn VoteVerifier>, capacity: usize) -> Self {
+        CachingVerifier {
+            inner,
+            cache: Mutex::new(LruSet::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the cache's hit and miss counts and how full it is
+    pub fn stats(&self) -> VerificationCacheStats {
+        let cache = self.cache.lock().expect("Verification cache lock poisoned");
+        VerificationCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: cache.len(),
+            capacity: cache.capacity,
+        }
+    }
+
+    fn key(public_key: &[u8], message: &[u8], signature: &[u8]) -> Vec<u8> {
+        // Length prefixes keep different splits of the same bytes from sharing a key
+        let mut bytes = Vec::with_capacity(24 + public_key.len() + message.len() + signature.len());
+        for part in &[public_key, message, signature] {
+            bytes.extend_from_slice(&(part.len() as u64).to_be_bytes());
+            bytes.extend_from_slice(part);
+        }
+        hash_sha512(&bytes)
+    }
+}
+
+impl VoteVerifier for CachingVerifier {
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), PbftError> {
+        let key = Self::key(public_key, message, signature);
+
+        if self
+            .cache
+            .lock()
+            .expect("Verification cache lock poisoned")
+            .touch(&key)
+        {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        self.inner.verify(public_key, message, signature)?;
+        self.cache
+            .lock()
+            .expect("Verification cache lock poisoned")
+            .insert(key);
+        Ok(())
+    }
+}
+
+/// A set of keys that evicts the least recently used key when it is full
+struct LruSet {
+    capacity: usize,
+    /// Incremented each time a key is used
+    clock: u64,
+    /// When each key was last used
+    last_used: HashMap<Vec<u8>, u64>,
+    /// Keys by when they were last used
+    by_use: BTreeMap<u64, Vec<u8>>,
+}
+
+impl LruSet {
+    fn new(capacity: usize) -> Self {
+        LruSet {
+            capacity,
+            clock: 0,
+            last_used: HashMap::new(),
+            by_use: BTreeMap::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.last_used.len()
+    }
+
+    /// Mark the key as used; returns `false` if the key isn't in the set
+    fn touch(&mut self, key: &[u8]) -> bool {
+        self.clock += 1;
+        let clock = self.clock;
+        match self.last_used.get_mut(key) {
+            Some(last_used) => {
+                self.by_use.remove(last_used);
+                *last_used = clock;
+                self.by_use.insert(clock, key.to_vec());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, key: Vec<u8>) {
+        if self.capacity == 0 || self.touch(&key) {
+            return;
+        }
+        if self.last_used.len() >= self.capacity {
+            if let Some((&oldest, _)) = self.by_use.iter().next() {
+                if let Some(evicted) = self.by_use.remove(&oldest) {
+                    self.last_used.remove(&evicted);
+                }
+            }
+        }
+        self.last_used.insert(key.clone(), self.clock);
+        self.by_use.insert(self.clock, key);
+    }
+}
+
+/// The signature schemes that votes can be checked with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureScheme {
+    Secp256k1,
+    Ed25519,
+}
+
+impl SignatureScheme {
+    /// Get a verifier for this scheme
+    pub fn verifier(self) -> Box<dyn VoteVerifier> {
+        match self {
+            SignatureScheme::Secp256k1 => Box::new(Secp256k1Verifier),
+            SignatureScheme::Ed25519 => Box::new(Ed25519Verifier),
+        }
+    }
+}
+
+impl Default for SignatureScheme {
+    fn default() -> Self {
+        SignatureScheme::Secp256k1
+    }
+}
+
+impl FromStr for SignatureScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "secp256k1" => Ok(SignatureScheme::Secp256k1),
+            "ed25519" => Ok(SignatureScheme::Ed25519),
+            _ => Err(format!(
+                "Unknown signature scheme '{}'; expected 'secp256k1' or 'ed25519'",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SignatureScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignatureScheme::Secp256k1 => write!(f, "secp256k1"),
+            SignatureScheme::Ed25519 => write!(f, "ed25519"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::sign::Signer;
+    use std::sync::Arc;
+
+    /// Each verifier must accept a signature made with its scheme by the key it is given, and
+    /// reject signatures of other messages, signatures by other keys, and signatures made with
+    /// the other scheme.
+    #[test]
+    fn test_verifiers() {
+        let message = b"message".to_vec();
+
+        // secp256k1
+        let context = create_context("secp256k1").expect("Failed to create context");
+        let secp_private = context
+            .new_random_private_key()
+            .expect("Failed to generate private key");
+        let secp_public = context
+            .get_public_key(&*secp_private)
+            .expect("Failed to get public key")
+            .as_slice()
+            .to_vec();
+        let secp_signature = hex::decode(
+            context
+                .sign(&message, &*secp_private)
+                .expect("Failed to sign"),
+        )
+        .expect("Failed to decode signature");
+
+        // Ed25519
+        let ed_key = PKey::generate_ed25519().expect("Failed to generate key");
+        let ed_public = ed_key.raw_public_key().expect("Failed to get public key");
+        let ed_signature = Signer::new_without_digest(&ed_key)
+            .and_then(|mut signer| signer.sign_oneshot_to_vec(&message))
+            .expect("Failed to sign");
+        let other_ed_public = PKey::generate_ed25519()
+            .and_then(|key| key.raw_public_key())
+            .expect("Failed to generate key");
+
+        let secp = SignatureScheme::Secp256k1.verifier();
+        assert!(secp.verify(&secp_public, &message, &secp_signature).is_ok());
+        assert!(secp
+            .verify(&secp_public, b"other", &secp_signature)
+            .is_err());
+        assert!(secp.verify(&ed_public, &message, &ed_signature).is_err());
+
+        let ed = SignatureScheme::Ed25519.verifier();
+        assert!(ed.verify(&ed_public, &message, &ed_signature).is_ok());
+        assert!(ed.verify(&ed_public, b"other", &ed_signature).is_err());
+        assert!(ed
+            .verify(&other_ed_public, &message, &ed_signature)
+            .is_err());
+        assert!(ed.verify(&secp_public, &message, &secp_signature).is_err());
+    }
+
+    /// Counts the signatures it is asked to verify; signatures equal to `b"bad"` are invalid
+    struct CountingVerifier(Arc<AtomicU64>);
+
+    impl VoteVerifier for CountingVerifier {
+        fn verify(&self, _: &[u8], _: &[u8], signature: &[u8]) -> Result<(), PbftError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            if signature == b"bad" {
+                Err(PbftError::SigningError("Bad signature".into()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// A valid signature must only be checked by the inner verifier the first time it is seen;
+    /// later checks must be cache hits. Invalid signatures must never be cached, and a full cache
+    /// must evict the signature that was used least recently.
+    #[test]
+    fn test_caching_verifier() {
+        let calls = Arc::new(AtomicU64::new(0));
+        let verifier = CachingVerifier::new(Box::new(CountingVerifier(calls.clone())), 2);
+
+        // A repeated signature is a hit
+        assert!(verifier.verify(b"key", b"header a", b"sig a").is_ok());
+        assert!(verifier.verify(b"key", b"header a", b"sig a").is_ok());
+        assert_eq!(1, calls.load(Ordering::Relaxed));
+
+        // Invalid signatures are always checked
+        assert!(verifier.verify(b"key", b"header a", b"bad").is_err());
+        assert!(verifier.verify(b"key", b"header a", b"bad").is_err());
+        assert_eq!(3, calls.load(Ordering::Relaxed));
+
+        // Different keys and headers are different entries
+        assert!(verifier.verify(b"other key", b"header a", b"sig a").is_ok());
+        assert_eq!(4, calls.load(Ordering::Relaxed));
+        assert_eq!(
+            VerificationCacheStats {
+                hits: 1,
+                misses: 4,
+                entries: 2,
+                capacity: 2,
+            },
+            verifier.stats()
+        );
+
+        // Use the first entry, then add a third; the second entry is evicted
+        assert!(verifier.verify(b"key", b"header a", b"sig a").is_ok());
+        assert!(verifier.verify(b"key", b"header b", b"sig b").is_ok());
+        assert_eq!(5, calls.load(Ordering::Relaxed));
+        assert!(verifier.verify(b"key", b"header a", b"sig a").is_ok());
+        assert_eq!(5, calls.load(Ordering::Relaxed));
+        assert!(verifier.verify(b"other key", b"header a", b"sig a").is_ok());
+        assert_eq!(6, calls.load(Ordering::Relaxed));
+
+        // With a capacity of 0, nothing is cached
+        let calls = Arc::new(AtomicU64::new(0));
+        let verifier = CachingVerifier::new(Box::new(CountingVerifier(calls.clone())), 0);
+        assert!(verifier.verify(b"key", b"header a", b"sig a").is_ok());
+        assert!(verifier.verify(b"key", b"header a", b"sig a").is_ok());
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+        assert_eq!(0, verifier.stats().entries);
+    }
+
+    /// Signature schemes are configured by name.
+    #[test]
+    fn test_signature_scheme_names() {
+        for scheme in &[SignatureScheme::Secp256k1, SignatureScheme::Ed25519] {
+            assert_eq!(Ok(*scheme), scheme.to_string().parse());
+        }
+        assert_eq!(SignatureScheme::Secp256k1, SignatureScheme::default());
+        assert!("rsa".parse::<SignatureScheme>().is_err());
+    }
+}
@@ -0,0 +1,543 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Scripted Byzantine behavior for simulated nodes
+//!
+//! A `Byzantine` adversary wraps an otherwise honest `PbftNode` and carries out a list of
+//! `Misbehavior`s by rewriting what the node sends and by sending messages the node never would.
+//! It plugs into a `Simulation` with `Simulation::with_adversaries`, so tests can check how the
+//! honest nodes of the network react.
+//!
+//! This module is available to unit tests and, for other crates, with the `byzantine` feature.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
+
+use hex;
+use protobuf::{Message, RepeatedField};
+use sawtooth_sdk::consensus::engine::{Block, BlockId, Error, PeerId, Update};
+use sawtooth_sdk::consensus::service::Service;
+
+use crate::hash::hash_sha512;
+use crate::message_type::PbftMessageType;
+use crate::node::PbftNode;
+use crate::protos::pbft_message::{
+    PbftMessage, PbftMessageInfo, PbftNewView, PbftSeal, PbftSignedVote,
+};
+use crate::state::{PbftMode, PbftState};
+
+use super::{Adversary, Signer};
+
+/// Ways a Byzantine node can misbehave
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Misbehavior {
+    /// When publishing a block, also publish a conflicting block at the same height, and send
+    /// the PrePrepare for the conflicting block to half of the other nodes
+    Equivocate,
+    /// Follow every Commit with a Commit for a block that doesn't exist
+    CommitUnknownBlocks,
+    /// Replace the votes in the seals of published blocks with votes that claim to be from the
+    /// original voters but are signed by this node
+    ForgeSealVotes,
+    /// Vote for a view change as soon as possible, even though nothing is wrong
+    DemandViewChange,
+    /// Upon receiving a ViewChange for a view this node would be primary of, announce the view
+    /// with a NewView that only contains the votes received so far
+    NewViewTooFewVotes,
+    /// Like `NewViewTooFewVotes`, but repeat the received votes until there appear to be enough
+    NewViewDuplicateVotes,
+}
+
+/// State shared by the adversary and the service it wraps around the node's service
+struct Script {
+    misbehaviors: Vec<Misbehavior>,
+    inner: Option<Box<dyn Service>>,
+    signer: Option<Signer>,
+    peer_id: PeerId,
+    members: Vec<PeerId>,
+    /// Conflicting blocks published for each of this node's blocks
+    twins: HashMap<BlockId, BlockId>,
+    /// Valid ViewChange votes received from other nodes, by view
+    view_changes: BTreeMap<u64, Vec<PbftSignedVote>>,
+    new_views_sent: HashSet<u64>,
+    demanded_view_change: bool,
+    /// Number of times a misbehavior was carried out
+    performed: u64,
+}
+
+impl Script {
+    fn has(&self, misbehavior: Misbehavior) -> bool {
+        self.misbehaviors.contains(&misbehavior)
+    }
+
+    fn inner(&mut self) -> &mut dyn Service {
+        &mut **self
+            .inner
+            .as_mut()
+            .expect("Byzantine adversary used before its service was wrapped")
+    }
+
+    fn other_members(&self) -> Vec<PeerId> {
+        self.members
+            .iter()
+            .filter(|member| **member != self.peer_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Send the PrePrepare for the block to half of the other nodes, and a PrePrepare for its
+    /// twin to the rest
+    fn equivocate(&mut self, payload: Vec<u8>) -> Result<(), Error> {
+        let message = parse_message(&payload)?;
+        let twin_id = match self.twins.get(message.get_block_id()) {
+            Some(twin_id) => twin_id.clone(),
+            None => return self.inner().broadcast("PrePrepare", payload),
+        };
+
+        let mut twin_message = message;
+        twin_message.set_block_id(twin_id);
+        let twin_payload = write_message(&twin_message)?;
+
+        let peers = self.other_members();
+        let (originals, twins) = peers.split_at(peers.len() / 2);
+        for peer in originals {
+            self.inner().send_to(peer, "PrePrepare", payload.clone())?;
+        }
+        for peer in twins {
+            self.inner()
+                .send_to(peer, "PrePrepare", twin_payload.clone())?;
+        }
+
+        self.performed += 1;
+        Ok(())
+    }
+
+    /// Broadcast a Commit like the given one, but for a block that doesn't exist
+    fn commit_unknown_block(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let mut message = parse_message(payload)?;
+        let unknown_id = hash_sha512(message.get_block_id());
+        message.set_block_id(unknown_id);
+        let payload = write_message(&message)?;
+        self.inner().broadcast("Commit", payload)?;
+        self.performed += 1;
+        Ok(())
+    }
+
+    /// Re-sign every vote in the serialized seal with this node's key
+    fn forge_seal(&mut self, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let mut seal: PbftSeal = protobuf::parse_from_bytes(&data)
+            .map_err(|err| Error::EncodingError(err.to_string()))?;
+        let signer = self
+            .signer
+            .as_ref()
+            .expect("Byzantine adversary used before its service was wrapped");
+
+        for vote in seal.mut_commit_votes().iter_mut() {
+            let signature = signer(vote.get_header_bytes())?;
+            vote.set_header_signature(signature);
+        }
+
+        self.performed += 1;
+        seal.write_to_bytes()
+            .map_err(|err| Error::EncodingError(err.to_string()))
+    }
+
+    /// Publish a block at the same height as the given one, on top of the same parent
+    fn publish_twin(&mut self, block_id: &[u8], data: Vec<u8>) -> Result<(), Error> {
+        let block: Block = self
+            .inner()
+            .get_blocks(vec![block_id.to_vec()])?
+            .remove(block_id)
+            .ok_or_else(|| Error::UnknownBlock(hex::encode(block_id)))?;
+
+        self.inner().initialize_block(Some(block.previous_id))?;
+        self.inner().summarize_block()?;
+        let twin_id = self.inner().finalize_block(data)?;
+        self.twins.insert(block_id.to_vec(), twin_id);
+        Ok(())
+    }
+
+    /// Announce any view this node is the primary of and has received ViewChanges for, with an
+    /// invalid NewView
+    fn announce_views(&mut self, state: &PbftState) -> Result<(), Error> {
+        let duplicate = self.has(Misbehavior::NewViewDuplicateVotes);
+        let views: Vec<u64> = self
+            .view_changes
+            .keys()
+            .cloned()
+            .filter(|view| {
+                *view > state.view
+                    && state.is_primary_at_view(*view)
+                    && !self.new_views_sent.contains(view)
+            })
+            .collect();
+
+        for view in views {
+            let received = self.view_changes[&view].clone();
+            let votes: Vec<PbftSignedVote> = if duplicate {
+                let required = (2 * state.f as usize).max(received.len());
+                received.iter().cycle().take(required).cloned().collect()
+            } else {
+                received
+            };
+
+            let mut new_view = PbftNewView::new();
+            new_view.set_info(PbftMessageInfo::new_from(
+                PbftMessageType::NewView,
+                view,
+                state.seq_num - 1,
+                self.peer_id.clone(),
+            ));
+            new_view.set_view_changes(RepeatedField::from(votes));
+            let payload = new_view
+                .write_to_bytes()
+                .map_err(|err| Error::EncodingError(err.to_string()))?;
+
+            self.inner().broadcast("NewView", payload)?;
+            self.new_views_sent.insert(view);
+            self.performed += 1;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_message(payload: &[u8]) -> Result<PbftMessage, Error> {
+    protobuf::parse_from_bytes(payload).map_err(|err| Error::EncodingError(err.to_string()))
+}
+
+fn write_message(message: &PbftMessage) -> Result<Vec<u8>, Error> {
+    message
+        .write_to_bytes()
+        .map_err(|err| Error::EncodingError(err.to_string()))
+}
+
+/// An adversary that makes a node carry out a list of misbehaviors
+///
+/// Clones share their state, so a test can keep a clone to see what the adversary has done.
+#[derive(Clone)]
+pub struct Byzantine {
+    script: Rc<RefCell<Script>>,
+}
+
+impl Byzantine {
+    pub fn new(misbehaviors: Vec<Misbehavior>) -> Self {
+        Byzantine {
+            script: Rc::new(RefCell::new(Script {
+                misbehaviors,
+                inner: None,
+                signer: None,
+                peer_id: vec![],
+                members: vec![],
+                twins: HashMap::new(),
+                view_changes: BTreeMap::new(),
+                new_views_sent: HashSet::new(),
+                demanded_view_change: false,
+                performed: 0,
+            })),
+        }
+    }
+
+    /// Number of times a misbehavior has been carried out
+    pub fn performed(&self) -> u64 {
+        self.script.borrow().performed
+    }
+}
+
+impl Adversary for Byzantine {
+    fn wrap_service(
+        &mut self,
+        service: Box<dyn Service>,
+        peer_id: &[u8],
+        members: &[PeerId],
+        signer: Signer,
+    ) -> Box<dyn Service> {
+        let mut script = self.script.borrow_mut();
+        script.inner = Some(service);
+        script.signer = Some(signer);
+        script.peer_id = peer_id.to_vec();
+        script.members = members.to_vec();
+
+        Box::new(ByzantineService {
+            script: self.script.clone(),
+        })
+    }
+
+    fn observe(&mut self, update: &Update) {
+        let mut script = self.script.borrow_mut();
+        let collect_votes = script.has(Misbehavior::NewViewTooFewVotes)
+            || script.has(Misbehavior::NewViewDuplicateVotes);
+
+        if let Update::PeerMessage(message, _) = update {
+            if !collect_votes || message.header.message_type != "ViewChange" {
+                return;
+            }
+            if let Ok(view_change) = parse_message(&message.content) {
+                let mut vote = PbftSignedVote::new();
+                vote.set_header_bytes(message.header_bytes.clone());
+                vote.set_header_signature(message.header_signature.clone());
+                vote.set_message_bytes(message.content.clone());
+                script
+                    .view_changes
+                    .entry(view_change.get_info().get_view())
+                    .or_default()
+                    .push(vote);
+            }
+        }
+    }
+
+    fn act(&mut self, node: &mut PbftNode, state: &mut PbftState) {
+        let demand = {
+            let script = self.script.borrow();
+            script.has(Misbehavior::DemandViewChange)
+                && !script.demanded_view_change
+                && state.mode == PbftMode::Normal
+        };
+        if demand {
+            let view = state.view + 1;
+            match node.start_view_change(state, view) {
+                Ok(()) => {
+                    let mut script = self.script.borrow_mut();
+                    script.demanded_view_change = true;
+                    script.performed += 1;
+                }
+                Err(err) => warn!("Byzantine node couldn't demand view change: {}", err),
+            }
+        }
+
+        let mut script = self.script.borrow_mut();
+        if script.has(Misbehavior::NewViewTooFewVotes)
+            || script.has(Misbehavior::NewViewDuplicateVotes)
+        {
+            script
+                .announce_views(state)
+                .unwrap_or_else(|err| warn!("Byzantine node couldn't announce view: {}", err));
+        }
+    }
+}
+
+/// The service given to a Byzantine node; passes everything through to the node's real service
+/// except for what the misbehaviors rewrite
+struct ByzantineService {
+    script: Rc<RefCell<Script>>,
+}
+
+impl Service for ByzantineService {
+    fn send_to(
+        &mut self,
+        peer: &PeerId,
+        message_type: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.script
+            .borrow_mut()
+            .inner()
+            .send_to(peer, message_type, payload)
+    }
+
+    fn broadcast(&mut self, message_type: &str, payload: Vec<u8>) -> Result<(), Error> {
+        let mut script = self.script.borrow_mut();
+        match message_type {
+            "PrePrepare" if script.has(Misbehavior::Equivocate) => script.equivocate(payload),
+            "Commit" if script.has(Misbehavior::CommitUnknownBlocks) => {
+                script.inner().broadcast(message_type, payload.clone())?;
+                script.commit_unknown_block(&payload)
+            }
+            _ => script.inner().broadcast(message_type, payload),
+        }
+    }
+
+    fn initialize_block(&mut self, previous_id: Option<BlockId>) -> Result<(), Error> {
+        self.script
+            .borrow_mut()
+            .inner()
+            .initialize_block(previous_id)
+    }
+
+    fn summarize_block(&mut self) -> Result<Vec<u8>, Error> {
+        self.script.borrow_mut().inner().summarize_block()
+    }
+
+    fn finalize_block(&mut self, data: Vec<u8>) -> Result<BlockId, Error> {
+        let mut script = self.script.borrow_mut();
+        let data = if script.has(Misbehavior::ForgeSealVotes) && !data.is_empty() {
+            script.forge_seal(data)?
+        } else {
+            data
+        };
+
+        let block_id = script.inner().finalize_block(data.clone())?;
+
+        if script.has(Misbehavior::Equivocate) {
+            script
+                .publish_twin(&block_id, data)
+                .unwrap_or_else(|err| warn!("Byzantine node couldn't publish twin: {}", err));
+        }
+
+        Ok(block_id)
+    }
+
+    fn cancel_block(&mut self) -> Result<(), Error> {
+        self.script.borrow_mut().inner().cancel_block()
+    }
+
+    fn check_blocks(&mut self, priority: Vec<BlockId>) -> Result<(), Error> {
+        self.script.borrow_mut().inner().check_blocks(priority)
+    }
+
+    fn commit_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        self.script.borrow_mut().inner().commit_block(block_id)
+    }
+
+    fn ignore_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        self.script.borrow_mut().inner().ignore_block(block_id)
+    }
+
+    fn fail_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        self.script.borrow_mut().inner().fail_block(block_id)
+    }
+
+    fn get_blocks(&mut self, block_ids: Vec<BlockId>) -> Result<HashMap<BlockId, Block>, Error> {
+        self.script.borrow_mut().inner().get_blocks(block_ids)
+    }
+
+    fn get_chain_head(&mut self) -> Result<Block, Error> {
+        self.script.borrow_mut().inner().get_chain_head()
+    }
+
+    fn get_settings(
+        &mut self,
+        block_id: BlockId,
+        settings: Vec<String>,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.script
+            .borrow_mut()
+            .inner()
+            .get_settings(block_id, settings)
+    }
+
+    fn get_state(
+        &mut self,
+        block_id: BlockId,
+        addresses: Vec<String>,
+    ) -> Result<HashMap<String, Vec<u8>>, Error> {
+        self.script
+            .borrow_mut()
+            .inner()
+            .get_state(block_id, addresses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{Simulation, SimulationConfig};
+
+    /// Run a 4-node simulation with the given node controlled by a Byzantine adversary with the
+    /// given misbehaviors
+    fn simulate(node: usize, misbehaviors: Vec<Misbehavior>) -> (Simulation, Byzantine) {
+        let byzantine = Byzantine::new(misbehaviors);
+        let sim = Simulation::with_adversaries(
+            SimulationConfig::default(),
+            vec![(node, Box::new(byzantine.clone()) as Box<dyn Adversary>)],
+        );
+        (sim, byzantine)
+    }
+
+    /// A primary that sends PrePrepares for two different blocks at the same height to different
+    /// nodes must not get the nodes to commit different blocks.
+    #[test]
+    fn test_equivocating_primary() {
+        let (mut sim, byzantine) = simulate(0, vec![Misbehavior::Equivocate]);
+
+        sim.run_until(20_000, |_| false);
+
+        assert!(byzantine.performed() > 0);
+        sim.check_safety().expect("Safety violated");
+    }
+
+    /// A node that sends Commits for blocks that don't exist must not keep the other nodes from
+    /// making progress or from agreeing on the chain.
+    #[test]
+    fn test_commits_for_unknown_blocks() {
+        let (mut sim, byzantine) = simulate(3, vec![Misbehavior::CommitUnknownBlocks]);
+
+        assert!(sim.run_until_height(10, 60_000), "Network stalled");
+
+        assert!(byzantine.performed() > 0);
+        sim.check_safety().expect("Safety violated");
+    }
+
+    /// A primary that publishes blocks whose seals contain forged votes must not get any other
+    /// node to commit those blocks. Block 1 has no seal, so it is the only block that can be
+    /// committed.
+    #[test]
+    fn test_forged_seal_votes() {
+        let (mut sim, byzantine) = simulate(0, vec![Misbehavior::ForgeSealVotes]);
+
+        sim.run_until(20_000, |_| false);
+
+        assert!(byzantine.performed() > 0);
+        sim.check_safety().expect("Safety violated");
+
+        for node in 1..sim.node_count() {
+            assert!(
+                sim.committed_height(node) <= 1,
+                "Block with forged seal was committed"
+            );
+        }
+    }
+
+    /// NewView messages that don't have 2f distinct ViewChange votes, whether because there are
+    /// too few or because votes are repeated, must be rejected: the honest nodes must stay in
+    /// their view and keep committing blocks.
+    #[test]
+    fn test_invalid_new_views() {
+        for misbehavior in &[
+            Misbehavior::NewViewTooFewVotes,
+            Misbehavior::NewViewDuplicateVotes,
+        ] {
+            // Node 2 starts a view change to view 1 for no reason, and node 1 (the primary for
+            // view 1) announces the view with only node 2's vote
+            let announcer = Byzantine::new(vec![*misbehavior]);
+            let demander = Byzantine::new(vec![Misbehavior::DemandViewChange]);
+            let mut sim = Simulation::with_adversaries(
+                SimulationConfig::default(),
+                vec![
+                    (1, Box::new(announcer.clone()) as Box<dyn Adversary>),
+                    (2, Box::new(demander.clone()) as Box<dyn Adversary>),
+                ],
+            );
+
+            let progressed = sim.run_until(60_000, |sim| {
+                [0, 1, 3]
+                    .iter()
+                    .all(|node| sim.committed_height(*node) >= 8)
+            });
+            assert!(progressed, "Network stalled");
+
+            assert_eq!(1, demander.performed());
+            assert_eq!(1, announcer.performed());
+            for node in &[0, 1, 3] {
+                assert_eq!(0, sim.state(*node).view);
+                assert_eq!(PbftMode::Normal, sim.state(*node).mode);
+            }
+            sim.check_safety().expect("Safety violated");
+        }
+    }
+}
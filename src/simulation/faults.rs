@@ -0,0 +1,225 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Faults of the simulated network between nodes
+//!
+//! Every message between two nodes passes through the `FaultModel`, which decides (using the
+//! simulation's seeded generator) how long the message takes and whether it is dropped,
+//! duplicated, or allowed to overtake messages sent before it. Partitions cut nodes off from each
+//! other for a period of virtual time.
+//!
+//! Faults only apply to peer messages and block gossip between nodes; the updates a validator
+//! sends to its own node are always delivered immediately. Blocks are never dropped, duplicated,
+//! or reordered, since the validator makes sure every block eventually reaches its peers (and
+//! only after its predecessor): blocks sent across a partition are delivered once it heals.
+
+use std::collections::HashMap;
+
+use super::SimRng;
+
+/// Distribution of the time it takes a message to cross a link, in milliseconds
+#[derive(Debug, Clone, PartialEq)]
+pub enum Latency {
+    /// Every message takes exactly this long
+    Fixed(u64),
+    /// Latency is drawn uniformly from `[min, max]`
+    Uniform { min: u64, max: u64 },
+    /// Latency is usually drawn from `[min, max]`, but with the given probability it is drawn
+    /// from `[max, spike_max]` instead
+    Spiky {
+        min: u64,
+        max: u64,
+        spike_probability: f64,
+        spike_max: u64,
+    },
+}
+
+impl Latency {
+    pub fn sample(&self, rng: &mut SimRng) -> u64 {
+        match *self {
+            Latency::Fixed(latency) => latency,
+            Latency::Uniform { min, max } => rng.range(min, max),
+            Latency::Spiky {
+                min,
+                max,
+                spike_probability,
+                spike_max,
+            } => {
+                if rng.chance(spike_probability) {
+                    rng.range(max, spike_max)
+                } else {
+                    rng.range(min, max)
+                }
+            }
+        }
+    }
+}
+
+/// Faults of a single (directed) link between two nodes
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkFaults {
+    pub latency: Latency,
+    /// Probability that a message is lost
+    pub drop_probability: f64,
+    /// Probability that a message is delivered twice
+    pub duplicate_probability: f64,
+    /// Probability that a message may overtake messages sent before it; otherwise the link
+    /// delivers messages in the order they were sent
+    pub reorder_probability: f64,
+}
+
+impl Default for LinkFaults {
+    fn default() -> Self {
+        LinkFaults {
+            latency: Latency::Uniform { min: 1, max: 50 },
+            drop_probability: 0.0,
+            duplicate_probability: 0.0,
+            reorder_probability: 0.0,
+        }
+    }
+}
+
+/// A period of virtual time during which groups of nodes can't reach each other
+///
+/// A single group is cut off from the rest of the network. With several groups, nodes in
+/// different groups are cut off from each other, and nodes that aren't in any group are cut off
+/// from everyone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partition {
+    /// When the partition starts, in milliseconds
+    pub start: u64,
+    /// When the partition heals, in milliseconds
+    pub end: u64,
+    pub groups: Vec<Vec<usize>>,
+}
+
+impl Partition {
+    /// Isolate a single node from the rest of the network
+    pub fn isolate(node: usize, start: u64, end: u64) -> Self {
+        Partition {
+            start,
+            end,
+            groups: vec![vec![node]],
+        }
+    }
+
+    fn separates(&self, from: usize, to: usize, time: u64) -> bool {
+        if time < self.start || time >= self.end || from == to {
+            return false;
+        }
+        let group_of = |node| self.groups.iter().position(|group| group.contains(&node));
+        match (group_of(from), group_of(to)) {
+            (Some(from), Some(to)) => from != to,
+            (None, None) => self.groups.len() > 1,
+            _ => true,
+        }
+    }
+}
+
+/// Faults of the whole simulated network
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultModel {
+    /// Faults of every link that isn't listed in `links`
+    pub default_link: LinkFaults,
+    /// Faults of specific links, by (sender, recipient)
+    pub links: HashMap<(usize, usize), LinkFaults>,
+    pub partitions: Vec<Partition>,
+}
+
+impl FaultModel {
+    /// Faults of the link from one node to another
+    pub fn link(&self, from: usize, to: usize) -> &LinkFaults {
+        self.links.get(&(from, to)).unwrap_or(&self.default_link)
+    }
+
+    /// If the nodes are partitioned from each other at the given time, get the time when the
+    /// partition heals
+    pub fn partitioned_until(&self, from: usize, to: usize, time: u64) -> Option<u64> {
+        self.partitions
+            .iter()
+            .filter(|partition| partition.separates(from, to, time))
+            .map(|partition| partition.end)
+            .max()
+    }
+}
+
+/// What happened to the messages sent between nodes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct NetworkStats {
+    /// Peer messages and blocks sent from one node to another
+    pub sent: u64,
+    /// Messages lost due to link faults
+    pub dropped: u64,
+    /// Messages lost because the sender and recipient were partitioned
+    pub partitioned: u64,
+    /// Extra copies of messages that were delivered
+    pub duplicated: u64,
+    /// Messages that were allowed to overtake earlier messages
+    pub reordered: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Partitions must only separate nodes in different groups (or isolated nodes) while they
+    /// are in effect, and links without specific faults must use the default.
+    #[test]
+    fn test_fault_model() {
+        let mut faults = FaultModel::default();
+        faults.partitions.push(Partition::isolate(3, 100, 200));
+        faults.partitions.push(Partition {
+            start: 150,
+            end: 300,
+            groups: vec![vec![0, 1], vec![2]],
+        });
+        faults.links.insert(
+            (0, 1),
+            LinkFaults {
+                latency: Latency::Fixed(5),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(None, faults.partitioned_until(0, 3, 99));
+        assert_eq!(Some(200), faults.partitioned_until(0, 3, 100));
+        assert_eq!(Some(200), faults.partitioned_until(3, 1, 199));
+        assert_eq!(None, faults.partitioned_until(0, 1, 150));
+        assert_eq!(None, faults.partitioned_until(2, 1, 149));
+        assert_eq!(Some(300), faults.partitioned_until(2, 1, 150));
+        // Node 3 isn't in either group of the second partition, so it is cut off until it heals
+        assert_eq!(Some(300), faults.partitioned_until(3, 0, 150));
+        assert_eq!(None, faults.partitioned_until(2, 1, 300));
+
+        assert_eq!(Latency::Fixed(5), faults.link(0, 1).latency);
+        assert_eq!(faults.default_link, *faults.link(1, 0));
+
+        let mut rng = SimRng::new(1);
+        let spiky = Latency::Spiky {
+            min: 1,
+            max: 10,
+            spike_probability: 0.5,
+            spike_max: 1000,
+        };
+        let samples: Vec<u64> = (0..1000).map(|_| spiky.sample(&mut rng)).collect();
+        assert!(samples
+            .iter()
+            .all(|latency| *latency >= 1 && *latency <= 1000));
+        assert!(samples.iter().any(|latency| *latency > 10));
+        assert!(samples.iter().any(|latency| *latency < 10));
+    }
+}
@@ -0,0 +1,1154 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Deterministic in-process simulation of a PBFT network
+//!
+//! A `Simulation` runs several `PbftNode`s in a single thread against a shared simulated
+//! validator. Each node gets its own `SimulatedValidator` as its `Service`; the validators sign
+//! and route the nodes' peer messages to each other, build and distribute blocks, and keep track
+//! of what each node has committed. Every update is delivered through a single event queue in
+//! virtual time, and messages between nodes are subject to the network faults of the
+//! `FaultModel`, which are decided by a seeded random number generator; a run is completely
+//! determined by its `SimulationConfig`.
+//!
+//! The nodes' timeouts are driven by a `ManualClock` that follows the virtual time, so view
+//! changes happen just as they would on a real network, without waiting for them.
+//!
+//! Events can also be processed in an order chosen by the caller (see `step_event`), which the
+//! property-based tests of the `model` module use to check the protocol's invariants against
+//! arbitrary interleavings.
+//!
+//! This module is available to unit tests and, for other crates, with the `simulation` feature.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hex;
+use protobuf::Message;
+use sawtooth_sdk::consensus::engine::{
+    Block, BlockId, Error, PeerId, PeerMessage, PeerMessageHeader, Update,
+};
+use sawtooth_sdk::consensus::service::Service;
+use sawtooth_sdk::messages::consensus::ConsensusPeerMessageHeader;
+use sawtooth_sdk::signing::secp256k1::Secp256k1PrivateKey;
+use sawtooth_sdk::signing::{create_context, Context};
+
+use crate::config::PbftConfig;
+use crate::engine::{check_timeouts, handle_update, log_any_error};
+use crate::hash::hash_sha512;
+use crate::node::PbftNode;
+use crate::protos::pbft_message::PbftMessage;
+use crate::state::PbftState;
+use crate::timing::ManualClock;
+
+use self::faults::{FaultModel, NetworkStats};
+
+#[cfg(any(test, feature = "byzantine"))]
+pub mod byzantine;
+pub mod faults;
+#[cfg(test)]
+mod model;
+
+/// On-chain setting that holds the list of members
+const MEMBERS_SETTING: &str = "sawtooth.consensus.pbft.members";
+
+/// Small, fast pseudo-random number generator (SplitMix64) used to drive simulations
+///
+/// A simple generator is used instead of an external crate so that the sequence produced by a
+/// seed never changes underneath recorded runs.
+#[derive(Debug, Clone)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Get a number in the inclusive range `[low, high]`
+    pub fn range(&mut self, low: u64, high: u64) -> u64 {
+        if high <= low {
+            return low;
+        }
+        match (high - low).checked_add(1) {
+            Some(span) => low + self.next_u64() % span,
+            None => self.next_u64(),
+        }
+    }
+
+    /// Get `true` with the given probability
+    pub fn chance(&mut self, probability: f64) -> bool {
+        // Use the top 53 bits, which is all the precision an f64 has
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+}
+
+/// Parameters of a simulation
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    /// Number of nodes in the network
+    pub nodes: usize,
+    /// Seed for all of the simulation's randomness, including the nodes' keys
+    pub seed: u64,
+    /// Faults of the network between the nodes
+    pub faults: FaultModel,
+    /// Configuration used by every node; the `members` are replaced by the simulated nodes' IDs
+    pub pbft: PbftConfig,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            nodes: 4,
+            seed: 0,
+            faults: FaultModel::default(),
+            pbft: PbftConfig::default(),
+        }
+    }
+}
+
+/// A message sent from one node to another
+#[derive(Clone)]
+enum Message {
+    Peer {
+        message_type: String,
+        payload: Vec<u8>,
+    },
+    Block(Block),
+}
+
+/// Something that happens to a node at a point in virtual time
+enum Event {
+    /// The node's validator sends it an update
+    Update { node: usize, update: Update },
+    /// A message from another node arrives
+    Deliver {
+        from: usize,
+        to: usize,
+        message: Message,
+    },
+    /// The node's block publishing ticker fires
+    Tick { node: usize },
+}
+
+/// What a single simulated validator keeps track of
+struct ValidatorState {
+    peer_id: PeerId,
+    private_key: Secp256k1PrivateKey,
+    /// Parent of the block that is currently being built, if any
+    building: Option<Block>,
+    /// Every block committed by this validator, starting with genesis
+    committed: Vec<Block>,
+}
+
+/// State shared by all of the simulated validators
+struct World {
+    /// Current virtual time in milliseconds
+    now: u64,
+    /// Pending events, ordered by delivery time and then by the order they were scheduled in
+    events: BTreeMap<(u64, u64), Event>,
+    next_event_id: u64,
+    rng: SimRng,
+    faults: FaultModel,
+    stats: NetworkStats,
+    /// Delivery time of the last message sent over each (sender, recipient) link; links deliver
+    /// messages in the order they were sent, like the validator's connections do
+    links: HashMap<(usize, usize), u64>,
+    context: Box<dyn Context>,
+    validators: Vec<ValidatorState>,
+    blocks: HashMap<BlockId, Block>,
+    settings: HashMap<String, String>,
+    blocks_built: u64,
+    /// Nodes that have sent a Commit message for each (view, seq_num, block_id)
+    commits: HashMap<(u64, u64, BlockId), BTreeSet<usize>>,
+    /// Blocks that the validators consider invalid
+    invalid_blocks: BTreeSet<BlockId>,
+}
+
+impl World {
+    fn schedule(&mut self, time: u64, event: Event) {
+        self.events.insert((time, self.next_event_id), event);
+        self.next_event_id += 1;
+    }
+
+    /// Send an update from a node's validator to the node itself
+    fn notify(&mut self, node: usize, update: Update) {
+        let now = self.now;
+        self.schedule(now, Event::Update { node, update });
+    }
+
+    /// Send a message from one node to another, subject to the faults of the network
+    fn send(&mut self, from: usize, to: usize, message: Message) {
+        self.stats.sent += 1;
+        let link = self.faults.link(from, to).clone();
+        let is_block = match message {
+            Message::Block(_) => true,
+            Message::Peer { .. } => false,
+        };
+
+        // Peer messages sent across a partition are lost, but blocks are held until it heals
+        let mut earliest = self.now;
+        if let Some(heal) = self.faults.partitioned_until(from, to, self.now) {
+            if !is_block {
+                self.stats.partitioned += 1;
+                return;
+            }
+            earliest = heal;
+        }
+
+        if !is_block && self.rng.chance(link.drop_probability) {
+            self.stats.dropped += 1;
+            return;
+        }
+
+        let copies = if !is_block && self.rng.chance(link.duplicate_probability) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            let mut time = earliest + link.latency.sample(&mut self.rng);
+            if !is_block && self.rng.chance(link.reorder_probability) {
+                self.stats.reordered += 1;
+            } else {
+                time = time.max(self.links.get(&(from, to)).cloned().unwrap_or(0));
+                self.links.insert((from, to), time);
+            }
+            self.schedule(
+                time,
+                Event::Deliver {
+                    from,
+                    to,
+                    message: message.clone(),
+                },
+            );
+        }
+    }
+
+    /// Turn a message that has arrived into the update the recipient's validator gives it
+    fn receive(&self, from: usize, message: Message) -> Result<Update, Error> {
+        match message {
+            Message::Peer {
+                message_type,
+                payload,
+            } => Ok(Update::PeerMessage(
+                self.peer_message(from, &message_type, payload)?,
+                self.validators[from].peer_id.clone(),
+            )),
+            Message::Block(block) => Ok(Update::BlockNew(block)),
+        }
+    }
+
+    fn next_event(&mut self) -> Option<(u64, Event)> {
+        self.take_event(0)
+    }
+
+    /// Remove the pending event with the given position in the queue; if the event was scheduled
+    /// for the past (because later events were taken first), it happens now instead
+    ///
+    /// A node's validator sends it updates in order, so if the event is an update, the node's
+    /// earliest pending update is taken instead.
+    fn take_event(&mut self, index: usize) -> Option<(u64, Event)> {
+        let mut key = *self.events.keys().nth(index)?;
+        if let Some(Event::Update { node, .. }) = self.events.get(&key) {
+            let node = *node;
+            key = *self
+                .events
+                .iter()
+                .find(|(_, event)| match event {
+                    Event::Update { node: other, .. } => *other == node,
+                    _ => false,
+                })
+                .map(|(key, _)| key)?;
+        }
+        let event = self.events.remove(&key)?;
+        self.now = self.now.max(key.0);
+        Some((self.now, event))
+    }
+
+    /// Keep track of the Commit messages each node sends
+    fn record_commit(&mut self, from: usize, message_type: &str, payload: &[u8]) {
+        if message_type != "Commit" {
+            return;
+        }
+        if let Ok(message) = protobuf::parse_from_bytes::<PbftMessage>(payload) {
+            let info = message.get_info();
+            self.commits
+                .entry((
+                    info.get_view(),
+                    info.get_seq_num(),
+                    message.get_block_id().to_vec(),
+                ))
+                .or_default()
+                .insert(from);
+        }
+    }
+
+    fn next_event_time(&self) -> Option<u64> {
+        self.events.keys().next().map(|(time, _)| *time)
+    }
+
+    fn index_of(&self, peer_id: &[u8]) -> Option<usize> {
+        self.validators
+            .iter()
+            .position(|validator| validator.peer_id.as_slice() == peer_id)
+    }
+
+    /// Sign the bytes with the given node's key
+    fn sign(&self, node: usize, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+        self.context
+            .sign(bytes, &self.validators[node].private_key)
+            .map_err(|err| Error::EncodingError(err.to_string()))
+            .and_then(|signature| {
+                hex::decode(signature).map_err(|err| Error::EncodingError(err.to_string()))
+            })
+    }
+
+    /// Wrap a message from the given node in a signed peer message, as the validator would
+    fn peer_message(
+        &self,
+        from: usize,
+        message_type: &str,
+        payload: Vec<u8>,
+    ) -> Result<PeerMessage, Error> {
+        let validator = &self.validators[from];
+
+        let mut header = ConsensusPeerMessageHeader::new();
+        header.set_signer_id(validator.peer_id.clone());
+        header.set_content_sha512(hash_sha512(&payload));
+        header.set_message_type(message_type.into());
+        header.set_name("pbft".into());
+        header.set_version("1.0".into());
+
+        let header_bytes = header
+            .write_to_bytes()
+            .map_err(|err| Error::EncodingError(err.to_string()))?;
+        let header_signature = self.sign(from, &header_bytes)?;
+
+        Ok(PeerMessage {
+            header: PeerMessageHeader {
+                signer_id: validator.peer_id.clone(),
+                content_sha512: header.get_content_sha512().to_vec(),
+                message_type: message_type.into(),
+                name: "pbft".into(),
+                version: "1.0".into(),
+            },
+            header_bytes,
+            header_signature,
+            content: payload,
+        })
+    }
+}
+
+/// The `Service` given to each simulated node
+struct SimulatedValidator {
+    node: usize,
+    world: Rc<RefCell<World>>,
+}
+
+impl SimulatedValidator {
+    fn send_from(&self, to: usize, message_type: &str, payload: Vec<u8>) {
+        let mut world = self.world.borrow_mut();
+        world.record_commit(self.node, message_type, &payload);
+        world.send(
+            self.node,
+            to,
+            Message::Peer {
+                message_type: message_type.into(),
+                payload,
+            },
+        );
+    }
+
+    fn get_block(world: &World, block_id: &[u8]) -> Result<Block, Error> {
+        world
+            .blocks
+            .get(block_id)
+            .cloned()
+            .ok_or_else(|| Error::UnknownBlock(hex::encode(block_id)))
+    }
+}
+
+impl Service for SimulatedValidator {
+    fn send_to(
+        &mut self,
+        peer: &PeerId,
+        message_type: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), Error> {
+        let to = self
+            .world
+            .borrow()
+            .index_of(peer)
+            .ok_or_else(|| Error::UnknownPeer(hex::encode(peer)))?;
+        self.send_from(to, message_type, payload);
+        Ok(())
+    }
+
+    fn broadcast(&mut self, message_type: &str, payload: Vec<u8>) -> Result<(), Error> {
+        let nodes = self.world.borrow().validators.len();
+        for to in (0..nodes).filter(|to| *to != self.node) {
+            self.send_from(to, message_type, payload.clone());
+        }
+        Ok(())
+    }
+
+    fn initialize_block(&mut self, previous_id: Option<BlockId>) -> Result<(), Error> {
+        let mut world = self.world.borrow_mut();
+        let parent = match previous_id {
+            Some(id) => Self::get_block(&world, &id)?,
+            None => world.validators[self.node]
+                .committed
+                .last()
+                .cloned()
+                .ok_or(Error::NoChainHead)?,
+        };
+
+        let validator = &mut world.validators[self.node];
+        if validator.building.is_some() {
+            return Err(Error::InvalidState("Block already initialized".into()));
+        }
+        validator.building = Some(parent);
+        Ok(())
+    }
+
+    fn summarize_block(&mut self) -> Result<Vec<u8>, Error> {
+        if self.world.borrow().validators[self.node].building.is_some() {
+            Ok(vec![])
+        } else {
+            Err(Error::BlockNotReady)
+        }
+    }
+
+    fn finalize_block(&mut self, data: Vec<u8>) -> Result<BlockId, Error> {
+        let mut world = self.world.borrow_mut();
+        let parent = world.validators[self.node]
+            .building
+            .take()
+            .ok_or_else(|| Error::InvalidState("No block initialized".into()))?;
+        let signer_id = world.validators[self.node].peer_id.clone();
+        let block_num = parent.block_num + 1;
+
+        let mut preimage = parent.block_id.clone();
+        preimage.extend_from_slice(&signer_id);
+        preimage.extend_from_slice(&block_num.to_be_bytes());
+        preimage.extend_from_slice(&world.blocks_built.to_be_bytes());
+        preimage.extend_from_slice(&data);
+        world.blocks_built += 1;
+
+        let block = Block {
+            block_id: hash_sha512(&preimage),
+            previous_id: parent.block_id,
+            signer_id,
+            block_num,
+            payload: data,
+            summary: vec![],
+        };
+        world.blocks.insert(block.block_id.clone(), block.clone());
+
+        // The new block is gossiped to all validators, and each one tells its own node
+        world.notify(self.node, Update::BlockNew(block.clone()));
+        for to in (0..world.validators.len()).filter(|to| *to != self.node) {
+            world.send(self.node, to, Message::Block(block.clone()));
+        }
+
+        Ok(block.block_id)
+    }
+
+    fn cancel_block(&mut self) -> Result<(), Error> {
+        self.world.borrow_mut().validators[self.node].building = None;
+        Ok(())
+    }
+
+    fn check_blocks(&mut self, priority: Vec<BlockId>) -> Result<(), Error> {
+        let mut world = self.world.borrow_mut();
+        for block_id in priority {
+            Self::get_block(&world, &block_id)?;
+            if world.invalid_blocks.contains(&block_id) {
+                world.notify(self.node, Update::BlockInvalid(block_id));
+            } else {
+                world.notify(self.node, Update::BlockValid(block_id));
+            }
+        }
+        Ok(())
+    }
+
+    fn commit_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        let mut world = self.world.borrow_mut();
+        let block = Self::get_block(&world, &block_id)?;
+        world.validators[self.node].committed.push(block);
+        world.notify(self.node, Update::BlockCommit(block_id));
+        Ok(())
+    }
+
+    fn ignore_block(&mut self, _block_id: BlockId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn fail_block(&mut self, _block_id: BlockId) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_blocks(&mut self, block_ids: Vec<BlockId>) -> Result<HashMap<BlockId, Block>, Error> {
+        let world = self.world.borrow();
+        Ok(block_ids
+            .into_iter()
+            .filter_map(|id| world.blocks.get(&id).cloned().map(|block| (id, block)))
+            .collect())
+    }
+
+    fn get_chain_head(&mut self) -> Result<Block, Error> {
+        self.world.borrow().validators[self.node]
+            .committed
+            .last()
+            .cloned()
+            .ok_or(Error::NoChainHead)
+    }
+
+    fn get_settings(
+        &mut self,
+        _block_id: BlockId,
+        settings: Vec<String>,
+    ) -> Result<HashMap<String, String>, Error> {
+        let world = self.world.borrow();
+        Ok(settings
+            .into_iter()
+            .filter_map(|key| world.settings.get(&key).cloned().map(|value| (key, value)))
+            .collect())
+    }
+
+    fn get_state(
+        &mut self,
+        _block_id: BlockId,
+        _addresses: Vec<String>,
+    ) -> Result<HashMap<String, Vec<u8>>, Error> {
+        Ok(HashMap::new())
+    }
+}
+
+/// A node taking part in a simulation
+struct SimulatedNode {
+    node: PbftNode,
+    state: PbftState,
+    adversary: Option<Box<dyn Adversary>>,
+}
+
+/// Signs bytes with a node's key, as its validator does for the node's messages
+pub type Signer = Box<dyn Fn(&[u8]) -> Result<Vec<u8>, Error>>;
+
+/// A way for a simulated node to deviate from the protocol (see the `byzantine` module)
+///
+/// The node itself is an ordinary `PbftNode`; an adversary misbehaves by tampering with what the
+/// node sends through its service and by making the node do things it otherwise wouldn't.
+pub trait Adversary {
+    /// Wrap the service that is given to the node
+    fn wrap_service(
+        &mut self,
+        service: Box<dyn Service>,
+        peer_id: &[u8],
+        members: &[PeerId],
+        signer: Signer,
+    ) -> Box<dyn Service>;
+
+    /// Called with every update before the node handles it
+    fn observe(&mut self, update: &Update);
+
+    /// Called each time the node's block publishing ticker fires, after the node has tried to
+    /// publish a block
+    fn act(&mut self, node: &mut PbftNode, state: &mut PbftState);
+}
+
+/// A network of PBFT nodes running in virtual time
+pub struct Simulation {
+    world: Rc<RefCell<World>>,
+    nodes: Vec<SimulatedNode>,
+    /// Clock used by all nodes, kept in step with the virtual time
+    clock: ManualClock,
+    /// Block publishing delay in milliseconds
+    publishing_delay: u64,
+}
+
+impl Simulation {
+    /// Create the network and start all of its nodes at genesis
+    pub fn new(config: SimulationConfig) -> Self {
+        Self::with_adversaries(config, vec![])
+    }
+
+    /// Create the network with some of its nodes (by index) controlled by adversaries, and start
+    /// all of its nodes at genesis
+    pub fn with_adversaries(
+        config: SimulationConfig,
+        adversaries: Vec<(usize, Box<dyn Adversary>)>,
+    ) -> Self {
+        let mut adversaries: HashMap<usize, Box<dyn Adversary>> = adversaries.into_iter().collect();
+
+        let context = create_context("secp256k1").expect("Failed to create context");
+        let mut rng = SimRng::new(config.seed);
+
+        let genesis = Block {
+            block_id: hash_sha512(b"genesis"),
+            previous_id: vec![],
+            signer_id: vec![],
+            block_num: 0,
+            payload: vec![],
+            summary: vec![],
+        };
+
+        let validators: Vec<ValidatorState> = (0..config.nodes)
+            .map(|_| {
+                let private_key = Secp256k1PrivateKey::from_hex(&hex::encode(rng.bytes(32)))
+                    .expect("Failed to create private key");
+                let peer_id = context
+                    .get_public_key(&private_key)
+                    .expect("Failed to get public key")
+                    .as_slice()
+                    .to_vec();
+                ValidatorState {
+                    peer_id,
+                    private_key,
+                    building: None,
+                    committed: vec![genesis.clone()],
+                }
+            })
+            .collect();
+
+        let members: Vec<PeerId> = validators.iter().map(|v| v.peer_id.clone()).collect();
+        let mut settings = HashMap::new();
+        settings.insert(
+            MEMBERS_SETTING.to_string(),
+            serde_json::to_string(&members.iter().map(hex::encode).collect::<Vec<_>>())
+                .expect("Failed to serialize members"),
+        );
+
+        let mut blocks = HashMap::new();
+        blocks.insert(genesis.block_id.clone(), genesis.clone());
+
+        let world = Rc::new(RefCell::new(World {
+            now: 0,
+            events: BTreeMap::new(),
+            next_event_id: 0,
+            rng,
+            faults: config.faults,
+            stats: NetworkStats::default(),
+            links: HashMap::new(),
+            context,
+            validators,
+            blocks,
+            settings,
+            blocks_built: 0,
+            commits: HashMap::new(),
+            invalid_blocks: BTreeSet::new(),
+        }));
+
+        let mut pbft_config = config.pbft;
+        pbft_config.members = members.clone();
+        let publishing_delay = pbft_config.block_publishing_delay.as_millis() as u64;
+        let clock = ManualClock::new();
+
+        let nodes = members
+            .into_iter()
+            .enumerate()
+            .map(|(index, peer_id)| {
+                let mut service: Box<dyn Service> = Box::new(SimulatedValidator {
+                    node: index,
+                    world: world.clone(),
+                });
+                let mut adversary = adversaries.remove(&index);
+                if let Some(adversary) = adversary.as_mut() {
+                    let signer_world = world.clone();
+                    service = adversary.wrap_service(
+                        service,
+                        &peer_id,
+                        &pbft_config.members,
+                        Box::new(move |bytes| signer_world.borrow().sign(index, bytes)),
+                    );
+                }
+
+                let mut state = PbftState::new(peer_id, genesis.block_num, &pbft_config);
+                state.set_clock(Arc::new(clock.clone()));
+                let node =
+                    PbftNode::new(&pbft_config, genesis.clone(), vec![], service, &mut state);
+                node.start_idle_timeout(&mut state);
+                world
+                    .borrow_mut()
+                    .schedule(publishing_delay, Event::Tick { node: index });
+                SimulatedNode {
+                    node,
+                    state,
+                    adversary,
+                }
+            })
+            .collect();
+
+        Simulation {
+            world,
+            nodes,
+            clock,
+            publishing_delay,
+        }
+    }
+
+    /// Current virtual time in milliseconds
+    pub fn now(&self) -> u64 {
+        self.world.borrow().now
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// ID of the given node
+    pub fn peer_id(&self, node: usize) -> PeerId {
+        self.world.borrow().validators[node].peer_id.clone()
+    }
+
+    /// PBFT state of the given node
+    pub fn state(&self, node: usize) -> &PbftState {
+        &self.nodes[node].state
+    }
+
+    /// All blocks committed by the given node, starting with genesis
+    pub fn committed_blocks(&self, node: usize) -> Vec<Block> {
+        self.world.borrow().validators[node].committed.clone()
+    }
+
+    /// Block number of the given node's chain head
+    pub fn committed_height(&self, node: usize) -> u64 {
+        self.world.borrow().validators[node]
+            .committed
+            .last()
+            .map(|block| block.block_num)
+            .unwrap_or(0)
+    }
+
+    /// Lowest chain head block number of all nodes
+    pub fn min_committed_height(&self) -> u64 {
+        (0..self.nodes.len())
+            .map(|node| self.committed_height(node))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// What has happened to the messages sent between nodes so far
+    pub fn network_stats(&self) -> NetworkStats {
+        self.world.borrow().stats
+    }
+
+    /// Number of events that haven't been processed yet
+    pub fn pending_events(&self) -> usize {
+        self.world.borrow().events.len()
+    }
+
+    /// Process the next event; returns `false` if there are no more events
+    pub fn step(&mut self) -> bool {
+        let next = self.world.borrow_mut().next_event();
+        match next {
+            Some((time, event)) => {
+                self.process(time, event);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Process the pending event with the given position in the queue (ordered by time) instead
+    /// of the next one, so that events may happen in any order; returns `false` if there is no
+    /// such event
+    ///
+    /// Virtual time never goes backwards: an event that is taken after later ones happens late.
+    pub fn step_event(&mut self, index: usize) -> bool {
+        let taken = self.world.borrow_mut().take_event(index);
+        match taken {
+            Some((time, event)) => {
+                self.process(time, event);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// If the pending event with the given position in the queue is a validator reporting a
+    /// block as valid, make the block invalid instead; every validator reports the block as
+    /// invalid from then on. Returns `true` if the block was made invalid.
+    pub fn invalidate_event(&mut self, index: usize) -> bool {
+        let mut world = self.world.borrow_mut();
+        let block_id = match world.events.values().nth(index) {
+            Some(Event::Update {
+                update: Update::BlockValid(block_id),
+                ..
+            }) => block_id.clone(),
+            _ => return false,
+        };
+
+        for event in world.events.values_mut() {
+            if let Event::Update { update, .. } = event {
+                let reports_valid = match update {
+                    Update::BlockValid(id) => *id == block_id,
+                    _ => false,
+                };
+                if reports_valid {
+                    *update = Update::BlockInvalid(block_id.clone());
+                }
+            }
+        }
+        world.invalid_blocks.insert(block_id);
+        true
+    }
+
+    /// Move virtual time forward without processing any events, so that timeouts expire
+    pub fn advance(&mut self, millis: u64) {
+        let mut world = self.world.borrow_mut();
+        world.now += millis;
+        self.clock.advance_to(Duration::from_millis(world.now));
+    }
+
+    fn process(&mut self, time: u64, event: Event) {
+        self.clock.advance_to(Duration::from_millis(time));
+
+        match event {
+            Event::Update { node, update } => self.dispatch(node, update),
+            Event::Deliver { from, to, message } => {
+                let update = self.world.borrow().receive(from, message);
+                match update {
+                    Ok(update) => self.dispatch(to, update),
+                    Err(err) => error!("Failed to deliver message from node {}: {}", from, err),
+                }
+            }
+            Event::Tick { node } => {
+                let SimulatedNode {
+                    node: pbft,
+                    state,
+                    adversary,
+                } = &mut self.nodes[node];
+                log_any_error(pbft.try_publish(state));
+                if let Some(adversary) = adversary {
+                    adversary.act(pbft, state);
+                }
+                check_timeouts(pbft, state);
+
+                let mut world = self.world.borrow_mut();
+                let next = world.now + self.publishing_delay.max(1);
+                world.schedule(next, Event::Tick { node });
+            }
+        }
+    }
+
+    /// Have a node handle an update, as the engine's main loop does
+    fn dispatch(&mut self, node: usize, update: Update) {
+        let SimulatedNode {
+            node: pbft,
+            state,
+            adversary,
+        } = &mut self.nodes[node];
+        if let Some(adversary) = adversary {
+            adversary.observe(&update);
+        }
+        if let Err(err) = handle_update(pbft, Ok(update), state) {
+            log_any_error(Err(err));
+        }
+        check_timeouts(pbft, state);
+    }
+
+    /// Process events until the condition holds; returns `false` if virtual time would pass the
+    /// deadline (in milliseconds) first
+    pub fn run_until<F>(&mut self, deadline: u64, mut condition: F) -> bool
+    where
+        F: FnMut(&Simulation) -> bool,
+    {
+        loop {
+            if condition(self) {
+                return true;
+            }
+            match self.world.borrow().next_event_time() {
+                Some(time) if time <= deadline => {}
+                _ => return false,
+            }
+            self.step();
+        }
+    }
+
+    /// Process events until every node has committed the block at the given height; returns
+    /// `false` if this doesn't happen before the deadline
+    pub fn run_until_height(&mut self, height: u64, deadline: u64) -> bool {
+        self.run_until(deadline, |sim| sim.min_committed_height() >= height)
+    }
+
+    /// Verify that no two nodes have committed different blocks at the same height, and that
+    /// each node's committed blocks form a chain
+    pub fn check_safety(&self) -> Result<(), String> {
+        let world = self.world.borrow();
+        let mut committed_at: BTreeMap<u64, (usize, BlockId)> = BTreeMap::new();
+
+        for (node, validator) in world.validators.iter().enumerate() {
+            for pair in validator.committed.windows(2) {
+                let (parent, block) = (&pair[0], &pair[1]);
+                if block.previous_id != parent.block_id || block.block_num != parent.block_num + 1 {
+                    return Err(format!(
+                        "Node {} committed block {} / {} on top of {} / {}",
+                        node,
+                        block.block_num,
+                        hex::encode(&block.block_id),
+                        parent.block_num,
+                        hex::encode(&parent.block_id),
+                    ));
+                }
+            }
+
+            for block in &validator.committed {
+                let (other, other_id) = committed_at
+                    .entry(block.block_num)
+                    .or_insert_with(|| (node, block.block_id.clone()));
+                if *other_id != block.block_id {
+                    return Err(format!(
+                        "Nodes {} and {} committed different blocks at height {}: {} and {}",
+                        other,
+                        node,
+                        block.block_num,
+                        hex::encode(other_id),
+                        hex::encode(&block.block_id),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify that every block committed by any node (other than genesis) had Commit messages
+    /// sent for it by at least 2f + 1 distinct nodes in a single view
+    pub fn check_commit_quorums(&self) -> Result<(), String> {
+        let world = self.world.borrow();
+
+        for (node, validator) in world.validators.iter().enumerate() {
+            let quorum = 2 * self.nodes[node].state.f as usize + 1;
+            for block in validator.committed.iter().skip(1) {
+                let most = world
+                    .commits
+                    .iter()
+                    .filter(|((_, seq_num, block_id), _)| {
+                        *seq_num == block.block_num && *block_id == block.block_id
+                    })
+                    .map(|(_, senders)| senders.len())
+                    .max()
+                    .unwrap_or(0);
+                if most < quorum {
+                    return Err(format!(
+                        "Node {} committed block {} / {} with at most {} matching Commits in any \
+                         view; {} are required",
+                        node,
+                        block.block_num,
+                        hex::encode(&block.block_id),
+                        most,
+                        quorum,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::faults::{Latency, LinkFaults, Partition};
+    use super::*;
+
+    /// The seeded generator must produce the same sequence for the same seed, and ranges must be
+    /// inclusive and respected.
+    #[test]
+    fn test_sim_rng() {
+        let mut first = SimRng::new(42);
+        let mut second = SimRng::new(42);
+        let sequence: Vec<u64> = (0..10).map(|_| first.next_u64()).collect();
+        assert_eq!(
+            sequence,
+            (0..10).map(|_| second.next_u64()).collect::<Vec<_>>()
+        );
+        assert_ne!(
+            sequence,
+            (0..10)
+                .map(|_| SimRng::new(43).next_u64())
+                .collect::<Vec<_>>()
+        );
+
+        let mut rng = SimRng::new(0);
+        for _ in 0..1000 {
+            let value = rng.range(5, 7);
+            assert!(value >= 5 && value <= 7);
+        }
+        assert_eq!(3, rng.range(3, 3));
+    }
+
+    /// A network of 4 honest nodes must keep committing blocks (liveness), and all nodes must
+    /// commit the same chain (safety). Each block after the first must be committed with a
+    /// consensus seal in the following block.
+    #[test]
+    fn test_simulated_network() {
+        let mut sim = Simulation::new(SimulationConfig::default());
+
+        assert!(sim.run_until_height(10, 60_000), "Network stalled");
+        sim.check_safety().expect("Safety violated");
+
+        for node in 0..sim.node_count() {
+            let committed = sim.committed_blocks(node);
+            assert!(committed.len() >= 11);
+            assert!(committed[2..].iter().all(|block| !block.payload.is_empty()));
+            assert_eq!(committed[..11], sim.committed_blocks(0)[..11]);
+        }
+    }
+
+    /// Runs with the same seed must be identical, down to the block IDs (which include the
+    /// seals) and the virtual time that each block was committed at; runs with different seeds
+    /// must still satisfy safety and liveness.
+    #[test]
+    fn test_simulation_is_reproducible() {
+        let run = |seed| {
+            let mut sim = Simulation::new(SimulationConfig {
+                seed,
+                ..Default::default()
+            });
+            assert!(sim.run_until_height(5, 60_000), "Network stalled");
+            sim.check_safety().expect("Safety violated");
+            (
+                sim.now(),
+                (0..sim.node_count())
+                    .map(|node| sim.committed_blocks(node))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7).1, run(8).1);
+    }
+
+    /// On a network with slow, duplicated, and reordered messages, where one node also loses
+    /// many of its messages and is cut off from the others for a while, the other nodes must keep
+    /// committing blocks without it, the cut-off node must catch up once the partition heals, and
+    /// no two nodes may ever commit different blocks. The faults must be reproducible from the
+    /// seed.
+    #[test]
+    fn test_faulty_network() {
+        let unreliable = LinkFaults {
+            latency: Latency::Spiky {
+                min: 1,
+                max: 30,
+                spike_probability: 0.05,
+                spike_max: 500,
+            },
+            drop_probability: 0.0,
+            duplicate_probability: 0.1,
+            reorder_probability: 0.2,
+        };
+        let lossy = LinkFaults {
+            drop_probability: 0.2,
+            ..unreliable.clone()
+        };
+
+        let mut faults = FaultModel {
+            default_link: unreliable,
+            links: HashMap::new(),
+            partitions: vec![Partition::isolate(3, 2_000, 8_000)],
+        };
+        for node in 0..3 {
+            faults.links.insert((node, 3), lossy.clone());
+            faults.links.insert((3, node), lossy.clone());
+        }
+        // The partition is too short for view changes; those are tested separately
+        let mut pbft = PbftConfig::default();
+        pbft.idle_timeout = Duration::from_millis(60_000);
+        pbft.commit_timeout = Duration::from_millis(60_000);
+        let config = SimulationConfig {
+            seed: 3,
+            faults,
+            pbft,
+            ..Default::default()
+        };
+
+        let mut sim = Simulation::new(config.clone());
+
+        // While node 3 is cut off, it can commit at most a block that was already in progress,
+        // but the others still have a quorum and must keep going
+        sim.run_until(2_000, |_| false);
+        let isolated_height = sim.committed_height(3);
+        sim.run_until(7_999, |_| false);
+        assert!(sim.committed_height(3) <= isolated_height + 1);
+        assert!((0..3).all(|node| sim.committed_height(node) >= isolated_height + 3));
+        sim.check_safety()
+            .expect("Safety violated during partition");
+
+        assert!(sim.run_until_height(10, 60_000), "Network stalled");
+        sim.check_safety().expect("Safety violated after partition");
+
+        let stats = sim.network_stats();
+        assert!(stats.dropped > 0);
+        assert!(stats.partitioned > 0);
+        assert!(stats.duplicated > 0);
+        assert!(stats.reordered > 0);
+
+        let mut replay = Simulation::new(config);
+        assert!(replay.run_until_height(10, 60_000));
+        assert_eq!(sim.now(), replay.now());
+        assert_eq!(stats, replay.network_stats());
+        assert_eq!(sim.committed_blocks(3), replay.committed_blocks(3));
+    }
+
+    /// When the primary is cut off from the network, the other nodes must time out on the
+    /// simulation's clock, change to a new view, and keep committing blocks under the new
+    /// primary.
+    #[test]
+    fn test_view_change() {
+        let config = SimulationConfig {
+            faults: FaultModel {
+                partitions: vec![Partition::isolate(0, 5_000, 1_000_000)],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut sim = Simulation::new(config);
+
+        sim.run_until(5_000, |_| false);
+        let height = sim.committed_height(1);
+
+        let progressed = sim.run_until(120_000, |sim| {
+            (1..4).all(|node| sim.committed_height(node) >= height + 3)
+        });
+        assert!(progressed, "Network didn't recover from losing its primary");
+        for node in 1..4 {
+            assert!(sim.state(node).view > 0);
+        }
+        sim.check_safety().expect("Safety violated");
+    }
+}
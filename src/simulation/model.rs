@@ -0,0 +1,148 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Property-based model checking of the PBFT state machine
+//!
+//! Instead of processing a simulation's events in time order, these tests let `proptest` choose
+//! which pending event happens next, when time jumps ahead (so timeouts expire and views change),
+//! and which blocks the validators find invalid. After every action, the invariants that must hold
+//! for any interleaving are checked:
+//!
+//! + Agreement: no two nodes commit different blocks at the same height, and each node's
+//!   committed blocks form a chain
+//! + No node commits a block unless 2f + 1 nodes sent matching Commit messages for it in a single
+//!   view
+//! + A node's view never decreases
+//!
+//! Failing cases are shrunk by `proptest` to a minimal sequence of actions.
+
+use std::time::Duration;
+
+use proptest::prelude::*;
+
+use super::faults::{FaultModel, Latency, LinkFaults};
+use super::*;
+
+/// Something the model checker makes happen in a simulation
+#[derive(Debug, Clone)]
+enum Action {
+    /// Process the pending event with this position in the queue (modulo the number of events)
+    Step(usize),
+    /// Make the block of the pending BlockValid update with this position in the queue invalid
+    Invalidate(usize),
+    /// Move virtual time forward by this many milliseconds
+    Advance(u64),
+}
+
+fn action() -> impl Strategy<Value = Action> {
+    prop_oneof![
+        20 => any::<usize>().prop_map(Action::Step),
+        1 => any::<usize>().prop_map(Action::Invalidate),
+        2 => (1u64..3_000).prop_map(Action::Advance),
+    ]
+}
+
+/// Configuration with short timeouts and frequent forced view changes, so that the few hundred
+/// actions of a case are enough to go through several views
+fn model_config(seed: u64, faults: FaultModel) -> SimulationConfig {
+    let mut pbft = PbftConfig::default();
+    pbft.block_publishing_delay = Duration::from_millis(100);
+    pbft.idle_timeout = Duration::from_millis(2_000);
+    pbft.commit_timeout = Duration::from_millis(1_000);
+    pbft.view_change_duration = Duration::from_millis(1_000);
+    pbft.forced_view_change_interval = 3;
+
+    SimulationConfig {
+        nodes: 4,
+        seed,
+        faults,
+        pbft,
+    }
+}
+
+/// Perform the actions on a simulation, checking the invariants after each one
+fn check_model(config: SimulationConfig, actions: Vec<Action>) -> Result<(), TestCaseError> {
+    let mut sim = Simulation::new(config);
+    let mut views: Vec<u64> = (0..sim.node_count())
+        .map(|node| sim.state(node).view)
+        .collect();
+
+    for action in actions {
+        let pending = sim.pending_events();
+        match action {
+            Action::Step(index) if pending > 0 => {
+                sim.step_event(index % pending);
+            }
+            Action::Invalidate(index) if pending > 0 => {
+                sim.invalidate_event(index % pending);
+            }
+            Action::Advance(millis) => sim.advance(millis),
+            _ => {}
+        }
+
+        sim.check_safety().map_err(TestCaseError::fail)?;
+        sim.check_commit_quorums().map_err(TestCaseError::fail)?;
+        for (node, view) in views.iter_mut().enumerate() {
+            let current = sim.state(node).view;
+            prop_assert!(
+                current >= *view,
+                "Node {} went from view {} back to view {}",
+                node,
+                view,
+                current
+            );
+            *view = current;
+        }
+    }
+
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    /// Any interleaving of the events of an otherwise reliable 4-node network, with time jumps
+    /// and invalid blocks, must satisfy the invariants.
+    #[test]
+    fn prop_any_interleaving_is_safe(
+        seed in any::<u64>(),
+        actions in prop::collection::vec(action(), 1..400),
+    ) {
+        check_model(model_config(seed, FaultModel::default()), actions)?;
+    }
+
+    /// The invariants must also hold when the network loses, duplicates, and delays messages
+    /// on top of the chosen interleaving.
+    #[test]
+    fn prop_any_interleaving_on_faulty_network_is_safe(
+        seed in any::<u64>(),
+        drop_probability in 0.0f64..0.3,
+        duplicate_probability in 0.0f64..0.3,
+        actions in prop::collection::vec(action(), 1..400),
+    ) {
+        let faults = FaultModel {
+            default_link: LinkFaults {
+                latency: Latency::Uniform { min: 1, max: 200 },
+                drop_probability,
+                duplicate_probability,
+                reorder_probability: 0.0,
+            },
+            ..Default::default()
+        };
+        check_model(model_config(seed, faults), actions)?;
+    }
+}
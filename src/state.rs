@@ -20,11 +20,11 @@
 use std::fmt;
 use std::time::Duration;
 
-use sawtooth_sdk::consensus::engine::{BlockId, PeerId};
-
 use crate::config::PbftConfig;
 use crate::error::PbftError;
-use crate::timing::Timeout;
+use crate::host::{BlockId, PeerId};
+use crate::membership::MemberHistory;
+use crate::timing::{system_clock, ClockRef, Timeout};
 
 /// Phases of the PBFT algorithm, in `Normal` mode
 #[derive(Debug, PartialEq, PartialOrd, Clone, Serialize, Deserialize)]
@@ -107,6 +107,11 @@ pub struct PbftState {
     /// The maximum number of faulty nodes in the network
     pub f: u64,
 
+    /// The members of recently committed blocks, so seals can be verified without asking the
+    /// validator for them again; only the newest few are persisted
+    #[serde(default)]
+    pub member_history: MemberHistory,
+
     /// Timer used to make sure the primary publishes blocks in a timely manner. If not, then this
     /// node will initiate a view change.
     pub idle_timeout: Timeout,
@@ -133,6 +138,11 @@ pub struct PbftState {
 
     /// How many blocks to commit before forcing a view change for fairness
     pub forced_view_change_interval: u64,
+
+    /// Clock used by the node's timeouts; not persisted, so a loaded state uses the system clock
+    /// until it is given another one
+    #[serde(skip, default = "system_clock")]
+    pub clock: ClockRef,
 }
 
 impl PbftState {
@@ -148,6 +158,8 @@ impl PbftState {
             panic!("This network does not contain enough nodes to be fault tolerant");
         }
 
+        let clock = system_clock();
+
         PbftState {
             id,
             seq_num: head_block_num + 1,
@@ -157,16 +169,26 @@ impl PbftState {
             mode: PbftMode::Normal,
             f,
             member_ids: config.members.clone(),
-            idle_timeout: Timeout::new(config.idle_timeout),
-            commit_timeout: Timeout::new(config.commit_timeout),
-            view_change_timeout: Timeout::new(config.view_change_duration),
+            member_history: MemberHistory::new(),
+            idle_timeout: Timeout::new(config.idle_timeout, clock.clone()),
+            commit_timeout: Timeout::new(config.commit_timeout, clock.clone()),
+            view_change_timeout: Timeout::new(config.view_change_duration, clock.clone()),
             view_change_duration: config.view_change_duration,
             exponential_retry_base: config.exponential_retry_base,
             exponential_retry_max: config.exponential_retry_max,
             forced_view_change_interval: config.forced_view_change_interval,
+            clock,
         }
     }
 
+    /// Use the given clock for all of the node's timeouts
+    pub fn set_clock(&mut self, clock: ClockRef) {
+        self.idle_timeout.set_clock(clock.clone());
+        self.commit_timeout.set_clock(clock.clone());
+        self.view_change_timeout.set_clock(clock.clone());
+        self.clock = clock;
+    }
+
     /// Obtain the ID for the primary node in the network
     pub fn get_primary_id(&self) -> PeerId {
         let primary_index = (self.view as usize) % self.member_ids.len();
@@ -225,7 +247,7 @@ impl PbftState {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_helpers::*;
+    use crate::testkit::*;
 
     /// This test will verify that calling `PbftState::new` will properly initialize a state struct
     /// and fail if there are not enough members.
@@ -0,0 +1,494 @@
+/*
+ * Copyright 2019 Cargill Incorporated
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Helpers for writing tests of PBFT nodes
+//!
+//! The helpers create mock blocks, configurations, messages, and networks of signers whose
+//! validly-signed votes can be put into seals and NewView messages. A `MockService` stands in for
+//! the validator: it records every call the node makes and returns configurable results, so tests
+//! can drive a `PbftNode` with `mock_node` and check how it interacted with the validator.
+//!
+//! This module is used by the crate's unit tests and is available to other crates with the
+//! `testkit` feature.
+
+use std::collections::HashMap;
+use std::mem;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+
+use crate::config::PbftConfig;
+use crate::engine;
+use crate::error::PbftError;
+use crate::hash::hash_sha512;
+use crate::host::{Block, BlockId, PeerId};
+use crate::message_type::{ParsedMessage, PbftMessageType};
+use crate::node::PbftNode;
+use crate::protos::pbft_message::{
+    PbftMessage, PbftMessageInfo, PbftNewView, PbftSeal, PbftSignedVote,
+};
+use crate::state::PbftState;
+use hex;
+use protobuf::{Message, RepeatedField};
+use sawtooth_sdk::consensus::engine::{self as sdk, Error, Update};
+use sawtooth_sdk::consensus::service::Service;
+use sawtooth_sdk::messages::consensus::ConsensusPeerMessageHeader;
+use sawtooth_sdk::signing::create_context;
+use sawtooth_sdk::signing::secp256k1::Secp256k1PrivateKey;
+
+/// Create a mock configuration given a number of nodes
+pub fn mock_config(num_nodes: u8) -> PbftConfig {
+    let mut config = PbftConfig::default();
+    config.members = (0..num_nodes).map(|id| vec![id as u8]).collect();
+    config
+}
+
+/// Create a Block for the given block number
+pub fn mock_block(num: u8) -> Block {
+    let previous_id = if num == 0 { vec![] } else { vec![num - 1] };
+
+    Block {
+        block_id: vec![num],
+        previous_id,
+        signer_id: PeerId::from(vec![]),
+        block_num: num as u64,
+        payload: vec![],
+        summary: vec![],
+    }
+}
+
+/// Create a PbftMessage
+pub fn mock_msg(
+    msg_type: PbftMessageType,
+    view: u64,
+    seq_num: u64,
+    signer_id: PeerId,
+    block_id: BlockId,
+    from_self: bool,
+) -> ParsedMessage {
+    let info = PbftMessageInfo::new_from(msg_type, view, seq_num, signer_id);
+    let mut msg = PbftMessage::new();
+    msg.set_info(info);
+    msg.set_block_id(block_id);
+
+    let mut parsed = ParsedMessage::from_pbft_message(msg).expect("Failed to parse PbftMessage");
+    parsed.from_self = from_self;
+    parsed
+}
+
+/// A secp256k1 key pair for a mock signer
+pub struct KeyPair {
+    pub pub_key: Vec<u8>,
+    pub priv_key: Vec<u8>,
+}
+
+/// Create a list of public/private key pairs for the specified number of nodes
+pub fn mock_signer_network(size: u8) -> Vec<KeyPair> {
+    let context = create_context("secp256k1").expect("Failed to create context");
+    (0..size)
+        .map(|_| {
+            let priv_key = context
+                .new_random_private_key()
+                .expect("Failed to generate new private key");
+            let pub_key = context
+                .get_public_key(&*priv_key)
+                .expect("Failed to get public key");
+            KeyPair {
+                pub_key: pub_key.as_slice().to_vec(),
+                priv_key: priv_key.as_slice().to_vec(),
+            }
+        })
+        .collect()
+}
+
+/// Create a mock configuration for the list of signers generated by `mock_signer_network`
+pub fn mock_config_from_signer_network(keys: &[KeyPair]) -> PbftConfig {
+    let mut config = PbftConfig::default();
+    config.members = keys
+        .iter()
+        .map(|key_pair| key_pair.pub_key.clone())
+        .collect();
+    config
+}
+
+/// Create a validly-signed PbftSignedVote
+pub fn mock_vote(
+    msg_type: PbftMessageType,
+    view: u64,
+    seq_num: u64,
+    block_id: BlockId,
+    signer: &KeyPair,
+) -> PbftSignedVote {
+    let info = PbftMessageInfo::new_from(msg_type, view, seq_num, signer.pub_key.clone());
+    let mut msg = PbftMessage::new();
+    msg.set_info(info);
+    msg.set_block_id(block_id);
+    let msg_bytes = msg
+        .write_to_bytes()
+        .expect("Failed to write msg to bytes for mock vote");
+
+    let mut header = ConsensusPeerMessageHeader::new();
+    header.set_signer_id(signer.pub_key.clone());
+    header.set_content_sha512(hash_sha512(&msg_bytes));
+
+    let header_bytes = header
+        .write_to_bytes()
+        .expect("Failed to write header to bytes");
+    let header_signature = hex::decode(
+        create_context("secp256k1")
+            .expect("Failed to create context")
+            .sign(
+                &header_bytes,
+                &Secp256k1PrivateKey::from_hex(&hex::encode(signer.priv_key.clone()))
+                    .expect("Failed to create private key from hex"),
+            )
+            .expect("Failed to sign header"),
+    )
+    .expect("Failed to decode signed header");
+
+    let mut vote = PbftSignedVote::new();
+    vote.set_header_bytes(header_bytes);
+    vote.set_header_signature(header_signature);
+    vote.set_message_bytes(msg_bytes.to_vec());
+    vote
+}
+
+/// Create a PbftSeal
+pub fn mock_seal(
+    view: u64,
+    seq_num: u64,
+    block_id: BlockId,
+    signer: &KeyPair,
+    votes: Vec<PbftSignedVote>,
+) -> PbftSeal {
+    let mut seal = PbftSeal::new();
+    seal.set_info(PbftMessageInfo::new_from(
+        PbftMessageType::Seal,
+        view,
+        seq_num,
+        signer.pub_key.clone(),
+    ));
+    seal.set_block_id(block_id);
+    seal.set_commit_votes(RepeatedField::from(votes));
+    seal
+}
+
+/// Turns a series of items into a `Vec<String>` for easily tracking and checking for function
+/// calls to the `MockService`
+#[macro_export]
+macro_rules! stringify_func_call {
+    ( $( $x:expr ),* ) => {
+        {
+            let mut output = Vec::new();
+            $(
+                output.push(format!("{:?}", $x));
+            )*
+            output
+        }
+    }
+}
+
+/// Implementation of the consensus' `Service` trait that's used to mock out interactions with
+/// the Sawtooth validator. The `MockService` will track calls to its methods and supports
+/// configurable return values for some of its methods. Clones share the calls and return values,
+/// so a test can keep a clone to check on a service that was moved into a node on another thread.
+#[derive(Clone)]
+pub struct MockService {
+    /// A list of function calls, where each function call is a list of the form (func_name,
+    /// arg1, arg2, ...)
+    calls: Arc<Mutex<Vec<Vec<String>>>>,
+    /// For each block ID, the settings value to return when `get_settings` is called
+    settings: Arc<Mutex<HashMap<BlockId, HashMap<String, String>>>>,
+    /// The blocks that `get_blocks` can return
+    blocks: Arc<Mutex<HashMap<BlockId, Block>>>,
+    /// Determines the return value of the `summarize_block` method
+    summarize_block_return_val: Arc<Mutex<Result<Vec<u8>, Error>>>,
+    /// Whether `get_settings` fails, as if the validator couldn't be reached
+    settings_unavailable: Arc<Mutex<bool>>,
+}
+
+impl MockService {
+    /// Create a new `MockService` and set the members setting based on the `PbftConfig`
+    pub fn new(cfg: &PbftConfig) -> Self {
+        let members: Vec<_> = cfg.members.iter().map(hex::encode).collect();
+        let service = MockService {
+            calls: Default::default(),
+            settings: Default::default(),
+            blocks: Default::default(),
+            summarize_block_return_val: Arc::new(Mutex::new(Ok(Default::default()))),
+            settings_unavailable: Default::default(),
+        };
+        // Set the default settings
+        let mut default_settings = HashMap::new();
+        default_settings.insert(
+            "sawtooth.consensus.pbft.members".to_string(),
+            serde_json::to_string(&members).unwrap(),
+        );
+        service
+            .settings
+            .lock()
+            .unwrap()
+            .insert(vec![0], default_settings);
+
+        service
+    }
+
+    /// Indicates if the specified method was called
+    pub fn was_called(&self, method_name: &str) -> bool {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|call| call[0] == format!("{:?}", method_name))
+    }
+
+    /// Indicates if the specified method was called with the given arguments (allows partial
+    /// args)
+    pub fn was_called_with_args(&self, call: Vec<String>) -> bool {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|logged_call| logged_call.starts_with(&call))
+    }
+
+    /// Indicates if the specified method was called with the given arguments only once (allows
+    /// partial args)
+    pub fn was_called_with_args_once(&self, call: Vec<String>) -> bool {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|logged_call| logged_call.starts_with(&call))
+            .count()
+            == 1
+    }
+
+    /// All calls made so far, in order, each in the form (func_name, arg1, arg2, ...)
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Set the settings that `get_settings` returns for the given block; blocks without their
+    /// own settings fall back to the settings of block `[0]`
+    pub fn set_settings(&self, block_id: BlockId, settings: HashMap<String, String>) {
+        self.settings.lock().unwrap().insert(block_id, settings);
+    }
+
+    /// Make `get_settings` fail (or succeed again), as if the validator couldn't be reached
+    pub fn set_settings_unavailable(&self, unavailable: bool) {
+        *self.settings_unavailable.lock().unwrap() = unavailable;
+    }
+
+    /// Make a block available to `get_blocks`
+    pub fn add_block(&self, block: Block) {
+        self.blocks
+            .lock()
+            .unwrap()
+            .insert(block.block_id.clone(), block);
+    }
+
+    /// Set the result of the next call to `summarize_block`; later calls return `Ok` again
+    pub fn set_summarize_block_result(&self, result: Result<Vec<u8>, Error>) {
+        *self.summarize_block_return_val.lock().unwrap() = result;
+    }
+}
+
+impl Service for MockService {
+    fn send_to(
+        &mut self,
+        peer: &PeerId,
+        message_type: &str,
+        payload: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.calls.lock().unwrap().push(stringify_func_call!(
+            "send_to",
+            peer,
+            message_type,
+            payload
+        ));
+        Ok(())
+    }
+    fn broadcast(&mut self, message_type: &str, payload: Vec<u8>) -> Result<(), Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("broadcast", message_type, payload));
+        Ok(())
+    }
+    fn initialize_block(&mut self, previous_id: Option<BlockId>) -> Result<(), Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("initialize_block", previous_id));
+        Ok(())
+    }
+    fn summarize_block(&mut self) -> Result<Vec<u8>, Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("summarize_block"));
+        mem::replace(
+            &mut *self.summarize_block_return_val.lock().unwrap(),
+            Ok(Default::default()),
+        )
+    }
+    fn finalize_block(&mut self, data: Vec<u8>) -> Result<BlockId, Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("finalize_block", data));
+        Ok(Default::default())
+    }
+    fn cancel_block(&mut self) -> Result<(), Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("cancel_block"));
+        Ok(())
+    }
+    fn check_blocks(&mut self, priority: Vec<BlockId>) -> Result<(), Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("check_blocks", priority));
+        Ok(())
+    }
+    fn commit_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("commit_block", block_id));
+        Ok(())
+    }
+    fn ignore_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("ignore_block", block_id));
+        Ok(())
+    }
+    fn fail_block(&mut self, block_id: BlockId) -> Result<(), Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("fail_block", block_id));
+        Ok(())
+    }
+    fn get_blocks(
+        &mut self,
+        block_ids: Vec<BlockId>,
+    ) -> Result<HashMap<BlockId, sdk::Block>, Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("get_blocks", block_ids));
+        let blocks = self.blocks.lock().unwrap();
+        Ok(block_ids
+            .into_iter()
+            .filter_map(|id| blocks.get(&id).cloned().map(|block| (id, block.into())))
+            .collect())
+    }
+    fn get_chain_head(&mut self) -> Result<sdk::Block, Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("get_chain_head"));
+        Ok(Default::default())
+    }
+    fn get_settings(
+        &mut self,
+        block_id: BlockId,
+        settings: Vec<String>,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("get_settings", block_id, settings));
+        if *self.settings_unavailable.lock().unwrap() {
+            return Err(Error::ReceiveError("Validator is unavailable".into()));
+        }
+        let settings = self.settings.lock().unwrap();
+        Ok(settings
+            .get(&block_id)
+            .unwrap_or_else(|| {
+                // Fall back to defualt settings (in block 0)
+                settings.get(&vec![0]).expect("Default settings not set")
+            })
+            .clone())
+    }
+    fn get_state(
+        &mut self,
+        block_id: BlockId,
+        addresses: Vec<String>,
+    ) -> Result<HashMap<String, Vec<u8>>, Error> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(stringify_func_call!("get_state", block_id, addresses));
+        Ok(Default::default())
+    }
+}
+
+/// Create a new PbftNode, PbftState, and MockService based on the given config, node ID, and
+/// chain head
+pub fn mock_node(
+    cfg: &PbftConfig,
+    node_id: PeerId,
+    chain_head: Block,
+) -> (PbftNode, PbftState, MockService) {
+    let mut state = PbftState::new(node_id.clone(), chain_head.block_num, cfg);
+    let service = MockService::new(cfg);
+    (
+        PbftNode::new(
+            cfg,
+            chain_head.into(),
+            vec![],
+            Box::new(service.clone()),
+            &mut state,
+        ),
+        state,
+        service,
+    )
+}
+
+/// Create a PbftNewView
+pub fn mock_new_view(
+    view: u64,
+    seq_num: u64,
+    signer: &KeyPair,
+    votes: Vec<PbftSignedVote>,
+) -> PbftNewView {
+    let mut new_view = PbftNewView::new();
+    new_view.set_info(PbftMessageInfo::new_from(
+        PbftMessageType::NewView,
+        view,
+        seq_num,
+        signer.pub_key.clone(),
+    ));
+    new_view.set_view_changes(RepeatedField::from(votes));
+    new_view
+}
+
+/// Handle an update (or the lack of one) the way the engine does, so tests and fuzz targets can
+/// drive a node without running an engine; returns `false` once the engine would stop
+pub fn handle_update(
+    node: &mut PbftNode,
+    incoming_message: Result<Update, RecvTimeoutError>,
+    state: &mut PbftState,
+) -> Result<bool, PbftError> {
+    engine::handle_update(node, incoming_message, state)
+}
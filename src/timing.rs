@@ -17,33 +17,148 @@
 
 //! Timing-related structures
 
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
 use serde_millis;
 
+/// Source of the current time for timeouts, tickers, and retries
+///
+/// Everything time-related in the engine goes through a `Clock` rather than calling
+/// `Instant::now()` and `thread::sleep()` directly, so tests can control the passage of time.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Wait for the given amount of time to pass
+    fn sleep(&self, duration: Duration);
+
+    /// How long to block, in real time, to wait for the clock to reach the given time
+    fn time_until(&self, deadline: Instant) -> Duration {
+        let now = self.now();
+        if deadline > now {
+            deadline - now
+        } else {
+            Duration::from_millis(0)
+        }
+    }
+}
+
+/// Shared handle to a clock
+pub type ClockRef = Arc<dyn Clock>;
+
+/// The real clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        sleep(duration)
+    }
+}
+
+/// Get a handle to the real clock
+pub fn system_clock() -> ClockRef {
+    Arc::new(SystemClock)
+}
+
+/// How long to block, in real time, when waiting for a `ManualClock` to reach a deadline
+const MANUAL_CLOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A clock that only moves forward when it is told to
+///
+/// Sleeping advances the clock by the given duration instead of blocking. Clones share the same
+/// time, so a test can keep a clone to advance the clock used by the engine.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::from_millis(0))),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().expect("Manual clock lock poisoned") += duration;
+    }
+
+    /// How far the clock has been advanced since it was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().expect("Manual clock lock poisoned")
+    }
+
+    /// Advance the clock until the given time has elapsed since it was created, unless it is
+    /// already past that time
+    pub fn advance_to(&self, elapsed: Duration) {
+        let mut current = self.elapsed.lock().expect("Manual clock lock poisoned");
+        if elapsed > *current {
+            *current = elapsed;
+        }
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+
+    /// The clock only reaches a deadline when another thread advances it, which can happen at
+    /// any moment, so waiting for it only blocks briefly before checking again
+    fn time_until(&self, _deadline: Instant) -> Duration {
+        MANUAL_CLOCK_POLL_INTERVAL
+    }
+}
+
 /// Encapsulates calling a function every so often
 pub struct Ticker {
     last: Instant,
     timeout: Duration,
+    clock: ClockRef,
 }
 
 impl Ticker {
-    pub fn new(period: Duration) -> Self {
+    pub fn new(period: Duration, clock: ClockRef) -> Self {
         Ticker {
-            last: Instant::now(),
+            last: clock.now(),
             timeout: period,
+            clock,
         }
     }
 
     // Do some work if the timeout has expired
     pub fn tick<T: FnMut()>(&mut self, mut callback: T) {
-        let elapsed = Instant::now() - self.last;
+        let elapsed = self.clock.now() - self.last;
         if elapsed >= self.timeout {
             callback();
-            self.last = Instant::now();
+            self.last = self.clock.now();
         }
     }
+
+    /// The time at which the next call to `tick` will do the work
+    pub fn next_tick(&self) -> Instant {
+        self.last + self.timeout
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -61,20 +176,25 @@ pub struct Timeout {
     duration: Duration,
     #[serde(with = "serde_millis")]
     start: Instant,
+    /// Not persisted; a loaded timeout uses the system clock until it is given another one
+    #[serde(skip, default = "system_clock")]
+    clock: ClockRef,
 }
 
 impl Timeout {
-    pub fn new(duration: Duration) -> Self {
+    pub fn new(duration: Duration, clock: ClockRef) -> Self {
         Timeout {
             state: TimeoutState::Inactive,
             duration,
-            start: Instant::now(),
+            start: clock.now(),
+            clock,
         }
     }
 
-    /// Update the timer state, and check if the timer is expired
+    /// Update the timer state, and check if the timer is expired; like a `Backoff`, the timer is
+    /// expired as soon as its deadline is reached
     pub fn check_expired(&mut self) -> bool {
-        if self.state == TimeoutState::Active && Instant::now() - self.start > self.duration {
+        if self.state == TimeoutState::Active && self.clock.now() - self.start >= self.duration {
             self.state = TimeoutState::Expired;
         }
         match self.state {
@@ -85,12 +205,16 @@ impl Timeout {
 
     pub fn start(&mut self) {
         self.state = TimeoutState::Active;
-        self.start = Instant::now();
+        self.start = self.clock.now();
     }
 
     pub fn stop(&mut self) {
         self.state = TimeoutState::Inactive;
-        self.start = Instant::now();
+        self.start = self.clock.now();
+    }
+
+    pub fn set_clock(&mut self, clock: ClockRef) {
+        self.clock = clock;
     }
 
     #[cfg(test)]
@@ -101,10 +225,60 @@ impl Timeout {
     pub fn is_active(&self) -> bool {
         self.state == TimeoutState::Active
     }
+
+    /// The time at which the timer expires, if it is running; `check_expired` reports the expiry
+    /// once this time is reached
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.is_active() {
+            Some(self.start + self.duration)
+        } else {
+            None
+        }
+    }
+}
+
+/// Schedule for retrying something with exponential backoff without blocking: the first attempt
+/// is due right away, and after each failed attempt the next one is due after a delay that
+/// doubles each time (up to a maximum)
+#[derive(Debug)]
+pub struct Backoff {
+    delay: Duration,
+    max: Duration,
+    next_attempt: Instant,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration, now: Instant) -> Self {
+        Backoff {
+            delay: base,
+            max,
+            next_attempt: now,
+        }
+    }
+
+    /// Tell if it's time for the next attempt
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next_attempt
+    }
+
+    /// The time at which the next attempt is due
+    pub fn next_attempt(&self) -> Instant {
+        self.next_attempt
+    }
+
+    /// Schedule the next attempt after an attempt made at the given time failed
+    pub fn failed(&mut self, now: Instant) {
+        self.next_attempt = now + self.delay;
+        self.delay = self.delay.checked_mul(2).unwrap_or(self.max).min(self.max);
+    }
 }
 
 /// With exponential backoff, repeatedly try the callback until the result is `Ok`
+///
+/// This blocks until the callback succeeds, so it is only used before the engine starts handling
+/// updates; once it has, retries are scheduled with a `Backoff` instead.
 pub fn retry_until_ok<T, E, F: FnMut() -> Result<T, E>>(
+    clock: &dyn Clock,
     base: Duration,
     max: Duration,
     mut callback: F,
@@ -114,7 +288,7 @@ pub fn retry_until_ok<T, E, F: FnMut() -> Result<T, E>>(
         match callback() {
             Ok(res) => return res,
             Err(_) => {
-                sleep(delay);
+                clock.sleep(delay);
                 // Only increase delay if it's less than the max
                 if delay < max {
                     delay = delay
@@ -134,52 +308,65 @@ pub fn retry_until_ok<T, E, F: FnMut() -> Result<T, E>>(
 mod tests {
     use super::*;
 
-    macro_rules! assert_tolerance {
-        ($val1:expr, $val2:expr, $tol:expr) => {
-            if $val2 > $val1 && $val2 - $val1 > $tol {
-                panic!(
-                    "Value is not within tolerance ({:?} - {:?} > {:?})",
-                    $val2, $val1, $tol
-                );
-            }
-            if $val1 > $val2 && $val1 - $val2 > $tol {
-                panic!(
-                    "Value is not within tolerance ({:?} - {:?} > {:?})",
-                    $val1, $val2, $tol
-                );
-            }
-        };
+    /// Tell the ticker to wait for 100ms, and make sure it only calls back once 100ms have passed
+    /// on its clock
+    #[test]
+    fn ticker() {
+        let clock = ManualClock::new();
+        let mut t = Ticker::new(Duration::from_millis(100), Arc::new(clock.clone()));
+        let mut ticks = 0;
+
+        clock.advance(Duration::from_millis(99));
+        t.tick(|| ticks += 1);
+        assert_eq!(0, ticks);
+
+        clock.advance(Duration::from_millis(1));
+        t.tick(|| ticks += 1);
+        assert_eq!(1, ticks);
+
+        // The period starts over after each tick
+        clock.advance(Duration::from_millis(50));
+        t.tick(|| ticks += 1);
+        assert_eq!(1, ticks);
+        clock.advance(Duration::from_millis(50));
+        t.tick(|| ticks += 1);
+        assert_eq!(2, ticks);
     }
 
-    /// Tell the ticker to wait for 100ms, then see if it actually waited 100 +/- 5ms
+    /// The ticker's next tick must be a full period after its last one, and the ticker must do
+    /// its work once its clock reaches that time
     #[test]
-    fn ticker() {
-        let time = Duration::from_millis(100);
-        let mut t = Ticker::new(time);
-        let start_time = Instant::now();
-        let mut end_time = Instant::now();
-        let mut triggered = false;
-        while !triggered {
-            t.tick(|| {
-                end_time = Instant::now();
-                triggered = true;
-            })
-        }
-        assert_tolerance!(end_time - start_time, time, Duration::from_millis(5));
+    fn ticker_next_tick() {
+        let clock = ManualClock::new();
+        let mut t = Ticker::new(Duration::from_millis(100), Arc::new(clock.clone()));
+        assert_eq!(clock.now() + Duration::from_millis(100), t.next_tick());
+
+        clock.advance(Duration::from_millis(150));
+        assert!(t.next_tick() < clock.now());
+        let mut ticks = 0;
+        t.tick(|| ticks += 1);
+        assert_eq!(1, ticks);
+        assert_eq!(clock.now() + Duration::from_millis(100), t.next_tick());
     }
 
-    /// Create a Timeout that lasts for 100ms and check that it expires anytime after 100ms have
-    /// passed. Check whether `.start()` and `.stop()` work as expected.
+    /// Create a Timeout that lasts for 100ms and check that it expires as soon as 100ms have
+    /// passed on its clock. Check whether `.start()` and `.stop()` work as expected.
     #[test]
     fn timeout() {
-        let start_time = Instant::now();
-        let mut t = Timeout::new(Duration::from_millis(100));
+        let clock = ManualClock::new();
+        let mut t = Timeout::new(Duration::from_millis(100), Arc::new(clock.clone()));
         assert_eq!(t.state, TimeoutState::Inactive);
-        assert_tolerance!(t.start, start_time, Duration::from_millis(1));
+        assert_eq!(t.start, clock.now());
+
+        // An inactive timeout never expires
+        clock.advance(Duration::from_millis(200));
+        assert!(!t.check_expired());
 
         t.start();
         assert_eq!(t.state, TimeoutState::Active);
-        ::std::thread::sleep(Duration::from_millis(110));
+        clock.advance(Duration::from_millis(99));
+        assert!(!t.check_expired());
+        clock.advance(Duration::from_millis(1));
 
         assert!(t.check_expired());
         assert_eq!(t.state, TimeoutState::Expired);
@@ -188,21 +375,95 @@ mod tests {
         assert_eq!(t.state, TimeoutState::Inactive);
     }
 
+    /// A timeout must only have a deadline while it is running, and must expire once its clock
+    /// reaches the deadline
+    #[test]
+    fn timeout_deadline() {
+        let clock = ManualClock::new();
+        let mut t = Timeout::new(Duration::from_millis(100), Arc::new(clock.clone()));
+        assert_eq!(None, t.deadline());
+
+        clock.advance(Duration::from_millis(10));
+        t.start();
+        let deadline = t.deadline().expect("Running timeout has no deadline");
+        assert_eq!(clock.now() + Duration::from_millis(100), deadline);
+
+        clock.advance(Duration::from_millis(99));
+        assert!(!t.check_expired());
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(clock.now(), deadline);
+        assert!(t.check_expired());
+        assert_eq!(None, t.deadline());
+
+        t.start();
+        t.stop();
+        assert_eq!(None, t.deadline());
+    }
+
     /// Retry a function that fails three times and succeeds on the 4th try with the
-    /// `retry_until_ok` method, a 10ms base, and 20ms max; the total time should be 50ms.
+    /// `retry_until_ok` method, a 10ms base, and 20ms max; the total time waited should be 50ms.
     #[test]
     fn retry() {
-        let start_time = Instant::now();
+        let clock = ManualClock::new();
         let vec = vec![Err(()), Err(()), Err(()), Ok(())];
         let mut iter = vec.iter().cloned();
-        retry_until_ok(Duration::from_millis(10), Duration::from_millis(20), || {
-            iter.next().unwrap()
-        });
-        let end_time = Instant::now();
-        assert_tolerance!(
-            end_time - start_time,
-            Duration::from_millis(50),
-            Duration::from_millis(5)
+        retry_until_ok(
+            &clock,
+            Duration::from_millis(10),
+            Duration::from_millis(20),
+            || iter.next().unwrap(),
+        );
+        assert_eq!(Duration::from_millis(50), clock.elapsed());
+    }
+
+    /// Schedule retries with a 10ms base and 40ms max; after each failure, the next attempt must
+    /// be due 10ms, 20ms, 40ms, and then 40ms after the failure.
+    #[test]
+    fn backoff() {
+        let clock = ManualClock::new();
+        let mut backoff = Backoff::new(
+            Duration::from_millis(10),
+            Duration::from_millis(40),
+            clock.now(),
+        );
+        assert!(backoff.is_due(clock.now()));
+
+        for delay in &[10, 20, 40, 40] {
+            backoff.failed(clock.now());
+            clock.advance(Duration::from_millis(delay - 1));
+            assert!(!backoff.is_due(clock.now()));
+            clock.advance(Duration::from_millis(1));
+            assert!(backoff.is_due(clock.now()));
+        }
+    }
+
+    /// The system clock must actually wait when sleeping
+    #[test]
+    fn system_clock_sleeps() {
+        let clock = SystemClock;
+        let start = clock.now();
+        clock.sleep(Duration::from_millis(10));
+        assert!(clock.now() - start >= Duration::from_millis(10));
+    }
+
+    /// Waiting for a deadline on the system clock must block until the deadline and not at all
+    /// for a deadline that has passed; waiting on a manual clock must only block briefly, since
+    /// the clock can be advanced at any moment.
+    #[test]
+    fn time_until_deadline() {
+        let clock = SystemClock;
+        let deadline = clock.now() + Duration::from_secs(60);
+        let wait = clock.time_until(deadline);
+        assert!(wait <= Duration::from_secs(60) && wait > Duration::from_secs(59));
+        assert_eq!(
+            Duration::from_millis(0),
+            clock.time_until(clock.now() - Duration::from_millis(1))
+        );
+
+        let manual = ManualClock::new();
+        assert_eq!(
+            MANUAL_CLOCK_POLL_INTERVAL,
+            manual.time_until(manual.now() + Duration::from_secs(60))
         );
     }
 }
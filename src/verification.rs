@@ -0,0 +1,971 @@
+/*
+ * Copyright 2019 Bitwise IO, Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Verification of signed votes and consensus seals
+//!
+//! Nothing in this module talks to a validator; seals can be verified offline as long as the list
+//! of members they must be verified against is known.
+//!
+//! Checking signatures is by far the most expensive part of verifying a seal or a NewView, so the
+//! signatures of all of their votes are checked at once (see `AuthenticateVotes`); the Sawtooth
+//! host checks them in parallel on rayon's worker pool. The rest of the checks are made
+//! for each vote in order, so the problem reported for an invalid seal or NewView is the same
+//! as if the votes were checked one at a time.
+
+use std::collections::HashSet;
+
+use hex;
+
+use crate::error::PbftError;
+use crate::host::{PeerId, SawtoothHost};
+use crate::protos::pbft_message::{PbftMessage, PbftSeal, PbftSignedVote};
+use crate::signature::VoteVerifier;
+
+/// The type of vote that `verify_vote` expects
+pub use crate::message_type::PbftMessageType;
+
+/// A function that checks that a vote was signed by the member its header names and that the
+/// header covers the vote's message; returns the ID of the vote's signer
+///
+/// How votes are signed depends on the host (see the `host` module);
+/// `SawtoothHost::authenticate_vote_with` checks votes the way a Sawtooth validator signs its peer
+/// messages.
+pub type AuthenticateVote = dyn Fn(&PbftSignedVote) -> Result<PeerId, PbftError>;
+
+/// A function that authenticates each of the given votes like an `AuthenticateVote` does,
+/// returning the result for each vote in the same order as the votes; it may stop after the first
+/// vote that fails, so no more signatures are checked than needed
+pub type AuthenticateVotes = dyn Fn(&[PbftSignedVote]) -> Vec<Result<PeerId, PbftError>>;
+
+/// Verify that a vote matches the expected type, is properly signed, and passes the specified
+/// criteria; if it passes verification, return the signer ID to be used for further
+/// verification
+///
+/// The vote must be signed the way a Sawtooth validator signs peer messages, with the signature
+/// scheme of the given verifier (see `SignatureScheme::verifier`); use `verify_vote_with` for
+/// votes from other hosts.
+pub fn verify_vote<F>(
+    vote: &PbftSignedVote,
+    expected_type: PbftMessageType,
+    verifier: &dyn VoteVerifier,
+    validation_criteria: F,
+) -> Result<PeerId, PbftError>
+where
+    F: Fn(&PbftMessage) -> Result<(), PbftError>,
+{
+    verify_vote_with(
+        vote,
+        expected_type,
+        &|vote| SawtoothHost::authenticate_vote_with(vote, verifier),
+        validation_criteria,
+    )
+}
+
+/// Verify a vote like `verify_vote` does, checking its signature with the given function
+pub fn verify_vote_with<F>(
+    vote: &PbftSignedVote,
+    expected_type: PbftMessageType,
+    authenticate: &AuthenticateVote,
+    validation_criteria: F,
+) -> Result<PeerId, PbftError>
+where
+    F: Fn(&PbftMessage) -> Result<(), PbftError>,
+{
+    let pbft_message = parse_vote_message(vote, expected_type)?;
+    check_authenticated_vote(&pbft_message, authenticate(vote), &validation_criteria)
+}
+
+/// Verify each of the votes like `verify_vote_with` does, authenticating all of them at once with
+/// the given function; return the distinct IDs of the votes' signers if they are all valid, or
+/// the problem with the first invalid vote
+///
+/// The checks that don't need a vote's signature are made for all of the votes first, so no
+/// signatures are checked for votes that come after one that fails them. Several votes from the
+/// same signer only count once, so a signer can't stand in for other members by voting more than
+/// once.
+pub fn verify_votes_with<F>(
+    votes: &[PbftSignedVote],
+    expected_type: PbftMessageType,
+    authenticate: &AuthenticateVotes,
+    validation_criteria: F,
+) -> Result<HashSet<PeerId>, PbftError>
+where
+    F: Fn(&PbftMessage) -> Result<(), PbftError>,
+{
+    let mut messages = Vec::with_capacity(votes.len());
+    let mut first_invalid = None;
+    for vote in votes {
+        match parse_vote_message(vote, expected_type) {
+            Ok(pbft_message) => messages.push(pbft_message),
+            Err(err) => {
+                first_invalid = Some(err);
+                break;
+            }
+        }
+    }
+
+    let mut ids = HashSet::new();
+    for (pbft_message, signer_id) in messages
+        .iter()
+        .zip(authenticate_all(&votes[..messages.len()], authenticate)?)
+    {
+        ids.insert(check_authenticated_vote(
+            pbft_message,
+            signer_id,
+            &validation_criteria,
+        )?);
+    }
+
+    match first_invalid {
+        Some(err) => Err(err),
+        None => Ok(ids),
+    }
+}
+
+/// Authenticate the votes, making sure there is a result for each of them up to the first one
+/// that fails
+fn authenticate_all(
+    votes: &[PbftSignedVote],
+    authenticate: &AuthenticateVotes,
+) -> Result<Vec<Result<PeerId, PbftError>>, PbftError> {
+    let signer_ids = authenticate(votes);
+    let stopped_at_failure = signer_ids.last().map_or(false, Result::is_err);
+    if signer_ids.len() > votes.len() || (signer_ids.len() < votes.len() && !stopped_at_failure) {
+        return Err(PbftError::InternalError(format!(
+            "Authenticated {} votes, but {} were given",
+            signer_ids.len(),
+            votes.len()
+        )));
+    }
+    Ok(signer_ids)
+}
+
+/// Authenticate every one of the votes, even those after a vote that fails
+fn authenticate_each(
+    votes: &[PbftSignedVote],
+    authenticate: &AuthenticateVotes,
+) -> Result<Vec<Result<PeerId, PbftError>>, PbftError> {
+    let mut signer_ids = Vec::with_capacity(votes.len());
+    while signer_ids.len() < votes.len() {
+        let remaining = authenticate_all(&votes[signer_ids.len()..], authenticate)?;
+        if remaining.is_empty() {
+            return Err(PbftError::InternalError(format!(
+                "Authenticated {} votes, but {} were given",
+                signer_ids.len(),
+                votes.len()
+            )));
+        }
+        signer_ids.extend(remaining);
+    }
+    Ok(signer_ids)
+}
+
+/// Make the checks of a vote that don't need its signature: parse its message and check that it
+/// has the expected type
+fn parse_vote_message(
+    vote: &PbftSignedVote,
+    expected_type: PbftMessageType,
+) -> Result<PbftMessage, PbftError> {
+    let pbft_message: PbftMessage =
+        protobuf::parse_from_bytes(&vote.get_message_bytes()).map_err(|err| {
+            PbftError::SerializationError("Error parsing PbftMessage from vote".into(), err)
+        })?;
+
+    trace!("Verifying vote with PbftMessage: {:?}", pbft_message);
+
+    // Verify the message type
+    let msg_type = PbftMessageType::from(pbft_message.get_info().get_msg_type());
+    if msg_type != expected_type {
+        return Err(PbftError::InvalidMessage(format!(
+            "Received a {:?} vote, but expected a {:?}",
+            msg_type, expected_type
+        )));
+    }
+
+    Ok(pbft_message)
+}
+
+/// Finish verifying a vote once its signature has been checked and its signer ID (or the problem
+/// with its signature) is known
+fn check_authenticated_vote<F>(
+    pbft_message: &PbftMessage,
+    signer_id: Result<PeerId, PbftError>,
+    validation_criteria: &F,
+) -> Result<PeerId, PbftError>
+where
+    F: Fn(&PbftMessage) -> Result<(), PbftError>,
+{
+    // Verify the signature
+    let signer_id = signer_id?;
+
+    // Verify the header's signer matches the PbftMessage's signer
+    if signer_id.as_slice() != pbft_message.get_info().get_signer_id() {
+        return Err(PbftError::InvalidMessage(format!(
+            "Received a vote where PbftMessage's signer ID ({:?}) and PeerMessage's signer ID \
+             ({:?}) don't match",
+            pbft_message.get_info().get_signer_id(),
+            signer_id
+        )));
+    }
+
+    // Validate against the specified criteria
+    validation_criteria(pbft_message)?;
+
+    Ok(signer_id)
+}
+
+/// Make sure there aren't more votes than members; no signatures need to be checked to reject a
+/// list that can't be valid
+pub fn check_vote_count(votes: &[PbftSignedVote], members: &[PeerId]) -> Result<(), PbftError> {
+    if votes.len() > members.len() {
+        return Err(PbftError::InvalidMessage(format!(
+            "Received {} votes, but there are only {} members",
+            votes.len(),
+            members.len()
+        )));
+    }
+    Ok(())
+}
+
+/// The maximum number of faulty nodes that a network with the given number of members tolerates
+pub fn max_faulty_nodes(num_members: usize) -> u64 {
+    (num_members.saturating_sub(1) / 3) as u64
+}
+
+/// Check that a Commit vote from the given seal is for the same block, view, and sequence number
+/// as the seal
+fn check_seal_vote(seal: &PbftSeal, msg: &PbftMessage) -> Result<(), PbftError> {
+    // Make sure all votes are for the right block
+    if msg.block_id != seal.block_id {
+        return Err(PbftError::InvalidMessage(format!(
+            "Commit vote's block ID ({:?}) doesn't match seal's ID ({:?})",
+            msg.block_id, seal.block_id
+        )));
+    }
+    // Make sure all votes are for the right view
+    if msg.get_info().get_view() != seal.get_info().get_view() {
+        return Err(PbftError::InvalidMessage(format!(
+            "Commit vote's view ({:?}) doesn't match seal's view ({:?})",
+            msg.get_info().get_view(),
+            seal.get_info().get_view()
+        )));
+    }
+    // Make sure all votes are for the right sequence number
+    if msg.get_info().get_seq_num() != seal.get_info().get_seq_num() {
+        return Err(PbftError::InvalidMessage(format!(
+            "Commit vote's seq_num ({:?}) doesn't match seal's seq_num ({:?})",
+            msg.get_info().get_seq_num(),
+            seal.get_info().get_seq_num()
+        )));
+    }
+    Ok(())
+}
+
+/// Verify that a seal's signer is a member and that its voters are at least 2f members other than
+/// the signer
+fn verify_seal_voters(
+    seal: &PbftSeal,
+    voter_ids: &HashSet<PeerId>,
+    members: &[PeerId],
+    f: u64,
+) -> Result<(), PbftError> {
+    // Verify that the seal's signer is a PBFT member
+    if !members.contains(&seal.get_info().get_signer_id().to_vec()) {
+        return Err(PbftError::InvalidMessage(format!(
+            "Consensus seal is signed by an unknown peer: {:?}",
+            seal.get_info().get_signer_id()
+        )));
+    }
+
+    // All of the votes in a seal must come from PBFT members, and the primary can't explicitly
+    // vote itself, since building a consensus seal is an implicit vote. Check that the votes
+    // received are from a subset of "members - seal creator".
+    let peer_ids: HashSet<_> = members
+        .iter()
+        .cloned()
+        .filter(|pid| pid.as_slice() != seal.get_info().get_signer_id())
+        .collect();
+
+    trace!(
+        "Comparing voter IDs ({:?}) with member IDs - primary ({:?})",
+        voter_ids,
+        peer_ids
+    );
+
+    if !voter_ids.is_subset(&peer_ids) {
+        return Err(PbftError::InvalidMessage(format!(
+            "Consensus seal contains vote(s) from invalid ID(s): {:?}",
+            voter_ids.difference(&peer_ids).collect::<Vec<_>>()
+        )));
+    }
+
+    // Check that the seal contains 2f votes (primary vote is implicit, so total of 2f + 1)
+    if (voter_ids.len() as u64) < 2 * f {
+        return Err(PbftError::InvalidMessage(format!(
+            "Consensus seal needs {} votes, but only {} found",
+            2 * f,
+            voter_ids.len()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Verify a consensus seal against the members of the network at the time the sealed block was
+/// voted on, returning the first problem found; if the seal is valid, return the IDs of the
+/// members with a vote in it
+///
+/// The seal's votes must be signed the way a Sawtooth validator signs peer messages, with the
+/// signature scheme of the given verifier; use `verify_seal_with` for seals from other hosts.
+pub fn verify_seal(
+    seal: &PbftSeal,
+    members: &[PeerId],
+    f: u64,
+    verifier: &dyn VoteVerifier,
+) -> Result<HashSet<PeerId>, PbftError> {
+    verify_seal_with(seal, members, f, &|votes| {
+        SawtoothHost::authenticate_votes_with(votes, verifier)
+    })
+}
+
+/// Verify a consensus seal like `verify_seal` does, checking the signatures of its votes with the
+/// given function
+pub fn verify_seal_with(
+    seal: &PbftSeal,
+    members: &[PeerId],
+    f: u64,
+    authenticate: &AuthenticateVotes,
+) -> Result<HashSet<PeerId>, PbftError> {
+    check_vote_count(seal.get_commit_votes(), members)?;
+
+    // Verify each individual vote and extract the signer ID from each PbftMessage so the IDs can
+    // be verified
+    let voter_ids = verify_votes_with(
+        seal.get_commit_votes(),
+        PbftMessageType::Commit,
+        authenticate,
+        |msg| check_seal_vote(seal, msg),
+    )?;
+
+    verify_seal_voters(seal, &voter_ids, members, f)?;
+
+    Ok(voter_ids)
+}
+
+/// The outcome of verifying a single vote in a seal
+#[derive(Debug, Serialize)]
+pub struct VoteVerdict {
+    /// Hex-encoded ID of the vote's signer, if the vote's message could be parsed
+    pub signer_id: Option<String>,
+    /// Why the vote is invalid, if it is
+    pub error: Option<String>,
+}
+
+/// A detailed report on whether a seal proves that a block was committed
+#[derive(Debug, Serialize)]
+pub struct SealVerdict {
+    /// Whether the seal proves the block
+    pub valid: bool,
+    /// Hex-encoded ID of the block the seal is supposed to prove
+    pub block_id: String,
+    /// Hex-encoded ID of the proven block's predecessor, whose member list the seal was checked
+    /// against (if it was provided)
+    pub previous_id: Option<String>,
+    /// Hex-encoded ID of the block the seal is actually for
+    pub seal_block_id: String,
+    /// Hex-encoded ID of the node that built the seal
+    pub seal_signer_id: String,
+    pub view: u64,
+    pub seq_num: u64,
+    /// Number of votes from distinct members (other than the seal's signer) required
+    pub required_votes: u64,
+    /// Number of distinct members with a valid vote in the seal
+    pub voter_count: usize,
+    /// Results for each of the seal's votes, in order
+    pub votes: Vec<VoteVerdict>,
+    /// Every problem found with the seal
+    pub errors: Vec<String>,
+}
+
+/// Check every aspect of a seal for the given block against the given member list and report on
+/// all problems found, rather than stopping at the first one
+///
+/// The seal's votes must be signed the way a Sawtooth validator signs peer messages, with the
+/// signature scheme of the given verifier; use `check_seal_with` for seals from other hosts.
+pub fn check_seal(
+    seal: &PbftSeal,
+    block_id: &[u8],
+    previous_id: Option<&[u8]>,
+    members: &[PeerId],
+    verifier: &dyn VoteVerifier,
+) -> SealVerdict {
+    check_seal_with(seal, block_id, previous_id, members, &|votes| {
+        SawtoothHost::authenticate_votes_with(votes, verifier)
+    })
+}
+
+/// Check a seal like `check_seal` does, checking the signatures of its votes with the given
+/// function
+pub fn check_seal_with(
+    seal: &PbftSeal,
+    block_id: &[u8],
+    previous_id: Option<&[u8]>,
+    members: &[PeerId],
+    authenticate: &AuthenticateVotes,
+) -> SealVerdict {
+    let f = max_faulty_nodes(members.len());
+    let mut errors = Vec::new();
+
+    if f == 0 {
+        errors.push(format!(
+            "{} members are not enough for the network to be fault tolerant",
+            members.len()
+        ));
+    }
+
+    if seal.get_block_id() != block_id {
+        errors.push(format!(
+            "Seal is for block {}, not block {}",
+            hex::encode(seal.get_block_id()),
+            hex::encode(block_id)
+        ));
+    }
+
+    // Check everything that doesn't need a signature first, then check the signatures of the
+    // votes that passed; a list with more votes than members isn't checked any further
+    let commit_votes = match check_vote_count(seal.get_commit_votes(), members) {
+        Ok(()) => seal.get_commit_votes(),
+        Err(err) => {
+            errors.push(err.to_string());
+            &[]
+        }
+    };
+    let parsed = commit_votes
+        .iter()
+        .map(|vote| parse_vote_message(vote, PbftMessageType::Commit))
+        .collect::<Vec<_>>();
+    let to_authenticate = commit_votes
+        .iter()
+        .zip(&parsed)
+        .filter(|(_, parsed)| parsed.is_ok())
+        .map(|(vote, _)| vote.clone())
+        .collect::<Vec<_>>();
+    let mut authenticated = match authenticate_each(&to_authenticate, authenticate) {
+        Ok(authenticated) => authenticated,
+        Err(err) => {
+            errors.push(err.to_string());
+            Vec::new()
+        }
+    }
+    .into_iter();
+
+    let mut voter_ids = HashSet::new();
+    let votes = commit_votes
+        .iter()
+        .zip(parsed)
+        .map(|(vote, parsed)| {
+            let signer_id = protobuf::parse_from_bytes::<PbftMessage>(vote.get_message_bytes())
+                .ok()
+                .map(|msg| hex::encode(msg.get_info().get_signer_id()));
+            let checked = parsed.and_then(|pbft_message| {
+                let authenticated = authenticated
+                    .next()
+                    .ok_or_else(|| PbftError::InternalError("Vote was not authenticated".into()))?;
+                check_authenticated_vote(&pbft_message, authenticated, &|msg: &PbftMessage| {
+                    check_seal_vote(seal, msg)
+                })
+            });
+            let error = match checked {
+                Ok(id) => {
+                    voter_ids.insert(id);
+                    None
+                }
+                Err(err) => Some(err.to_string()),
+            };
+            VoteVerdict { signer_id, error }
+        })
+        .collect::<Vec<_>>();
+
+    let invalid_votes = votes.iter().filter(|vote| vote.error.is_some()).count();
+    if invalid_votes > 0 {
+        errors.push(format!("Seal contains {} invalid vote(s)", invalid_votes));
+    }
+
+    if let Err(err) = verify_seal_voters(seal, &voter_ids, members, f) {
+        errors.push(err.to_string());
+    }
+
+    SealVerdict {
+        valid: errors.is_empty(),
+        block_id: hex::encode(block_id),
+        previous_id: previous_id.map(hex::encode),
+        seal_block_id: hex::encode(seal.get_block_id()),
+        seal_signer_id: hex::encode(seal.get_info().get_signer_id()),
+        view: seal.get_info().get_view(),
+        seq_num: seal.get_info().get_seq_num(),
+        required_votes: 2 * f,
+        voter_count: voter_ids.len(),
+        votes,
+        errors,
+    }
+}
+
+/// Parse a serialized `PbftSeal` and check it with `check_seal`
+pub fn check_serialized_seal(
+    seal_bytes: &[u8],
+    block_id: &[u8],
+    previous_id: Option<&[u8]>,
+    members: &[PeerId],
+    verifier: &dyn VoteVerifier,
+) -> Result<SealVerdict, PbftError> {
+    check_serialized_seal_with(seal_bytes, block_id, previous_id, members, &|votes| {
+        SawtoothHost::authenticate_votes_with(votes, verifier)
+    })
+}
+
+/// Parse a serialized `PbftSeal` and check it with `check_seal_with`
+pub fn check_serialized_seal_with(
+    seal_bytes: &[u8],
+    block_id: &[u8],
+    previous_id: Option<&[u8]>,
+    members: &[PeerId],
+    authenticate: &AuthenticateVotes,
+) -> Result<SealVerdict, PbftError> {
+    let seal: PbftSeal = protobuf::parse_from_bytes(seal_bytes)
+        .map_err(|err| PbftError::SerializationError("Error parsing seal".into(), err))?;
+
+    Ok(check_seal_with(
+        &seal,
+        block_id,
+        previous_id,
+        members,
+        authenticate,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::hash_sha512;
+    use crate::signature::{Ed25519Verifier, Secp256k1Verifier};
+    use crate::testkit::*;
+    use openssl::pkey::PKey;
+    use openssl::sign::Signer;
+    use protobuf::{Message, RepeatedField};
+    use sawtooth_sdk::messages::consensus::ConsensusPeerMessageHeader;
+    use sawtooth_sdk::signing::create_context;
+    use sawtooth_sdk::signing::secp256k1::Secp256k1PrivateKey;
+    use std::cell::RefCell;
+
+    /// In order to verify that a consensus seal or a `NewView` is correct, nodes must be able to
+    /// verify each of the signed votes that are contained in the seal/`NewView`. `PbftSignedVote`s
+    /// are verified by the `verify_vote` method, which takes as arguments the vote
+    /// itself, the expected vote type, and a closure that evaluates some arbitrary criteria. The
+    /// `verify_vote` method should make sure the vote’s type matches the expected type, the header
+    /// is properly signed, the header’s signer matches the message’s signer, the message hash is
+    /// correct, and that it meets the criteria specified in the closure.
+    ///
+    /// This test verifies that the `verify_vote` method works correctly by testing all cases where
+    /// it should fail and a case where it should succeed.
+    #[test]
+    fn test_vote_verification() {
+        // Generate a public/private key pair
+        let key_pair = mock_signer_network(1).remove(0);
+
+        // Create a validly-signed Commit vote
+        let valid_vote = mock_vote(PbftMessageType::Commit, 0, 1, vec![1], &key_pair);
+
+        // Test verification of a valid vote
+        assert_eq!(
+            key_pair.pub_key,
+            verify_vote(
+                &valid_vote,
+                PbftMessageType::Commit,
+                &Secp256k1Verifier,
+                |_| Ok(())
+            )
+            .expect("Valid vote was determined to be invalid")
+        );
+
+        // Test verification of a vote with invalid type
+        assert!(verify_vote(
+            &valid_vote,
+            PbftMessageType::ViewChange,
+            &Secp256k1Verifier,
+            |_| Ok(())
+        )
+        .is_err());
+
+        // Test verification of a vote that doesn't meet the validation_criteria
+        assert!(verify_vote(
+            &valid_vote,
+            PbftMessageType::Commit,
+            &Secp256k1Verifier,
+            |_| Err(PbftError::InvalidMessage("".into()))
+        )
+        .is_err());
+
+        // Test verification of a vote with an invalid header signature
+        let mut invalid_header_sig = valid_vote.clone();
+        invalid_header_sig.set_header_signature(vec![0]);
+        assert!(verify_vote(
+            &invalid_header_sig,
+            PbftMessageType::ViewChange,
+            &Secp256k1Verifier,
+            |_| Ok(())
+        )
+        .is_err());
+
+        // Test verification of a vote with an invalid message hash
+        let mut invalid_msg_hash = valid_vote.clone();
+        invalid_msg_hash.set_message_bytes(vec![0]);
+        assert!(verify_vote(
+            &invalid_msg_hash,
+            PbftMessageType::Commit,
+            &Secp256k1Verifier,
+            |_| Ok(())
+        )
+        .is_err());
+
+        // Test verification of a vote where the header's signer doesn't match the message's
+        // signers (the vote signer didn't create the message)
+        let bad_key_pair = mock_signer_network(1).remove(0);
+
+        let other_nodes_message = mock_msg(
+            PbftMessageType::Commit,
+            0,
+            1,
+            key_pair.pub_key.clone(),
+            vec![1],
+            false,
+        );
+
+        let mut header = ConsensusPeerMessageHeader::new();
+        header.set_signer_id(bad_key_pair.pub_key.clone());
+        header.set_content_sha512(hash_sha512(&other_nodes_message.message_bytes));
+        let header_bytes = header
+            .write_to_bytes()
+            .expect("Failed to write header to bytes");
+        let header_signature = hex::decode(
+            create_context("secp256k1")
+                .expect("Failed to create context")
+                .sign(
+                    &header_bytes,
+                    &Secp256k1PrivateKey::from_hex(&hex::encode(bad_key_pair.priv_key.clone()))
+                        .expect("Failed to create private key from hex"),
+                )
+                .expect("Failed to sign header"),
+        )
+        .expect("Failed to decode signed header");
+
+        let mut mismatched_signer = PbftSignedVote::new();
+        mismatched_signer.set_header_bytes(header_bytes);
+        mismatched_signer.set_header_signature(header_signature);
+        mismatched_signer.set_message_bytes(other_nodes_message.message_bytes.clone());
+
+        assert!(verify_vote(
+            &mismatched_signer,
+            PbftMessageType::Commit,
+            &Secp256k1Verifier,
+            |_| Ok(())
+        )
+        .is_err());
+    }
+
+    /// Validators that sign with Ed25519 keys sign the headers of votes the same way, but with a
+    /// different signature scheme. A vote signed with Ed25519 must be accepted when it is checked
+    /// with the Ed25519 verifier and rejected by the default secp256k1 verification, and an
+    /// Ed25519 vote whose header was tampered with must be rejected.
+    #[test]
+    fn test_ed25519_vote_verification() {
+        let key = PKey::generate_ed25519().expect("Failed to generate key");
+        let signer_id = key.raw_public_key().expect("Failed to get public key");
+
+        let message = mock_msg(
+            PbftMessageType::Commit,
+            0,
+            1,
+            signer_id.clone(),
+            vec![1],
+            false,
+        );
+        let mut header = ConsensusPeerMessageHeader::new();
+        header.set_signer_id(signer_id.clone());
+        header.set_content_sha512(hash_sha512(&message.message_bytes));
+        let header_bytes = header
+            .write_to_bytes()
+            .expect("Failed to write header to bytes");
+        let header_signature = Signer::new_without_digest(&key)
+            .and_then(|mut signer| signer.sign_oneshot_to_vec(&header_bytes))
+            .expect("Failed to sign header");
+
+        let mut vote = PbftSignedVote::new();
+        vote.set_header_bytes(header_bytes);
+        vote.set_header_signature(header_signature);
+        vote.set_message_bytes(message.message_bytes.clone());
+
+        let ed25519 =
+            |vote: &PbftSignedVote| SawtoothHost::authenticate_vote_with(vote, &Ed25519Verifier);
+        assert_eq!(
+            signer_id,
+            verify_vote_with(&vote, PbftMessageType::Commit, &ed25519, |_| Ok(()))
+                .expect("Valid Ed25519 vote was determined to be invalid")
+        );
+        assert!(
+            verify_vote(&vote, PbftMessageType::Commit, &Secp256k1Verifier, |_| Ok(
+                ()
+            ))
+            .is_err()
+        );
+
+        let mut tampered = vote.clone();
+        let mut header_bytes = tampered.get_header_bytes().to_vec();
+        let last = header_bytes.len() - 1;
+        header_bytes[last] ^= 1;
+        tampered.set_header_bytes(header_bytes);
+        assert!(
+            verify_vote_with(&tampered, PbftMessageType::Commit, &ed25519, |_| Ok(())).is_err()
+        );
+    }
+
+    /// Auditors need to verify seals without a validator and see exactly what is wrong with a bad
+    /// seal. The `check_seal` function verifies the same conditions as `verify_seal` (every vote is
+    /// a valid Commit for the seal's block, view, and sequence number; the signer is a member; the
+    /// voters are at least 2f distinct members other than the signer), and also that the seal is
+    /// for the expected block. Instead of stopping at the first problem, it reports on every vote
+    /// and every problem found.
+    ///
+    /// This test verifies that a valid seal is accepted both by `check_seal` and `verify_seal`,
+    /// and that several kinds of invalid seals are rejected by both with the problems reported.
+    #[test]
+    fn test_seal_checking() {
+        let key_pairs = mock_signer_network(4);
+        let members = key_pairs
+            .iter()
+            .map(|key_pair| key_pair.pub_key.clone())
+            .collect::<Vec<_>>();
+        let votes = |signers: &[usize], block_id: u8| {
+            signers
+                .iter()
+                .map(|i| {
+                    mock_vote(
+                        PbftMessageType::Commit,
+                        0,
+                        1,
+                        vec![block_id],
+                        &key_pairs[*i],
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Verify a valid seal from its serialized form
+        let valid = mock_seal(0, 1, vec![1], &key_pairs[0], votes(&[1, 2], 1));
+        let verdict = check_serialized_seal(
+            &valid.write_to_bytes().unwrap(),
+            &[1],
+            Some(&[0][..]),
+            &members,
+        )
+        .expect("Failed to parse seal");
+        assert!(verdict.valid, "{:?}", verdict);
+        assert_eq!(2, verdict.required_votes);
+        assert_eq!(2, verdict.voter_count);
+        assert_eq!(Some("00".into()), verdict.previous_id);
+        assert_eq!(2, verdict.votes.len());
+        assert_eq!(
+            Some(hex::encode(&key_pairs[1].pub_key)),
+            verdict.votes[0].signer_id
+        );
+        assert!(verify_seal(&valid, &members, 1, &Secp256k1Verifier).is_ok());
+
+        // A valid seal for a different block doesn't prove the expected block
+        let verdict = check_seal(&valid, &[2], None, &members, &Secp256k1Verifier);
+        assert!(!verdict.valid);
+        assert_eq!(1, verdict.errors.len());
+
+        // A seal with too few votes
+        let too_few = mock_seal(0, 1, vec![1], &key_pairs[0], votes(&[1], 1));
+        assert!(!check_seal(&too_few, &[1], None, &members, &Secp256k1Verifier).valid);
+        assert!(verify_seal(&too_few, &members, 1, &Secp256k1Verifier).is_err());
+
+        // A seal with a vote for another block; the bad vote is identified
+        let mut mixed = mock_seal(0, 1, vec![1], &key_pairs[0], votes(&[1, 2, 3], 1));
+        let mut mixed_votes = mixed.get_commit_votes().to_vec();
+        mixed_votes[1] = votes(&[2], 2).remove(0);
+        mixed.set_commit_votes(RepeatedField::from(mixed_votes));
+        let verdict = check_seal(&mixed, &[1], None, &members, &Secp256k1Verifier);
+        assert!(!verdict.valid);
+        assert!(verdict.votes[0].error.is_none());
+        assert!(verdict.votes[1].error.is_some());
+        assert!(verdict.votes[2].error.is_none());
+        assert!(verify_seal(&mixed, &members, 1, &Secp256k1Verifier).is_err());
+
+        // A seal including the signer's own vote
+        let self_vote = mock_seal(0, 1, vec![1], &key_pairs[0], votes(&[0, 1], 1));
+        assert!(!check_seal(&self_vote, &[1], None, &members, &Secp256k1Verifier).valid);
+        assert!(verify_seal(&self_vote, &members, 1, &Secp256k1Verifier).is_err());
+
+        // A seal verified against a member list that doesn't include one of its voters
+        let other_members = vec![
+            members[0].clone(),
+            members[1].clone(),
+            members[3].clone(),
+            mock_signer_network(1).remove(0).pub_key,
+        ];
+        assert!(!check_seal(&valid, &[1], None, &other_members, &Secp256k1Verifier).valid);
+        assert!(verify_seal(&valid, &other_members, 1, &Secp256k1Verifier).is_err());
+
+        // A member list too small to be fault tolerant can't verify any seal
+        assert!(!check_seal(&valid, &[1], None, &members[..3], &Secp256k1Verifier).valid);
+
+        // Bytes that aren't a seal can't be checked
+        assert!(check_serialized_seal(&[0xff, 0xff], &[1], None, &members).is_err());
+    }
+
+    /// The signatures of a seal's or NewView's votes are checked all at once (in parallel, for
+    /// votes signed for a Sawtooth validator), but the outcome must be the same as checking the
+    /// votes one at a time: the problem reported is the one with the first invalid vote, and
+    /// several votes from the same signer only count once.
+    ///
+    /// This test verifies that parallel authentication gives the same results as serial
+    /// authentication, that `verify_votes_with` reports the first invalid vote's problem, that
+    /// duplicate votes don't count toward a seal's quorum, and that an authentication function
+    /// that doesn't return a result for every vote is caught.
+    #[test]
+    fn test_batch_vote_verification() {
+        let key_pairs = mock_signer_network(7);
+        let members = key_pairs
+            .iter()
+            .map(|key_pair| key_pair.pub_key.clone())
+            .collect::<Vec<_>>();
+        let vote = |i: usize, block_id: u8| {
+            mock_vote(PbftMessageType::Commit, 0, 1, vec![block_id], &key_pairs[i])
+        };
+
+        let mut bad_signature = vote(3, 1);
+        bad_signature.set_header_signature(vec![0]);
+        let votes = vec![vote(1, 1), vote(2, 2), bad_signature, vote(4, 1)];
+
+        // Parallel and serial authentication agree, and authentication stops at the bad signature
+        let parallel = SawtoothHost::authenticate_votes_with(&votes, &Secp256k1Verifier);
+        assert_eq!(3, parallel.len());
+        for (vote, result) in votes.iter().zip(parallel) {
+            let serial = SawtoothHost::authenticate_vote_with(vote, &Secp256k1Verifier);
+            assert_eq!(serial.ok(), result.ok());
+        }
+
+        // The second vote is for the wrong block and the third has a bad signature; the second
+        // vote's problem is reported
+        let criteria = |msg: &PbftMessage| {
+            if msg.get_block_id() == [1u8].as_ref() {
+                Ok(())
+            } else {
+                Err(PbftError::InvalidMessage("Wrong block".into()))
+            }
+        };
+        let authenticate = |votes: &[PbftSignedVote]| {
+            SawtoothHost::authenticate_votes_with(votes, &Secp256k1Verifier)
+        };
+        match verify_votes_with(&votes, PbftMessageType::Commit, &authenticate, criteria) {
+            Err(PbftError::InvalidMessage(msg)) => assert_eq!("Wrong block", msg),
+            res => panic!("Expected the second vote to be reported, got {:?}", res),
+        }
+        match verify_votes_with(
+            &votes[2..],
+            PbftMessageType::Commit,
+            &authenticate,
+            criteria,
+        ) {
+            Err(PbftError::SigningError(_)) => {}
+            res => panic!("Expected the bad signature to be reported, got {:?}", res),
+        }
+
+        // Duplicate votes count once: 2f = 4 votes are needed, but there are only 3 signers
+        let duplicated = vec![vote(1, 1), vote(2, 1), vote(3, 1), vote(3, 1)];
+        assert_eq!(
+            3,
+            verify_votes_with(
+                &duplicated,
+                PbftMessageType::Commit,
+                &authenticate,
+                criteria
+            )
+            .expect("Duplicate votes are valid votes")
+            .len()
+        );
+        let seal = mock_seal(0, 1, vec![1], &key_pairs[0], duplicated);
+        assert!(verify_seal(&seal, &members, 2, &Secp256k1Verifier).is_err());
+        let seal = mock_seal(
+            0,
+            1,
+            vec![1],
+            &key_pairs[0],
+            vec![vote(1, 1), vote(2, 1), vote(3, 1), vote(4, 1)],
+        );
+        assert_eq!(
+            4,
+            verify_seal(&seal, &members, 2, &Secp256k1Verifier)
+                .unwrap()
+                .len()
+        );
+
+        // Authentication must produce a result for every vote
+        assert!(verify_seal_with(&seal, &members, 2, &|_| Vec::new()).is_err());
+        assert!(!check_seal_with(&seal, &[1], None, &members, &|_| Vec::new()).valid);
+
+        // A seal with more votes than there are members is rejected without checking any
+        // signatures
+        let seal = mock_seal(0, 1, vec![1], &key_pairs[0], vec![vote(1, 1); 8]);
+        let no_authentication = |_: &[PbftSignedVote]| -> Vec<Result<PeerId, PbftError>> {
+            panic!("Votes were authenticated")
+        };
+        assert!(verify_seal_with(&seal, &members, 2, &no_authentication).is_err());
+        assert!(!check_seal_with(&seal, &[1], None, &members, &no_authentication).valid);
+
+        // Votes that fail the checks that don't need a signature are rejected before any
+        // signatures after them are checked
+        let mut wrong_type = vote(2, 1);
+        wrong_type.set_message_bytes(
+            mock_msg(
+                PbftMessageType::Prepare,
+                0,
+                1,
+                members[2].clone(),
+                vec![1],
+                false,
+            )
+            .message_bytes,
+        );
+        let votes = vec![vote(1, 1), wrong_type, vote(3, 1)];
+        let authenticated = RefCell::new(0);
+        let counting_authentication = |votes: &[PbftSignedVote]| {
+            *authenticated.borrow_mut() += votes.len();
+            SawtoothHost::authenticate_votes_with(votes, &Secp256k1Verifier)
+        };
+        match verify_votes_with(
+            &votes,
+            PbftMessageType::Commit,
+            &counting_authentication,
+            criteria,
+        ) {
+            Err(PbftError::InvalidMessage(_)) => {}
+            res => panic!("Expected the wrong type to be reported, got {:?}", res),
+        }
+        assert_eq!(1, *authenticated.borrow());
+    }
+}